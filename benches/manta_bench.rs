@@ -32,7 +32,6 @@ use manta_asset::*;
 use manta_crypto::*;
 use pallet_manta_pay::*;
 use rand_chacha::ChaCha20Rng;
-use std::{fs::File, io::prelude::*};
 
 criterion_group!(
 	manta_bench,
@@ -40,6 +39,7 @@ criterion_group!(
 	bench_pedersen_hash,
 	bench_pedersen_com,
 	bench_merkle_tree,
+	bench_ledger_update,
 	bench_transfer_verify,
 	bench_transfer_prove,
 );
@@ -74,13 +74,9 @@ fn bench_transfer_verify(c: &mut Criterion) {
 	let mut rng = ChaCha20Rng::from_seed(hash_param_seed);
 	let hash_param = Hash::setup(&mut rng).unwrap();
 
-	let mut file = File::open("transfer_pk.bin").unwrap();
-	let mut transfer_key_bytes: Vec<u8> = vec![];
-	file.read_to_end(&mut transfer_key_bytes).unwrap();
-	let buf: &[u8] = transfer_key_bytes.as_ref();
-	let pk = Groth16Pk::deserialize_unchecked(buf).unwrap();
+	let pk = default_transfer_zkp_key();
 
-	println!("proving key loaded from disk");
+	println!("proving key generated");
 
 	// sender
 	let mut sk = [0u8; 32];
@@ -109,6 +105,10 @@ fn bench_transfer_verify(c: &mut Criterion) {
 		commit_param: commit_param.clone(),
 		hash_param: hash_param.clone(),
 
+		asset_id: TEST_ASSET,
+		fee: 0,
+		binding: [0u8; 32],
+
 		sender_1: sender_1.clone(),
 		sender_2: sender_2.clone(),
 
@@ -137,19 +137,111 @@ fn bench_transfer_verify(c: &mut Criterion) {
 		sender_2,
 		receiver_1,
 		receiver_2,
+		0,
+		[0u8; 32],
 		&mut rng,
-	);
+	)
+	.unwrap();
 	let transfer_data = PrivateTransferData::deserialize(transfer_data.as_ref());
 
 	println!("start benchmarking proof verification");
 	let mut bench_group = c.benchmark_group("private transfer");
 
-	let bench_str = format!("ZKP verification");
+	// verify against the key the proof was generated with
+	let mut vk_bytes = Vec::new();
+	pk.vk.serialize_uncompressed(&mut vk_bytes).unwrap();
+
+	// preparing the key on every verification,
+	// as before the prepared key was stored in the ledger
+	let transfer_data_clone = transfer_data.clone();
+	let vk_bytes_clone = vk_bytes.clone();
+	let bench_str = format!("ZKP verification with raw key");
+	bench_group.bench_function(bench_str, move |b| {
+		b.iter(|| {
+			let pvk = prepare_verification_key(&vk_bytes_clone).unwrap();
+			assert!(transfer_data_clone.verify(&pvk))
+		})
+	});
+
+	let pvk = prepare_verification_key(&vk_bytes).unwrap();
+	let transfer_data_clone = transfer_data.clone();
+	let pvk_clone = pvk.clone();
+	let bench_str = format!("ZKP verification with prepared key");
 	bench_group.bench_function(bench_str, move |b| {
-		b.iter(|| assert!(transfer_data.verify(&TRANSFER_PK)))
+		b.iter(|| assert!(transfer_data_clone.verify(&pvk_clone)))
 	});
 
 	bench_group.finish();
+
+	// the batches are made of distinct transfers, as they are on chain
+	println!("creating the proofs of the batches");
+	let batch_sizes = [1usize, 10, 100];
+	let transfers: Vec<PrivateTransferData> = (0..batch_sizes[batch_sizes.len() - 1])
+		.map(|_| sample_private_transfer(&commit_param, &hash_param, &pk, &mut rng))
+		.collect();
+
+	let mut bench_group = c.benchmark_group("private transfer batch");
+	for batch_size in batch_sizes.iter() {
+		let batch = transfers[..*batch_size].to_vec();
+
+		let batch_clone = batch.clone();
+		let pvk_clone = pvk.clone();
+		let bench_str = format!("{} ZKP verifications one by one", batch_size);
+		bench_group.bench_function(bench_str, move |b| {
+			b.iter(|| {
+				for data in batch_clone.iter() {
+					assert!(data.verify(&pvk_clone))
+				}
+			})
+		});
+
+		let pvk_clone = pvk.clone();
+		let bench_str = format!("{} ZKP verifications in a batch", batch_size);
+		bench_group.bench_function(bench_str, move |b| {
+			b.iter(|| assert!(batch_verify(&pvk_clone, &batch).is_ok()))
+		});
+	}
+	bench_group.finish();
+}
+
+// sample a private transfer of two fresh coins, proven with `pk`
+fn sample_private_transfer(
+	commit_param: &CommitmentParam,
+	hash_param: &HashParam,
+	pk: &Groth16Pk,
+	rng: &mut ChaCha20Rng,
+) -> PrivateTransferData {
+	let mut sk = [0u8; 32];
+	rng.fill_bytes(&mut sk);
+	let sender_1 = MantaAsset::sample(commit_param, &sk, &TEST_ASSET, &100, rng);
+	rng.fill_bytes(&mut sk);
+	let sender_2 = MantaAsset::sample(commit_param, &sk, &TEST_ASSET, &300, rng);
+
+	let list = [sender_1.commitment, sender_2.commitment];
+	let sender_1 = SenderMetaData::build(hash_param.clone(), sender_1, &list);
+	let sender_2 = SenderMetaData::build(hash_param.clone(), sender_2, &list);
+
+	rng.fill_bytes(&mut sk);
+	let receiver_1_full = MantaAssetFullReceiver::sample(commit_param, &sk, &TEST_ASSET, &(), rng);
+	let receiver_1 = receiver_1_full.prepared.process(&150, rng);
+	rng.fill_bytes(&mut sk);
+	let receiver_2_full = MantaAssetFullReceiver::sample(commit_param, &sk, &TEST_ASSET, &(), rng);
+	let receiver_2 = receiver_2_full.prepared.process(&250, rng);
+
+	let payload = generate_private_transfer_payload(
+		commit_param.clone(),
+		hash_param.clone(),
+		pk,
+		sender_1,
+		sender_2,
+		receiver_1,
+		receiver_2,
+		0,
+		[0u8; 32],
+		rng,
+	)
+	.unwrap();
+	PrivateTransferData::deserialize(payload.as_ref())
 }
 
 fn bench_merkle_tree(c: &mut Criterion) {
@@ -211,6 +303,38 @@ fn bench_merkle_tree(c: &mut Criterion) {
 	bench_group.finish();
 }
 
+fn bench_ledger_update(c: &mut Criterion) {
+	let hash_param = HashParam::deserialize(HASH_PARAM.data);
+	let mut rng = ChaCha20Rng::from_seed([3u8; 32]);
+
+	// the cost of appending a commitment to a shard grows with the depth of
+	// its tree, i.e., it depends on how many commitments each shard holds
+	let total_leaves = 1 << 12;
+	let mut bench_group = c.benchmark_group("ledger update");
+	for shard_count in [16usize, 256, 1024].iter() {
+		let leaves: Vec<[u8; 32]> = (0..total_leaves / shard_count)
+			.map(|_| {
+				let mut cm = [0u8; 32];
+				rng.fill_bytes(&mut cm);
+				cm
+			})
+			.collect();
+		let frontier = MerkleFrontier::from_leaves(&hash_param, &leaves);
+		let mut cm = [0u8; 32];
+		rng.fill_bytes(&mut cm);
+
+		let hash_param_clone = hash_param.clone();
+		let bench_str = format!("with {} shards", shard_count);
+		bench_group.bench_function(bench_str, move |b| {
+			b.iter(|| {
+				let mut frontier = frontier.clone();
+				frontier.append(&hash_param_clone, &cm)
+			})
+		});
+	}
+	bench_group.finish();
+}
+
 fn bench_pedersen_com(c: &mut Criterion) {
 	let commit_param_seed = COMMIT_PARAM_SEED;
 	let mut rng = ChaCha20Rng::from_seed(commit_param_seed);
@@ -252,13 +376,9 @@ fn bench_transfer_prove(c: &mut Criterion) {
 	let mut rng = ChaCha20Rng::from_seed(hash_param_seed);
 	let hash_param = Hash::setup(&mut rng).unwrap();
 
-	let mut file = File::open("transfer_pk.bin").unwrap();
-	let mut transfer_key_bytes: Vec<u8> = vec![];
-	file.read_to_end(&mut transfer_key_bytes).unwrap();
-	let buf: &[u8] = transfer_key_bytes.as_ref();
-	let pk = Groth16Pk::deserialize_unchecked(buf).unwrap();
+	let pk = default_transfer_zkp_key();
 
-	println!("proving key loaded from disk");
+	println!("proving key generated");
 
 	// sender
 	let mut sk = [0u8; 32];
@@ -287,6 +407,10 @@ fn bench_transfer_prove(c: &mut Criterion) {
 		commit_param: commit_param.clone(),
 		hash_param,
 
+		asset_id: TEST_ASSET,
+		fee: 0,
+		binding: [0u8; 32],
+
 		sender_1: sender_1.clone(),
 		sender_2: sender_2.clone(),
 
@@ -16,26 +16,320 @@
 
 //! This module implements the ledger for manta's private asset.
 //! TODO: Shall we factor out this module?
-//! The private asset ledger consist of a fixed number of __256__ merkle trees.
-//! Each tree is a `Shard`, and collectively they form the `Shards`.
-//! When an UTXO is posted to the ledger, it will be send to the corresponding
-//! shard via some deterministic fashion.
+//! The private asset ledger consist of `T::ShardCount` merkle trees.
+//! Each tree is a shard: its commitments are stored in `ShardLeaves`
+//! and its root and frontier in `ShardMetas`.
+//! When an UTXO is posted to the ledger, it will be send to the shard
+//! `shard_index_of(cm)`, which is derived from a hash of the commitment.
 
+use crate::{
+	CoinCommitments, Config, EncryptedNote, LedgerRoots, Module, ShardLeaves, ShardMetas,
+	ShardRootHistory,
+};
+use ark_crypto_primitives::{merkle_tree::Config as MerkleTreeConfig, FixedLengthCRH};
+use ark_ff::ToBytes;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
 use ark_std::vec::Vec;
-use frame_support::codec::{Decode, Encode};
+use frame_support::{
+	codec::{Decode, Encode},
+	traits::Get,
+};
 use manta_crypto::*;
+use sp_runtime::traits::{BlakeTwo256, Hash as HashT};
+use sp_std::collections::btree_map::BTreeMap;
 
-/// A shard is a list of commitment, and a merkle root of this list.
+/// The domain separator for hashing a commitment into a shard index.
+const SHARD_INDEX_DOMAIN: &[u8] = b"manta-pay/shard-index";
+
+/// The height of the ledger merkle tree, i.e., of `LedgerMerkleTree`.
+const TREE_HEIGHT: usize = <MerkleTreeParams as MerkleTreeConfig>::HEIGHT as usize;
+
+/// The number of siblings on the path from a leaf to the root of the ledger
+/// merkle tree, i.e., the number of inner hashes to compute the root.
+pub(crate) const MERKLE_PATH_LENGTH: usize = TREE_HEIGHT - 2;
+
+/// The meta data of a shard: the merkle root of its commitments,
+/// and the frontier of its merkle tree.
+/// The commitments themselves are stored separately, indexed by
+/// `(shard_index, leaf_index)`, so that appending to a shard only
+/// touches this shard.
 #[derive(Encode, Debug, Decode, Clone, Default, PartialEq)]
-pub struct Shard {
-	pub list: Vec<[u8; 32]>,
+pub struct ShardMeta {
 	pub root: [u8; 32],
+	pub frontier: MerkleFrontier,
 }
 
-/// A Shards is a list of Shard-s.
+/// The progress of a re-sharding of the ledger, see `migration::start_reshard`.
 #[derive(Encode, Debug, Decode, Clone, PartialEq)]
-pub struct Shards {
-	pub shard: [Shard; 256],
+pub enum ReshardProgress {
+	/// Moving the leaves of the `shard_count` old shards into `ReshardQueue`,
+	/// in order: the next one is the leaf `leaf_index` of the shard
+	/// `shard_index`, and `queued` leaves are queued so far.
+	/// The shard indices of the old shards are `u8`s when `legacy` is set.
+	Collect {
+		shard_count: u32,
+		legacy: bool,
+		shard_index: u32,
+		leaf_index: u64,
+		queued: u64,
+	},
+	/// Re-inserting the queued leaves into the new shards:
+	/// the next one is the leaf `index` out of `queued`.
+	Replay { index: u64, queued: u64 },
+}
+
+/// The encrypted note of a coin, together with the position of the coin
+/// in the ledger, i.e., all a wallet needs to recover and spend the coin.
+#[derive(Encode, Debug, Decode, Clone, Default, PartialEq)]
+pub struct NoteRecord {
+	pub cm: [u8; 32],
+	pub shard_index: u32,
+	pub leaf_index: u64,
+	pub encrypted_note: EncryptedNote,
+}
+
+/// The left frontier of an append-only merkle tree.
+/// `left[i]` is the latest node at level `i` that is a left child;
+/// together with `leaf_count` this is all we need to compute the new root
+/// when a leaf is appended.
+///
+/// The roots are identical to the ones of `LedgerMerkleTree`:
+///  * a tree of `n` leaves has `next_power_of_two(n)` leaves, where the
+///    missing leaves are the hash of an empty leaf;
+///  * the tree is then padded up to `TREE_HEIGHT` by hashing the root
+///    with the hash of an empty leaf.
+#[derive(Encode, Debug, Decode, Clone, Default, PartialEq)]
+pub struct MerkleFrontier {
+	pub leaf_count: u64,
+	pub left: Vec<[u8; 32]>,
+}
+
+impl MerkleFrontier {
+	/// Build the frontier of an existing list of leaves.
+	/// This costs `O(n * depth)` hashes and is meant for migrating
+	/// shards that were populated before the frontier was introduced.
+	pub fn from_leaves(param: &HashParam, leaves: &[[u8; 32]]) -> Self {
+		let mut frontier = Self::default();
+		for leaf in leaves.iter() {
+			frontier.append(param, leaf);
+		}
+		frontier
+	}
+
+	/// Append a leaf to the tree, and return the new root.
+	/// This costs `O(depth)` hashes.
+	pub fn append(&mut self, param: &HashParam, leaf: &[u8; 32]) -> [u8; 32] {
+		self.append_with_path(param, leaf).0
+	}
+
+	/// Append a leaf to the tree, and return the new root together with the
+	/// path from the leaf to the root: the pair `(node, sibling)` of every
+	/// level, where `node` is the root of the sub-tree of the new leaf.
+	pub(crate) fn append_with_path(
+		&mut self,
+		param: &HashParam,
+		leaf: &[u8; 32],
+	) -> ([u8; 32], Vec<(HashOutput, HashOutput)>) {
+		let index = self.leaf_count;
+		self.leaf_count += 1;
+
+		// the depth of the smallest complete tree that holds all the leaves
+		let depth = tree_depth(self.leaf_count);
+
+		// `empty` is the root of an empty sub-tree at the current level
+		let empty_leaf = hash_leaf(param, &[0u8; 32]);
+		let mut empty = empty_leaf;
+		let mut node = hash_leaf(param, leaf);
+		let mut path = Vec::with_capacity(MERKLE_PATH_LENGTH);
+		for level in 0..depth {
+			if (index >> level) & 1 == 0 {
+				self.set_left(level, &node);
+				path.push((node, empty));
+				node = hash_inner_node(param, &node, &empty);
+			} else {
+				let left = HashOutput::deserialize(self.left[level].as_ref()).unwrap();
+				path.push((node, left));
+				node = hash_inner_node(param, &left, &node);
+			}
+			empty = hash_inner_node(param, &empty, &empty);
+		}
+		// the root of the complete tree is always a left child
+		self.set_left(depth, &node);
+
+		// pad the tree up to `TREE_HEIGHT`
+		for _ in depth..MERKLE_PATH_LENGTH {
+			path.push((node, empty_leaf));
+			node = hash_inner_node(param, &node, &empty_leaf);
+		}
+
+		(node_to_bytes(&node), path)
+	}
+
+	// store `node` as the left node of `level`
+	fn set_left(&mut self, level: usize, node: &HashOutput) {
+		let bytes = node_to_bytes(node);
+		if level == self.left.len() {
+			self.left.push(bytes);
+		} else {
+			self.left[level] = bytes;
+		}
+	}
+}
+
+/// The errors of the merkle witnesses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WitnessError {
+	/// The leaf is not tracked, or not in the tree at all.
+	UnknownLeaf,
+	/// The witness is the witness of another leaf.
+	LeafMismatch,
+}
+
+/// The authentication path of a leaf of an append-only merkle tree:
+/// `siblings[i]` is the sibling of the ancestor of the leaf at level `i`,
+/// the side of the sibling is given by the bit `i` of `leaf_index`.
+///
+/// The path is kept up to date with `WitnessTracker`, so that the root it
+/// opens to is always the latest root of the tree.
+#[derive(Encode, Debug, Decode, Clone, Default, PartialEq)]
+pub struct MerkleWitness {
+	pub leaf_index: u64,
+	pub leaf: [u8; 32],
+	pub siblings: Vec<[u8; 32]>,
+}
+
+impl MerkleWitness {
+	// the witness of the leaf that was just appended along `path`
+	fn new(leaf_index: u64, leaf: &[u8; 32], path: &[(HashOutput, HashOutput)]) -> Self {
+		Self {
+			leaf_index,
+			leaf: *leaf,
+			siblings: path
+				.iter()
+				.map(|(_, sibling)| node_to_bytes(sibling))
+				.collect(),
+		}
+	}
+
+	// update the witness after the leaf `leaf_index` has been appended along
+	// `path`; only the sibling at the level where the two leaves meet changes
+	fn update(&mut self, leaf_index: u64, path: &[(HashOutput, HashOutput)]) {
+		let level = 63 - (self.leaf_index ^ leaf_index).leading_zeros() as usize;
+		self.siblings[level] = node_to_bytes(&path[level].0);
+	}
+
+	/// The sibling at `level`, or the default node if the witness is too
+	/// short, e.g., the default witness that is used to generate the keys.
+	pub(crate) fn sibling(&self, level: usize) -> HashOutput {
+		self.siblings
+			.get(level)
+			.and_then(|sibling| HashOutput::deserialize(sibling.as_ref()).ok())
+			.unwrap_or_default()
+	}
+
+	/// The root this witness opens to.
+	/// This costs `O(depth)` hashes.
+	pub fn root(&self, param: &HashParam) -> HashOutput {
+		let mut node = hash_leaf(param, &self.leaf);
+		for level in 0..MERKLE_PATH_LENGTH {
+			let sibling = self.sibling(level);
+			node = if (self.leaf_index >> level) & 1 == 0 {
+				hash_inner_node(param, &node, &sibling)
+			} else {
+				hash_inner_node(param, &sibling, &node)
+			};
+		}
+		node
+	}
+}
+
+/// An append-only merkle tree that only keeps its frontier,
+/// and the witnesses of the leaves that are tracked,
+/// e.g., the commitments of the coins of a wallet.
+/// Appending a leaf costs `O(depth)` hashes for the frontier,
+/// and `O(1)` for every tracked witness.
+#[derive(Encode, Debug, Decode, Clone, Default, PartialEq)]
+pub struct WitnessTracker {
+	pub frontier: MerkleFrontier,
+	pub root: [u8; 32],
+	witnesses: BTreeMap<[u8; 32], MerkleWitness>,
+}
+
+impl WitnessTracker {
+	/// Build the tracker of an existing list of leaves, tracking the leaves
+	/// for which `track(leaf_index, leaf)` is true.
+	/// This costs `O(n * depth)` hashes.
+	pub fn from_leaves<F: Fn(u64, &[u8; 32]) -> bool>(
+		param: &HashParam,
+		leaves: &[[u8; 32]],
+		track: F,
+	) -> Self {
+		let mut tracker = Self::default();
+		for (leaf_index, leaf) in leaves.iter().enumerate() {
+			tracker.append(param, leaf, track(leaf_index as u64, leaf));
+		}
+		tracker
+	}
+
+	/// Append a leaf to the tree, update the tracked witnesses,
+	/// and return the new root.
+	pub fn append(&mut self, param: &HashParam, leaf: &[u8; 32], track: bool) -> [u8; 32] {
+		let leaf_index = self.frontier.leaf_count;
+		let (root, path) = self.frontier.append_with_path(param, leaf);
+		for witness in self.witnesses.values_mut() {
+			witness.update(leaf_index, &path);
+		}
+		if track {
+			self.witnesses
+				.insert(*leaf, MerkleWitness::new(leaf_index, leaf, &path));
+		}
+		self.root = root;
+		root
+	}
+
+	/// Get the witness of the tracked leaf `leaf`.
+	pub fn witness(&self, leaf: &[u8; 32]) -> Result<&MerkleWitness, WitnessError> {
+		self.witnesses.get(leaf).ok_or(WitnessError::UnknownLeaf)
+	}
+
+	/// Stop tracking `leaf`, e.g., once the coin is spent.
+	pub fn untrack(&mut self, leaf: &[u8; 32]) -> Option<MerkleWitness> {
+		self.witnesses.remove(leaf)
+	}
+
+	/// The number of leaves of the tree.
+	pub fn leaf_count(&self) -> u64 {
+		self.frontier.leaf_count
+	}
+}
+
+// the depth of the smallest complete binary tree with `leaf_count` leaves
+fn tree_depth(leaf_count: u64) -> usize {
+	leaf_count.next_power_of_two().trailing_zeros() as usize
+}
+
+// serialize a node of the tree
+fn node_to_bytes(node: &HashOutput) -> [u8; 32] {
+	let mut bytes = [0u8; 32];
+	node.serialize(bytes.as_mut()).unwrap();
+	bytes
+}
+
+// hash a leaf the same way `LedgerMerkleTree` does,
+// i.e., the leaf is padded with 0s to the input size of the hash
+fn hash_leaf(param: &HashParam, leaf: &[u8; 32]) -> HashOutput {
+	let mut buffer = [0u8; 128];
+	buffer[..32].copy_from_slice(leaf.as_ref());
+	Hash::evaluate(param, &buffer[..(Hash::INPUT_SIZE_BITS / 8)]).unwrap()
+}
+
+// hash two nodes the same way `LedgerMerkleTree` does
+fn hash_inner_node(param: &HashParam, left: &HashOutput, right: &HashOutput) -> HashOutput {
+	let mut buffer = [0u8; 128];
+	let mut writer = buffer.as_mut();
+	left.write(&mut writer).unwrap();
+	right.write(&mut writer).unwrap();
+	Hash::evaluate(param, &buffer[..(Hash::INPUT_SIZE_BITS / 8)]).unwrap()
 }
 
 pub trait LedgerSharding {
@@ -44,324 +338,82 @@ pub trait LedgerSharding {
 	type Param;
 
 	/// root exists in the current shards
-	fn check_root(&self, target: &Self::Root) -> bool;
+	fn check_root(target: &Self::Root) -> bool;
 
 	/// the commitment exists in the current shards
-	fn exist(&self, target: &Self::Commitment) -> bool;
+	fn exist(target: &Self::Commitment) -> bool;
 
-	/// update the shards with a new commitment
-	fn update(&mut self, target: &Self::Commitment, param: Self::Param);
+	/// update the shards with a new commitment,
+	/// and return its position `(shard_index, leaf_index)` in the shards
+	fn update(target: &Self::Commitment, param: &Self::Param) -> (u32, u64);
 }
 
-impl LedgerSharding for Shards {
+impl<T: Config> LedgerSharding for Module<T> {
 	type Commitment = [u8; 32];
 	type Root = [u8; 32];
 	type Param = HashParam;
 
-	// root exists in the current shards
-	fn check_root(&self, target: &Self::Root) -> bool {
-		for shard in self.shard.iter() {
-			if shard.root == *target {
-				return true;
-			}
-		}
-		false
+	// root is one of the recent roots of the shards
+	fn check_root(target: &Self::Root) -> bool {
+		LedgerRoots::contains_key(target)
 	}
 
-	fn exist(&self, target: &Self::Commitment) -> bool {
-		// FIXME: at the moment, the index of the shard is determined by the first
-		// byte of the cm. this may be potentially risky, since the commitment
-		// is a group element, and the first byte may not be uniformly distributed
-		// between 0 and 255.
-		let shard_index = target[0] as usize;
-
-		for e in self.shard[shard_index].list.iter() {
-			if e == target {
-				return true;
-			}
-		}
-		false
+	fn exist(target: &Self::Commitment) -> bool {
+		CoinCommitments::contains_key(target)
 	}
 
 	// this function updates the ledger shards,
 	// this function does not check if target already exists in the list or not
-	fn update(&mut self, target: &Self::Commitment, param: Self::Param) {
-		// FIXME: at the moment, the index of the shard is determined by the first
-		// byte of the cm. this may be potentially risky, since the commitment
-		// is a group element, and the first byte may not be uniformly distributed
-		// between 0 and 255.
-		let shard_index = target[0] as usize;
-
-		// update the list, and the root accordingly
-		self.shard[shard_index].list.push(*target);
-		self.shard[shard_index].root =
-			<MantaCrypto as MerkleTree>::root(param, &self.shard[shard_index].list);
+	fn update(target: &Self::Commitment, param: &Self::Param) -> (u32, u64) {
+		let shard_index = Self::shard_index_of(target);
+
+		// update the leaves, and the root accordingly
+		let mut meta = ShardMetas::get(shard_index);
+		let leaf_index = meta.frontier.leaf_count;
+		let root = meta.frontier.append(param, target);
+		meta.root = root;
+
+		// push the new root into the history of the shard,
+		// and evict the root that falls out of the window
+		let slot = root_history_slot::<T>(leaf_index);
+		if let Some(evicted) = ShardRootHistory::get(shard_index, slot) {
+			LedgerRoots::remove(&evicted);
+		}
+		ShardRootHistory::insert(shard_index, slot, root);
+		LedgerRoots::insert(&root, ());
+
+		ShardLeaves::insert(shard_index, leaf_index, target);
+		CoinCommitments::insert(target, ());
+		ShardMetas::insert(shard_index, meta);
+
+		(shard_index, leaf_index)
 	}
 }
 
-impl Default for Shards {
-	fn default() -> Self {
-		// is there a non-std macro for this?
-		// this code is really stupid LOL
-		let tmp = [
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-			Shard::default(),
-		];
-
-		Self { shard: tmp }
+/// The slot of `ShardRootHistory` that holds the root obtained by
+/// appending the leaf `leaf_index` to a shard.
+pub(crate) fn root_history_slot<T: Config>(leaf_index: u64) -> u32 {
+	(leaf_index % T::RootHistorySize::get().max(1) as u64) as u32
+}
+
+impl<T: Config> Module<T> {
+	/// Get the index of the shard that holds the commitment `cm`.
+	///
+	/// The first byte of a commitment is not uniformly distributed, since
+	/// the commitment is a serialized group element; we reduce a domain
+	/// separated blake2 hash of the commitment modulo `T::ShardCount` instead.
+	pub fn shard_index_of(cm: &[u8; 32]) -> u32 {
+		let digest = BlakeTwo256::hash(&[SHARD_INDEX_DOMAIN, cm.as_ref()].concat());
+		let mut bytes = [0u8; 8];
+		bytes.copy_from_slice(&digest.as_ref()[..8]);
+		(u64::from_le_bytes(bytes) % T::ShardCount::get().max(1) as u64) as u32
+	}
+
+	/// Get the list of commitments of the shard `shard_index`, in order.
+	pub fn shard_leaves(shard_index: u32) -> Vec<[u8; 32]> {
+		let leaf_count = ShardMetas::get(shard_index).frontier.leaf_count;
+		(0..leaf_count)
+			.map(|leaf_index| ShardLeaves::get(shard_index, leaf_index))
+			.collect()
 	}
 }
@@ -56,6 +56,9 @@
 //!
 //! ### Dispatchable Functions
 //!
+//! * `initialize_ledger` - Sets the parameters and the verification keys of the private ledger.
+//! Can only be called once, by the root origin.
+//! * `set_verification_key` - Sets the verification key of a circuit. Can only be called by the root origin.
 //! * `init_asset` - Issues the total supply of a new fungible asset to the account of the caller of the function.
 //! * `transfer_asset` - Transfers an `amount` of units of fungible asset `id` from the balance of
 //! the function caller's account (`origin`) to a `target` account.
@@ -63,9 +66,15 @@
 //! (The caller does not need to be the owner of this UTXO)
 //! * `private_transfer` - Transfer two input UTXOs into two output UTXOs. Require that 1) the input UTXOs are
 //! already in the ledger and are not spend before 2) the sum of private assets in input UTXOs matches that
-//! of the output UTXOs. The requirements are guaranteed via ZK proof.
+//! of the output UTXOs plus the public fee. The requirements are guaranteed via ZK proof. The fee is paid out
+//! of the private pool to the `FeeCollector` account.
+//! * `private_transfer_unsigned` - The same as `private_transfer`, without a signature; the transfer is checked
+//! in the transaction pool, see `ValidateUnsigned`, and the fee goes to the `FeeCollector` account.
+//! * `private_transfer_batch` - Apply several private transfers at once, verifying their proofs together.
+//! * `private_transfer_n` - Transfer any number of input UTXOs into any number of output UTXOs, with the
+//! verification key registered for this shape of transfer.
 //! * `reclaim` - Transfer two input UTXOs into one output UTXOs, and convert the remaining assets to the
-//! public assets. Require that 1) the input UTXOs are already in the ledger and are not spend before; 2) the
+//! public assets of the recipient bound to the proof. Require that 1) the input UTXOs are already in the ledger and are not spend before; 2) the
 //! sum of private assets in input UTXOs matches that of the output UTXO + the reclaimed amount. The
 //! requirements are guaranteed via ZK proof.
 //!
@@ -78,9 +87,29 @@
 //! * `balance` - Get the asset balance of `who`.
 //! * `total_supply` - Get the total supply of an asset `id`.
 //! * `pool_balance` - Get the total number of private asset.
+//! * `is_void_number_spent` - Check if a void number has been revealed.
+//! * `shard_index_of` - Get the index of the shard that holds a commitment.
+//! * `active_verification_key` - Get the verification key in use for a circuit.
+//! * `active_prepared_verification_key` - Get the prepared verification key in use for a circuit.
+//! * `chain_id` - Get the identifier of the chain the payloads are bound to.
+//! * `encrypted_note` - Get the encrypted note of a coin, which its receiver decrypts with `try_decrypt_note`.
+//! * `notes` - Get a page of the notes, in the order the coins have been received. It backs the
+//! `MantaPayApi` runtime API, with which the light wallets sync the notes incrementally.
 //!
 //! Please refer to the [`Module`](./struct.Module.html) struct for details on publicly available functions.
 //!
+//! With the `std` feature, the `wallet` module finds the coins of a user in the ledger, from the notes,
+//! the leaves of the shards and the void numbers, and the `planner` module splits a payment out of
+//! these coins into the `private_transfer` and `reclaim` payloads of the 2-in/2-out circuits.
+//!
+//! ### Genesis Configuration
+//!
+//! * `assets` - The assets issued at genesis, as `(asset_id, owner, total_supply)`.
+//! * `minted_coins` - The coins minted at genesis, as `(asset_id, amount, commitment)`.
+//! Like the minted coins, they are listed in the notes, with an empty encrypted note.
+//! * `hash_param`, `commit_param` - The serialized ledger parameters, default to `HASH_PARAM`
+//! and `COMMIT_PARAM`. The ledger only mints with the parameters it is built with.
+//!
 //! ## Usage
 //!
 //! The following example shows how to use the Assets module in your runtime by exposing public functions to:
@@ -103,8 +132,14 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 
 mod ledger;
+mod migration;
 mod payload;
+#[cfg(feature = "std")]
+pub mod planner;
+pub mod runtime_api;
 mod runtime_benchmark;
+#[cfg(feature = "std")]
+pub mod wallet;
 mod zkp;
 
 #[cfg(test)]
@@ -113,7 +148,10 @@ mod test;
 #[macro_use]
 extern crate std;
 
-pub use ledger::{Shard, Shards};
+pub use ledger::{
+	MerkleFrontier, MerkleWitness, NoteRecord, ReshardProgress, ShardMeta, WitnessError,
+	WitnessTracker,
+};
 pub use manta_crypto::MantaSerDes;
 pub use payload::*;
 pub use zkp::*;
@@ -121,20 +159,64 @@ pub mod weights;
 pub use weights::WeightInfo;
 
 use ark_std::vec::Vec;
-use frame_support::{decl_error, decl_event, decl_module, decl_storage, ensure};
-use frame_system::ensure_signed;
+use frame_support::{
+	codec::{Decode, Encode},
+	decl_error, decl_event, decl_module, decl_storage,
+	dispatch::DispatchResult,
+	ensure,
+	traits::Get,
+	weights::Weight,
+};
+use frame_system::{ensure_none, ensure_root, ensure_signed};
 use ledger::LedgerSharding;
 use manta_asset::SanityCheck;
 use manta_crypto::*;
-use sp_runtime::traits::{StaticLookup, Zero};
-use sp_std::prelude::*;
+use sp_runtime::{
+	traits::{StaticLookup, Zero},
+	transaction_validity::{
+		InvalidTransaction, TransactionPriority, TransactionSource, TransactionValidity,
+		ValidTransaction,
+	},
+};
+use sp_std::{
+	collections::{btree_map::BTreeMap, btree_set::BTreeSet},
+	prelude::*,
+};
 
 /// An abstract struct for manta-pay.
 pub struct MantaPay;
 
 pub const MINT_PAYLOAD_SIZE: usize = 112;
-pub const PRIVATE_TRANSFER_PAYLOAD_SIZE: usize = 608;
-pub const RECLAIM_PAYLOAD_SIZE: usize = 512;
+pub const PRIVATE_TRANSFER_PAYLOAD_SIZE: usize = 816;
+pub const RECLAIM_PAYLOAD_SIZE: usize = 656;
+
+/// The maximum number of senders, and of receivers, of a `private_transfer_n`.
+pub const MAX_TRANSFER_ARITY: u8 = 8;
+
+/// The maximum number of notes returned by `notes`.
+pub const MAX_NOTES_PAGE_SIZE: u32 = 1024;
+
+// A value placed in storage that represents the current version of the storage.
+// This value is used by the `on_runtime_upgrade` logic to determine whether we run
+// storage migration logic.
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq, Debug)]
+enum Releases {
+	V1_0_0,
+	V2_0_0,
+	V3_0_0,
+	V4_0_0,
+	V5_0_0,
+	V6_0_0,
+	V7_0_0,
+	V8_0_0,
+	V9_0_0,
+}
+
+impl Default for Releases {
+	fn default() -> Self {
+		Releases::V1_0_0
+	}
+}
 
 /// The module configuration trait.
 pub trait Config: frame_system::Config {
@@ -143,36 +225,67 @@ pub trait Config: frame_system::Config {
 
 	/// Weight information for extrinsics in this pallet.
 	type WeightInfo: WeightInfo;
+
+	/// The number of recent roots of each shard that are accepted
+	/// as the ledger state of a sender.
+	/// Changing this value requires to migrate `ShardRootHistory`.
+	type RootHistorySize: Get<u32>;
+
+	/// The number of shards of the ledger.
+	/// The ledger is re-sharded over the blocks following a runtime upgrade
+	/// that changes this value.
+	type ShardCount: Get<u32>;
+
+	/// The maximum number of transfers in a `private_transfer_batch`.
+	type MaxBatchSize: Get<u32>;
+
+	/// The account credited with the fees of the private transfers.
+	type FeeCollector: Get<Self::AccountId>;
 }
 
 decl_module! {
 	pub struct Module<T: Config> for enum Call where origin: T::Origin {
 		type Error = Error<T>;
 
+		/// The number of recent roots of each shard that are accepted
+		/// as the ledger state of a sender.
+		const RootHistorySize: u32 = T::RootHistorySize::get();
+
+		/// The number of shards of the ledger.
+		const ShardCount: u32 = T::ShardCount::get();
+
+		/// The maximum number of transfers in a `private_transfer_batch`.
+		const MaxBatchSize: u32 = T::MaxBatchSize::get();
+
 		fn deposit_event() = default;
-		/// Issue a new class of fungible assets. There are, and will only ever be, `total`
-		/// such assets and they'll all belong to the `origin` initially. It will have an
-		/// identifier `AssetId` instance: this will be specified in the `Issued` event.
+
+		fn on_runtime_upgrade() -> Weight {
+			migration::migrate::<T>()
+		}
+
+		fn on_initialize(_n: T::BlockNumber) -> Weight {
+			migration::continue_reshard::<T>()
+		}
+
+		/// Initialize the private ledger: set the ledger parameters and the
+		/// checksums of the ZKP verification keys.
+		/// This can only be done once, either here or in the genesis config.
 		/// # <weight>
 		/// - `O(1)`
-		/// - 1 storage mutation (codec `O(1)`).
-		/// - 2 storage writes (codec `O(1)`).
+		/// - 1 storage read (codec `O(1)`).
+		/// - 10 storage writes (codec `O(1)`).
+		/// - 2 verification key preparations.
 		/// - 1 event.
 		/// # </weight>
-		#[weight = T::WeightInfo::mint_private_asset()]
-		fn init_asset(origin,
-			asset_id: u64,
-			total: u64
-		) {
+		#[weight = T::WeightInfo::initialize_ledger()]
+		fn initialize_ledger(origin) {
+			ensure_root(origin)?;
 
-			// if the asset_id has a total suply != 0, then this asset is initialized
 			ensure!(
-				!TotalSupply::contains_key(&asset_id),
-				<Error<T>>::AlreadyInitialized
+				!HashParamChecksum::exists(),
+				<Error<T>>::LedgerAlreadyInitialized
 			);
 
-			let origin = ensure_signed(origin)?;
-
 			// for now we hard code the parameters generated from the following seed:
 			//  * hash parameter seed: [1u8; 32]
 			//  * commitment parameter seed: [2u8; 32]
@@ -192,18 +305,73 @@ decl_module! {
 			//
 			// for prototype, we use this function to generate the ZKP verification key
 			// for product we should use a MPC protocol to build the ZKP verification key
-			// and then deploy that vk
+			// and then deploy that vk via `set_verification_key`
 			//
-			let transfer_key_digest = TRANSFER_PK.get_checksum();
-			TransferZKPKeyChecksum::put(transfer_key_digest);
+			Self::put_default_verification_keys();
 
-			let reclaim_key_digest = RECLAIM_PK.get_checksum();
-			ReclaimZKPKeyChecksum::put(reclaim_key_digest);
+			StorageVersion::put(Releases::V9_0_0);
+			LedgerShardCount::put(T::ShardCount::get());
 
-			// coin_shards are 256 lists of commitments
-			let coin_shards = Shards::default();
-			CoinShards::put(coin_shards);
+			Self::deposit_event(RawEvent::LedgerInitialized);
+		}
 
+		/// Set the verification key of the circuit `kind`, and use it from now on
+		/// to verify the proofs of this circuit.
+		/// The `version` of the key must be greater than the one of the key in use.
+		/// # <weight>
+		/// - `O(1)`
+		/// - 1 storage read (codec `O(1)`).
+		/// - 3 storage writes (codec `O(key.len())`).
+		/// - 1 verification key preparation.
+		/// - 1 event.
+		/// # </weight>
+		#[weight = T::WeightInfo::set_verification_key()]
+		fn set_verification_key(origin,
+			kind: CircuitKind,
+			version: u32,
+			key: Vec<u8>
+		) {
+			ensure_root(origin)?;
+			ensure!(kind.is_supported(), <Error<T>>::UnsupportedCircuit);
+
+			if let Some(active_version) = ActiveVerificationKeyVersion::get(kind) {
+				ensure!(
+					version > active_version,
+					<Error<T>>::StaleVerificationKey
+				);
+			}
+			let prepared_key = prepare_verification_key(&key)
+				.ok_or(<Error<T>>::InvalidVerificationKey)?;
+
+			Self::put_verification_key(kind, version, key, prepared_key);
+
+			Self::deposit_event(RawEvent::VerificationKeySet(kind, version));
+		}
+
+		/// Issue a new class of fungible assets. There are, and will only ever be, `total`
+		/// such assets and they'll all belong to the `origin` initially. It will have an
+		/// identifier `AssetId` instance: this will be specified in the `Issued` event.
+		/// Issuing an asset does not touch the private ledger,
+		/// which is shared by all the assets.
+		/// # <weight>
+		/// - `O(1)`
+		/// - 1 storage read (codec `O(1)`).
+		/// - 3 storage writes (codec `O(1)`).
+		/// - 1 event.
+		/// # </weight>
+		#[weight = T::WeightInfo::init_asset()]
+		fn init_asset(origin,
+			asset_id: u64,
+			total: u64
+		) {
+
+			// if the asset_id has a total suply != 0, then this asset is initialized
+			ensure!(
+				!TotalSupply::contains_key(&asset_id),
+				<Error<T>>::AlreadyInitialized
+			);
+
+			let origin = ensure_signed(origin)?;
 
 			// initialize the asset with `total` number of supplies
 			// the total number of private asset (pool balance) remain 0
@@ -212,9 +380,6 @@ decl_module! {
 			TotalSupply::insert(asset_id, total);
 			<Balances<T>>::insert(&origin, asset_id, total);
 
-			VNList::put(Vec::<[u8; 32]>::new());
-			EncValueList::put(Vec::<[u8; 16]>::new());
-
 			Self::deposit_event(RawEvent::Issued(asset_id, origin, total));
 		}
 
@@ -262,7 +427,7 @@ decl_module! {
 			// todo: Implement the fix denomination method
 
 			// parse the input_data into input
-			let input = MintData::deserialize(payload.as_ref());
+			let input = MintData::try_deserialize(payload.as_ref()).map_err(<Error<T>>::from)?;
 
 			// if the asset_id has a total suply == 0, then this asset is initialized
 			ensure!(
@@ -303,20 +468,20 @@ decl_module! {
 			);
 
 			// check cm is not in the ledger
-			let mut coin_shards = CoinShards::get();
+			ensure!(!Self::is_resharding(), <Error<T>>::LedgerResharding);
 			ensure!(
-				!coin_shards.exist(&input.cm),
+				!Self::exist(&input.cm),
 				Error::<T>::MantaCoinExist
 			);
 
-			// update the shards
-			coin_shards.update(&input.cm, hash_param);
+			// update the shards; the minter knows the opening of the coin,
+			// so its note is empty
+			Self::insert_coin(&input.cm, EncryptedNote::default(), &hash_param);
 
 			// write back to ledger storage
 			Self::deposit_event(
 				RawEvent::Minted(input.asset_id, origin, input.amount)
 			);
-			CoinShards::put(coin_shards);
 
 			let old_pool_balance = PoolBalance::get(input.asset_id);
 			PoolBalance::mutate(
@@ -334,16 +499,67 @@ decl_module! {
 		/// Manta's private transfer function that moves values from two
 		/// sender's private tokens into two receiver tokens. A proof is required to
 		/// make sure that this transaction is valid.
-		/// Neither the values nor the identities is leaked during this process.
+		/// Neither the values nor the identities is leaked during this process;
+		/// except for the fee, and the asset id when a fee is paid.
+		/// The fee is taken out of the private pool of the asset and credited to the
+		/// `FeeCollector` account, so that the caller, e.g., a relayer, does not
+		/// need to own any of the transferred asset.
 		#[weight = T::WeightInfo::private_transfer()]
 		fn private_transfer(origin,
 			payload: [u8; PRIVATE_TRANSFER_PAYLOAD_SIZE],
 		) {
-			// this function does not know which asset_id is been transferred.
-			// so there will not be an initialization check
+			let data = PrivateTransferData::try_deserialize(payload.as_ref())
+				.map_err(<Error<T>>::from)?;
+			let origin = ensure_signed(origin)?;
+
+			Self::do_private_transfer(&data)?;
+			Self::deposit_event(RawEvent::PrivateTransferred(origin));
+		}
+
+		/// The same as `private_transfer`, without a signature, so that no public
+		/// account is tied to the transfer.
+		/// The payload, i.e., its void numbers, its roots and its proof,
+		/// is checked in the transaction pool by `validate_unsigned`,
+		/// and again on dispatch.
+		#[weight = T::WeightInfo::private_transfer()]
+		fn private_transfer_unsigned(origin,
+			payload: [u8; PRIVATE_TRANSFER_PAYLOAD_SIZE],
+		) {
+			ensure_none(origin)?;
+			let data = PrivateTransferData::try_deserialize(payload.as_ref())
+				.map_err(<Error<T>>::from)?;
+
+			Self::do_private_transfer(&data)?;
+			Self::deposit_event(RawEvent::UnsignedPrivateTransferred);
+		}
 
-			let data = PrivateTransferData::deserialize(payload.as_ref());
+		/// Manta's batched private transfer function that applies a
+		/// `private_transfer` for each of the `payloads`, in order.
+		/// The proofs of the batch are verified together with a single
+		/// multi-pairing, see `batch_verify`, which is much cheaper than
+		/// verifying them one by one.
+		/// The batch is atomic: if any of the transfers is invalid, none is applied.
+		/// Batching is done within an extrinsic rather than in `on_finalize`,
+		/// since an invalid transfer cannot be reverted at the end of the block.
+		#[weight = T::WeightInfo::private_transfer_batch(
+			payloads.len().min(T::MaxBatchSize::get() as usize) as u32
+		)]
+		fn private_transfer_batch(origin,
+			payloads: Vec<[u8; PRIVATE_TRANSFER_PAYLOAD_SIZE]>,
+		) {
 			let origin = ensure_signed(origin)?;
+			ensure!(
+				payloads.len() <= T::MaxBatchSize::get() as usize,
+				<Error<T>>::BatchTooLarge
+			);
+
+			let mut data = Vec::with_capacity(payloads.len());
+			for payload in payloads.iter() {
+				data.push(
+					PrivateTransferData::try_deserialize(payload.as_ref())
+						.map_err(<Error<T>>::from)?
+				);
+			}
 
 			// get the parameter checksum from the ledger
 			// and make sure the parameters match
@@ -356,89 +572,85 @@ decl_module! {
 			);
 			let hash_param = HashParam::deserialize(HASH_PARAM.data);
 
-			// check if vn_old already spent
-			let mut sn_list = VNList::get();
-			ensure!(
-				!sn_list.contains(&data.sender_1.void_number),
-				<Error<T>>::MantaCoinSpent
-			);
-			sn_list.push(data.sender_1.void_number);
-			ensure!(
-				!sn_list.contains(&data.sender_2.void_number),
-				<Error<T>>::MantaCoinSpent
-			);
-			sn_list.push(data.sender_2.void_number);
+			// the transfers of the batch must not conflict with each other
+			Self::check_private_transfers(&data)?;
 
-			// get the ledger state from the ledger
-			// and check the validity of the state
-			let mut coin_shards = CoinShards::get();
-			ensure!(
-				coin_shards.check_root(&data.sender_1.root),
-				<Error<T>>::InvalidLedgerState
-			);
-			ensure!(
-				coin_shards.check_root(&data.sender_2.root),
-				<Error<T>>::InvalidLedgerState
-			);
+			// get the verification key from the ledger
+			let transfer_vk = Self::active_prepared_verification_key(CircuitKind::Transfer)
+				.ok_or(<Error<T>>::ZkpParamFail)?;
 
-			// check the commitment are not in the list already
-			// and update coin list
-			// with sharding, there is no point to batch update
-			// since the commitments are likely to go to different shards
-			ensure!(
-				!coin_shards.exist(&data.receiver_1.cm),
-				<Error<T>>::MantaCoinExist
-			);
-			coin_shards.update(&data.receiver_1.cm, hash_param.clone());
-			ensure!(
-				!coin_shards.exist(&data.receiver_2.cm),
-				<Error<T>>::MantaCoinExist
-			);
-			coin_shards.update(&data.receiver_2.cm, hash_param);
+			// check validity of all the zkps at once
+			batch_verify(&transfer_vk, &data).map_err(|(_, error)| <Error<T>>::from(error))?;
 
-			// get the verification key from the ledger
-			let transfer_vk_checksum = TransferZKPKeyChecksum::get();
-			let transfer_vk = TRANSFER_PK;
+			for transfer in data.iter() {
+				Self::apply_private_transfer(transfer, &hash_param);
+				Self::deposit_event(RawEvent::PrivateTransferred(origin.clone()));
+			}
+		}
 
+
+		/// Manta's private transfer function for any number of senders and receivers,
+		/// up to `MAX_TRANSFER_ARITY` of each: the same as `private_transfer`, except
+		/// that the proof is verified against the key of the `MultiTransferCircuit`
+		/// of this shape, `CircuitKind::TransferN(senders, receivers)`.
+		/// Wallets can spend a single coin, or merge several coins, without
+		/// creating dummy coins.
+		#[weight = private_transfer_n_weight::<T>(&payload)]
+		fn private_transfer_n(origin, payload: Vec<u8>) {
+			let data = PrivateTransferNData::try_deserialize(payload.as_slice())
+				.map_err(<Error<T>>::from)?;
+			// the weight is charged for the shape of the first two bytes
 			ensure!(
-				transfer_vk.get_checksum() == transfer_vk_checksum,
-				<Error<T>>::ZkpParamFail,
+				payload.len() == data.payload_size()
+					&& payload[..2] == [data.senders.len() as u8, data.receivers.len() as u8],
+				<Error<T>>::InvalidPayload
 			);
+			let origin = ensure_signed(origin)?;
+			ensure!(data.chain_id == Self::chain_id(), <Error<T>>::WrongChain);
 
-			// check validity of zkp
+			// get the parameter checksum from the ledger
+			// and make sure the parameters match
+			let hash_param_checksum_local = HASH_PARAM.get_checksum();
+
+			let hash_param_checksum = HashParamChecksum::get();
 			ensure!(
-				data.verify(&transfer_vk),
-				<Error<T>>::ZkpVerificationFail,
+				hash_param_checksum_local == hash_param_checksum,
+				<Error<T>>::MintFail
 			);
+			let hash_param = HashParam::deserialize(HASH_PARAM.data);
 
-			// TODO: revisit replay attack here
+			let senders: Vec<&SenderData> = data.senders.iter().collect();
+			let receivers: Vec<&ReceiverData> = data.receivers.iter().collect();
+			Self::check_spends(&senders, &receivers, &mut BTreeSet::new(), &mut BTreeSet::new())?;
+			Self::check_fees(&[(data.asset_id, data.fee)])?;
 
-			// update ledger storage
-			let mut enc_value_list = EncValueList::get();
-			enc_value_list.push(data.receiver_1.cipher);
-			enc_value_list.push(data.receiver_2.cipher);
+			// get the verification key of this shape from the ledger
+			let transfer_vk = Self::active_prepared_verification_key(data.circuit_kind())
+				.ok_or(<Error<T>>::ZkpParamFail)?;
+
+			// check validity of zkp
+			data.try_verify(&transfer_vk).map_err(<Error<T>>::from)?;
 
+			Self::apply_spends(&senders, &receivers, &hash_param);
+			Self::pay_fee(data.asset_id, data.fee);
 			Self::deposit_event(RawEvent::PrivateTransferred(origin));
-			CoinShards::put(coin_shards);
-			VNList::put(sn_list);
-			EncValueList::put(enc_value_list);
 		}
 
-
 		/// Manta's reclaim function that moves values from two
 		/// sender's private tokens into a receiver public account, and a private token.
 		/// A proof is required to
 		/// make sure that this transaction is valid.
 		/// Neither the values nor the identities is leaked during this process;
-		/// except for the reclaimed amount.
-		/// At the moment, the reclaimed amount goes directly to `origin` account.
-		/// __TODO__: shall we use a different receiver rather than `origin`?
+		/// except for the reclaimed amount and its recipient.
+		/// The reclaimed amount goes to the `recipient` of the payload, which is
+		/// bound to the proof, so that the payload cannot be resubmitted by
+		/// another account to its own benefit.
 		#[weight = T::WeightInfo::reclaim()]
 		fn reclaim(origin,
 			payload: [u8; RECLAIM_PAYLOAD_SIZE],
 		) {
 
-			let data = ReclaimData::deserialize(payload.as_ref());
+			let data = ReclaimData::try_deserialize(payload.as_ref()).map_err(<Error<T>>::from)?;
 
 			// if the asset_id has a total suply == 0, then this asset is initialized
 			ensure!(
@@ -446,9 +658,11 @@ decl_module! {
 				<Error<T>>::BasecoinNotInit
 			);
 
-			let origin = ensure_signed(origin)?;
-			let origin_account = origin.clone();
-			let origin_balance = <Balances<T>>::get(&origin, data.asset_id);
+			ensure_signed(origin)?;
+			ensure!(data.chain_id == Self::chain_id(), <Error<T>>::WrongChain);
+			let recipient = Self::recipient_account(&data.recipient)
+				.ok_or(<Error<T>>::InvalidRecipient)?;
+			let recipient_balance = <Balances<T>>::get(&recipient, data.asset_id);
 
 			// get the parameter checksum from the ledger
 			// and make sure the parameters match
@@ -467,71 +681,57 @@ decl_module! {
 			pool -= data.reclaim_amount;
 
 			// check if sn_old already spent
-			let mut sn_list = VNList::get();
 			ensure!(
-				!sn_list.contains(&data.sender_1.void_number),
+				!VoidNumbers::contains_key(&data.sender_1.void_number),
 				<Error<T>>::MantaCoinSpent
 			);
-			sn_list.push(data.sender_1.void_number);
 			ensure!(
-				!sn_list.contains(&data.sender_2.void_number),
+				!VoidNumbers::contains_key(&data.sender_2.void_number),
 				<Error<T>>::MantaCoinSpent
 			);
-			sn_list.push(data.sender_2.void_number);
-
-			// get the coin list
-			let mut coin_shards = CoinShards::get();
-
-			// get the verification key from the ledger
-			let reclaim_vk_checksum = ReclaimZKPKeyChecksum::get();
-			let reclaim_vk = RECLAIM_PK;
 			ensure!(
-				reclaim_vk.get_checksum() == reclaim_vk_checksum,
-				<Error<T>>::ZkpParamFail
+				data.sender_1.void_number != data.sender_2.void_number,
+				<Error<T>>::MantaCoinSpent
 			);
+
+			// get the verification key from the ledger
+			let reclaim_vk = Self::active_prepared_verification_key(CircuitKind::Reclaim)
+				.ok_or(<Error<T>>::ZkpParamFail)?;
 			// get the ledger state from the ledger
 			// and check the validity of the state
 			ensure!(
-				coin_shards.check_root(&data.sender_1.root),
+				Self::check_root(&data.sender_1.root),
 				<Error<T>>::InvalidLedgerState
 			);
 			ensure!(
-				coin_shards.check_root(&data.sender_2.root),
+				Self::check_root(&data.sender_2.root),
 				<Error<T>>::InvalidLedgerState
 			);
 			// check the commitment are not in the list already
+			ensure!(!Self::is_resharding(), <Error<T>>::LedgerResharding);
 			ensure!(
-				!coin_shards.exist(&data.receiver.cm),
+				!Self::exist(&data.receiver.cm),
 				<Error<T>>::MantaCoinSpent
 			);
 
 
-			// check validity of zkp
-			ensure!(
-				data.verify(&reclaim_vk),
-				<Error<T>>::ZkpVerificationFail,
-			);
-
-			// TODO: revisit replay attack here
+			// check validity of zkp; the proof is bound to the rest
+			// of the payload, so the payload cannot be tampered with
+			data.try_verify(&reclaim_vk).map_err(<Error<T>>::from)?;
 
 			// update ledger storage
-			let mut enc_value_list = EncValueList::get();
-			enc_value_list.push(data.receiver.cipher);
-
-
-			coin_shards.update(&data.receiver.cm, hash_param);
-			CoinShards::put(coin_shards);
+			Self::insert_note(&data.receiver, &hash_param);
 
 			Self::deposit_event(
-				RawEvent::PrivateReclaimed(data.asset_id, origin, data.reclaim_amount)
+				RawEvent::PrivateReclaimed(data.asset_id, recipient.clone(), data.reclaim_amount)
 			);
-			VNList::put(sn_list);
+			VoidNumbers::insert(&data.sender_1.void_number, ());
+			VoidNumbers::insert(&data.sender_2.void_number, ());
 			PoolBalance::mutate(data.asset_id, |balance| *balance = pool);
-			EncValueList::put(enc_value_list);
 			<Balances<T>>::mutate(
-				origin_account,
+				recipient,
 				data.asset_id,
-				|balance| *balance = origin_balance + data.reclaim_amount
+				|balance| *balance = recipient_balance + data.reclaim_amount
 			);
 		}
 	}
@@ -549,8 +749,19 @@ decl_event! {
 		Minted(u64, AccountId, u64),
 		/// Private transfer
 		PrivateTransferred(AccountId),
-		/// The assets was reclaimed
+		/// Private transfer without a signature
+		UnsignedPrivateTransferred,
+		/// The fee of a private transfer was paid. \[asset_id, fee_collector, fee\]
+		FeePaid(u64, AccountId, u64),
+		/// The assets was reclaimed. \[asset_id, recipient, amount\]
 		PrivateReclaimed(u64, AccountId, u64),
+		/// The private ledger was initialized
+		LedgerInitialized,
+		/// A verification key was set. \[circuit_kind, version\]
+		VerificationKeySet(CircuitKind, u32),
+		/// The ledger was re-sharded: the coins have new positions in the
+		/// shards, and the wallets have to rebuild their witnesses. \[shard_count\]
+		LedgerResharded(u32),
 	}
 }
 
@@ -577,14 +788,111 @@ decl_error! {
 		MantaCoinSpent,
 		/// ZKP parameter failed
 		ZkpParamFail,
-		/// ZKP verification failed
+		/// ZKP verification failed: the pairing check does not hold
 		ZkpVerificationFail,
+		/// The stored verification key is malformed or does not match the circuit
+		ZkpBadVerificationKey,
+		/// The proof cannot be deserialized
+		ZkpBadProofEncoding,
+		/// A public input of the proof cannot be converted into field elements
+		ZkpBadPublicInput,
+		/// The batch has more transfers than `MaxBatchSize`
+		BatchTooLarge,
+		/// The circuit is not supported by the ledger
+		UnsupportedCircuit,
 		/// invalid ledger state
 		InvalidLedgerState,
 		/// Pool overdrawn
 		PoolOverdrawn,
 		/// Invalid parameters
 		ParamFail,
+		/// The private ledger has already been initialized
+		LedgerAlreadyInitialized,
+		/// The verification key cannot be deserialized
+		InvalidVerificationKey,
+		/// The version of the verification key is not greater than the one in use
+		StaleVerificationKey,
+		/// The payload is malformed
+		InvalidPayload,
+		/// The recipient of the reclaim is not the padded encoding of an account
+		InvalidRecipient,
+		/// The payload is meant for another chain
+		WrongChain,
+		/// The proof in the payload is not a valid Groth16 proof
+		InvalidProofEncoding,
+		/// The payload contains an invalid curve point
+		InvalidCurvePoint,
+		/// The ledger is being re-sharded and does not accept new coins
+		LedgerResharding,
+	}
+}
+
+impl<T: Config> From<PayloadError> for Error<T> {
+	fn from(error: PayloadError) -> Self {
+		match error {
+			PayloadError::InvalidPayload => Error::<T>::InvalidPayload,
+			PayloadError::InvalidProofEncoding => Error::<T>::InvalidProofEncoding,
+			PayloadError::InvalidCurvePoint => Error::<T>::InvalidCurvePoint,
+		}
+	}
+}
+
+impl<T: Config> From<VerifyError> for Error<T> {
+	fn from(error: VerifyError) -> Self {
+		match error {
+			VerifyError::BadVerificationKey => Error::<T>::ZkpBadVerificationKey,
+			VerifyError::BadProofEncoding => Error::<T>::ZkpBadProofEncoding,
+			VerifyError::BadPublicInput => Error::<T>::ZkpBadPublicInput,
+			VerifyError::PairingCheckFailed => Error::<T>::ZkpVerificationFail,
+		}
+	}
+}
+
+/// The weight of `private_transfer_n`, for the shape `(senders, receivers)`
+/// decoded from the first two bytes of `payload`. The shape is bounded by
+/// `MAX_TRANSFER_ARITY`; a payload that is not of its shape is rejected on dispatch.
+fn private_transfer_n_weight<T: Config>(payload: &[u8]) -> Weight {
+	let (senders, receivers) = match payload {
+		[senders, receivers, ..] => (*senders, *receivers),
+		_ => (0, 0),
+	};
+	T::WeightInfo::private_transfer_n(
+		senders.min(MAX_TRANSFER_ARITY) as u32,
+		receivers.min(MAX_TRANSFER_ARITY) as u32,
+	)
+}
+
+impl<T: Config> frame_support::unsigned::ValidateUnsigned for Module<T> {
+	type Call = Call<T>;
+
+	/// Validate a `private_transfer_unsigned` in the transaction pool.
+	/// Since no account pays for an unsigned transfer, its proof is verified
+	/// before it enters the pool, so that a transfer with a bad proof cannot
+	/// be spammed for free; the proof of a transfer that is already in a block
+	/// is verified on dispatch. The void numbers are the tags provided by the
+	/// transfer, so that the pool keeps a single transfer spending a coin,
+	/// and the transfers paying a higher fee are preferred.
+	fn validate_unsigned(source: TransactionSource, call: &Self::Call) -> TransactionValidity {
+		let payload = match call {
+			Call::private_transfer_unsigned(payload) => payload,
+			_ => return InvalidTransaction::Call.into(),
+		};
+
+		let data = PrivateTransferData::try_deserialize(payload.as_ref())
+			.map_err(|_| InvalidTransaction::Call)?;
+		// the coins are spent, or the roots are too old
+		Self::check_private_transfers(sp_std::slice::from_ref(&data))
+			.map_err(|_| InvalidTransaction::Stale)?;
+		if source != TransactionSource::InBlock {
+			Self::verify_private_transfer(&data).map_err(|_| InvalidTransaction::BadProof)?;
+		}
+
+		ValidTransaction::with_tag_prefix("MantaPayTransfer")
+			.priority(data.fee as TransactionPriority)
+			.and_provides(data.sender_1.void_number)
+			.and_provides(data.sender_2.void_number)
+			.propagate(true)
+			.build()
 	}
 }
 
@@ -600,21 +908,64 @@ decl_storage! {
 		/// If 0, then this asset is not initialized.
 		pub TotalSupply: map hasher(blake2_128_concat) u64 => u64;
 
-		/// List of _void number_s.
+		/// Set of _void number_s.
 		/// A void number is also known as a `serial number` or `nullifier` in other protocols.
 		/// Each coin has a unique void number, and if this number is revealed,
 		/// the coin is voided.
-		/// The ledger maintains a list of all void numbers.
-		pub VNList get(fn vn_list): Vec<[u8; 32]>;
+		/// The ledger maintains a set of all void numbers.
+		pub VoidNumbers: map hasher(blake2_128_concat) [u8; 32] => ();
+
+		/// The number of shards the ledger is currently split into,
+		/// i.e., the value of `ShardCount` at the last re-sharding.
+		pub LedgerShardCount get(fn ledger_shard_count): u32;
+
+		/// The progress of the re-sharding of the ledger, if any.
+		pub ReshardState get(fn reshard_state): Option<ReshardProgress>;
 
-		/// List of Coins that has ever been created.
+		/// The leaves of the old shards, in order, while the ledger is being re-sharded.
+		pub ReshardQueue: map hasher(twox_64_concat) u64 => Option<[u8; 32]>;
+
+		/// The meta data of each shard of the ledger.
 		/// We employ a sharding system to host all the coins
 		/// for better concurrency.
-		pub CoinShards get(fn coin_shards): Shards;
+		pub ShardMetas get(fn shard_meta): map hasher(blake2_128_concat) u32 => ShardMeta;
+
+		/// List of Coins that has ever been created,
+		/// indexed by their shard and their position within the shard.
+		pub ShardLeaves get(fn shard_leaf):
+			double_map hasher(blake2_128_concat) u32, hasher(blake2_128_concat) u64 => [u8; 32];
+
+		/// Set of Coins that has ever been created.
+		pub CoinCommitments: map hasher(blake2_128_concat) [u8; 32] => ();
+
+		/// The recent roots of each shard, as a ring buffer of
+		/// `RootHistorySize` slots: the root obtained by appending the
+		/// `i`-th leaf to a shard is stored at slot `i % RootHistorySize`.
+		pub ShardRootHistory get(fn shard_root_history):
+			double_map hasher(blake2_128_concat) u32, hasher(blake2_128_concat) u32
+			=> Option<[u8; 32]>;
 
-		/// List of encrypted values.
+		/// Set of the recent roots of all the shards,
+		/// i.e., of all the roots in `ShardRootHistory`.
+		pub LedgerRoots: map hasher(blake2_128_concat) [u8; 32] => ();
+
+		/// List of encrypted values of the coins received before the notes
+		/// were encrypted, see `Notes`. It is no longer written.
 		pub EncValueList get(fn enc_value_list): Vec<[u8; 16]>;
 
+		/// The encrypted notes of the received coins, with the position of the
+		/// coins in the ledger, indexed by a global counter in the order the
+		/// coins have been received.
+		/// A receiver recovers the opening of its coins with `try_decrypt_note`.
+		pub Notes get(fn note): map hasher(twox_64_concat) u64 => Option<NoteRecord>;
+
+		/// The number of notes in `Notes`, i.e., the index of the next note.
+		pub NoteCount get(fn note_count): u64;
+
+		/// The index in `Notes` of the note of a commitment.
+		pub NoteIndices get(fn note_index):
+			map hasher(blake2_128_concat) [u8; 32] => Option<u64>;
+
 		/// The balance of all minted coins for this asset_id.
 		pub PoolBalance: map hasher(blake2_128_concat) u64 => u64;
 
@@ -624,15 +975,87 @@ decl_storage! {
 		/// The checksum of commitment parameter.
 		pub CommitParamChecksum get(fn commit_param_checksum): [u8; 32];
 
-		/// The verification key for zero-knowledge proof for transfer protocol.
-		/// At the moment we are storing the whole serialized key
-		/// in the blockchain storage.
-		pub TransferZKPKeyChecksum get(fn transfer_zkp_vk_checksum): [u8; 32];
-
-		/// The verification key for zero-knowledge proof for reclaim protocol.
-		/// At the moment we are storing the whole serialized key
-		/// in the blockchain storage.
-		pub ReclaimZKPKeyChecksum get(fn reclaim_zkp_vk_checksum): [u8; 32];
+		/// The serialized Groth16 verification keys of the circuits,
+		/// indexed by the kind of the circuit and the version of the key.
+		pub VerificationKeys get(fn verification_key):
+			double_map hasher(blake2_128_concat) CircuitKind, hasher(blake2_128_concat) u32
+			=> Option<Vec<u8>>;
+
+		/// The prepared form of `VerificationKeys`, see `prepare_verification_key`.
+		/// The pairing precomputation is done once when a key is set,
+		/// rather than on every verification.
+		pub PreparedVerificationKeys get(fn prepared_verification_key):
+			double_map hasher(blake2_128_concat) CircuitKind, hasher(blake2_128_concat) u32
+			=> Option<Vec<u8>>;
+
+		/// The version of the verification key in use for each circuit.
+		pub ActiveVerificationKeyVersion get(fn active_verification_key_version):
+			map hasher(blake2_128_concat) CircuitKind => Option<u32>;
+
+		/// Storage version of the pallet.
+		StorageVersion: Releases;
+	}
+	add_extra_genesis {
+		/// The assets issued at genesis, as `(asset_id, owner, total_supply)`.
+		config(assets): Vec<(u64, T::AccountId, u64)>;
+		/// The coins minted at genesis, as `(asset_id, amount, commitment)`.
+		/// The amount is taken from the balance of the owner of the asset;
+		/// the commitments are trusted, i.e., not checked against the amount.
+		config(minted_coins): Vec<(u64, u64, [u8; 32])>;
+		/// The serialized hash parameter, defaults to `HASH_PARAM`.
+		config(hash_param): Vec<u8>;
+		/// The serialized commitment parameter, defaults to `COMMIT_PARAM`.
+		config(commit_param): Vec<u8>;
+
+		build(|config: &GenesisConfig<T>| {
+			let hash_param = if config.hash_param.is_empty() {
+				HashParam::deserialize(HASH_PARAM.data)
+			} else {
+				HashParam::deserialize(config.hash_param.as_ref())
+			};
+			let commit_param = if config.commit_param.is_empty() {
+				CommitmentParam::deserialize(COMMIT_PARAM.data)
+			} else {
+				CommitmentParam::deserialize(config.commit_param.as_ref())
+			};
+			HashParamChecksum::put(hash_param.get_checksum());
+			CommitParamChecksum::put(commit_param.get_checksum());
+			<Module<T>>::put_default_verification_keys();
+
+			StorageVersion::put(Releases::V9_0_0);
+			LedgerShardCount::put(T::ShardCount::get());
+
+			for (asset_id, owner, total) in config.assets.iter() {
+				assert!(
+					!TotalSupply::contains_key(asset_id),
+					"asset {} is issued twice at genesis",
+					asset_id
+				);
+				PoolBalance::insert(asset_id, 0);
+				TotalSupply::insert(asset_id, total);
+				<Balances<T>>::insert(owner, asset_id, total);
+			}
+
+			for (asset_id, amount, cm) in config.minted_coins.iter() {
+				let (_, owner, _) = config.assets
+					.iter()
+					.find(|(id, _, _)| id == asset_id)
+					.expect("coins are minted at genesis for an asset that is not issued");
+				assert!(!amount.is_zero(), "coins are minted at genesis with a zero amount");
+				assert!(
+					!<Module<T> as LedgerSharding>::exist(cm),
+					"a commitment is minted twice at genesis"
+				);
+
+				<Balances<T>>::mutate(owner, asset_id, |balance| {
+					*balance = balance
+						.checked_sub(*amount)
+						.expect("coins minted at genesis exceed the balance of the owner");
+				});
+				PoolBalance::mutate(asset_id, |pool| *pool += amount);
+				<Module<T>>::insert_coin(cm, EncryptedNote::default(), &hash_param);
+			}
+		});
 	}
 }
 
@@ -649,4 +1072,285 @@ impl<T: Config> Module<T> {
 	pub fn total_supply(what: u64) -> u64 {
 		TotalSupply::get(what)
 	}
+
+	/// Get the verification key in use for the circuit `kind`.
+	pub fn active_verification_key(kind: CircuitKind) -> Option<Vec<u8>> {
+		let version = ActiveVerificationKeyVersion::get(kind)?;
+		VerificationKeys::get(kind, version)
+	}
+
+	/// Get the prepared verification key in use for the circuit `kind`.
+	pub fn active_prepared_verification_key(kind: CircuitKind) -> Option<Vec<u8>> {
+		let version = ActiveVerificationKeyVersion::get(kind)?;
+		PreparedVerificationKeys::get(kind, version)
+	}
+
+	/// Store the verification key `key` of the circuit `kind`, together with
+	/// its prepared form, and mark it as the one in use.
+	pub(crate) fn put_verification_key(
+		kind: CircuitKind,
+		version: u32,
+		key: Vec<u8>,
+		prepared_key: Vec<u8>,
+	) {
+		VerificationKeys::insert(kind, version, key);
+		PreparedVerificationKeys::insert(kind, version, prepared_key);
+		ActiveVerificationKeyVersion::insert(kind, version);
+	}
+
+	/// Store the verification keys compiled in the runtime as the version 0 of the keys.
+	pub(crate) fn put_default_verification_keys() {
+		for (kind, key) in [
+			(CircuitKind::Transfer, TRANSFER_PK),
+			(CircuitKind::Reclaim, RECLAIM_PK),
+		]
+		.iter()
+		{
+			// the compiled keys are always valid
+			let prepared_key = prepare_verification_key(key.data).unwrap();
+			Self::put_verification_key(*kind, 0, key.data.to_vec(), prepared_key);
+		}
+	}
+
+	/// Check, verify and apply the private transfer `data`.
+	fn do_private_transfer(data: &PrivateTransferData) -> DispatchResult {
+		// get the parameter checksum from the ledger
+		// and make sure the parameters match
+		let hash_param_checksum_local = HASH_PARAM.get_checksum();
+
+		let hash_param_checksum = HashParamChecksum::get();
+		ensure!(
+			hash_param_checksum_local == hash_param_checksum,
+			<Error<T>>::MintFail
+		);
+		let hash_param = HashParam::deserialize(HASH_PARAM.data);
+
+		Self::check_private_transfers(sp_std::slice::from_ref(data))?;
+		Self::verify_private_transfer(data)?;
+
+		Self::apply_private_transfer(data, &hash_param);
+		Ok(())
+	}
+
+	/// Verify the proof of the private transfer `data`
+	/// against the active verification key.
+	fn verify_private_transfer(data: &PrivateTransferData) -> DispatchResult {
+		// get the verification key from the ledger
+		let transfer_vk = Self::active_prepared_verification_key(CircuitKind::Transfer)
+			.ok_or(<Error<T>>::ZkpParamFail)?;
+
+		// check validity of zkp
+		data.try_verify(&transfer_vk).map_err(<Error<T>>::from)?;
+		Ok(())
+	}
+
+	/// Check that the private transfers `data` can be applied to the ledger,
+	/// see `check_spends`. Two transfers of `data` cannot spend or create the same coin.
+	fn check_private_transfers(data: &[PrivateTransferData]) -> DispatchResult {
+		let mut void_numbers = BTreeSet::new();
+		let mut commitments = BTreeSet::new();
+		let chain_id = Self::chain_id();
+
+		for transfer in data.iter() {
+			ensure!(transfer.chain_id == chain_id, <Error<T>>::WrongChain);
+			Self::check_spends(
+				&[&transfer.sender_1, &transfer.sender_2],
+				&[&transfer.receiver_1, &transfer.receiver_2],
+				&mut void_numbers,
+				&mut commitments,
+			)?;
+		}
+
+		let fees: Vec<(u64, u64)> = data
+			.iter()
+			.map(|transfer| (transfer.asset_id, transfer.fee))
+			.collect();
+		Self::check_fees(&fees)
+	}
+
+	/// Check that the `fees`, as `(asset_id, fee)`, can be paid together
+	/// out of the private pools of their assets.
+	/// A transfer without a fee has a zero asset id, see `TransferCircuit`.
+	fn check_fees(fees: &[(u64, u64)]) -> DispatchResult {
+		ensure!(
+			fees.iter().all(|(asset_id, fee)| !fee.is_zero() || asset_id.is_zero()),
+			<Error<T>>::InvalidPayload
+		);
+
+		let mut totals: BTreeMap<u64, u64> = BTreeMap::new();
+		for (asset_id, fee) in fees.iter().filter(|(_, fee)| !fee.is_zero()) {
+			let total = totals.entry(*asset_id).or_insert(0);
+			*total = total.checked_add(*fee).ok_or(<Error<T>>::PoolOverdrawn)?;
+		}
+
+		for (asset_id, total) in totals.iter() {
+			ensure!(
+				TotalSupply::contains_key(asset_id),
+				<Error<T>>::BasecoinNotInit
+			);
+			ensure!(
+				PoolBalance::get(asset_id) >= *total,
+				<Error<T>>::PoolOverdrawn
+			);
+		}
+
+		Ok(())
+	}
+
+	/// Check that the `senders` can be spent and the `receivers` can be created:
+	/// the senders are not spent and their roots are known, and the receivers
+	/// are new. The void numbers and the commitments are added to `void_numbers`
+	/// and `commitments`, and must not be there already.
+	fn check_spends(
+		senders: &[&SenderData],
+		receivers: &[&ReceiverData],
+		void_numbers: &mut BTreeSet<[u8; 32]>,
+		commitments: &mut BTreeSet<[u8; 32]>,
+	) -> DispatchResult {
+		// check if vn_old already spent
+		for sender in senders.iter() {
+			ensure!(
+				!VoidNumbers::contains_key(&sender.void_number),
+				<Error<T>>::MantaCoinSpent
+			);
+			ensure!(
+				void_numbers.insert(sender.void_number),
+				<Error<T>>::MantaCoinSpent
+			);
+		}
+
+		// get the ledger state from the ledger
+		// and check the validity of the state
+		for sender in senders.iter() {
+			ensure!(
+				Self::check_root(&sender.root),
+				<Error<T>>::InvalidLedgerState
+			);
+		}
+
+		// check the commitment are not in the list already
+		ensure!(!Self::is_resharding(), <Error<T>>::LedgerResharding);
+		for receiver in receivers.iter() {
+			ensure!(!Self::exist(&receiver.cm), <Error<T>>::MantaCoinExist);
+			ensure!(commitments.insert(receiver.cm), <Error<T>>::MantaCoinExist);
+		}
+
+		Ok(())
+	}
+
+	/// Apply the checked private transfer `data` to the ledger.
+	fn apply_private_transfer(data: &PrivateTransferData, hash_param: &HashParam) {
+		Self::apply_spends(
+			&[&data.sender_1, &data.sender_2],
+			&[&data.receiver_1, &data.receiver_2],
+			hash_param,
+		);
+		Self::pay_fee(data.asset_id, data.fee);
+	}
+
+	/// Move the checked `fee` from the private pool of `asset_id`
+	/// to the balance of the `FeeCollector`.
+	fn pay_fee(asset_id: u64, fee: u64) {
+		if fee.is_zero() {
+			return;
+		}
+
+		let fee_collector = T::FeeCollector::get();
+		PoolBalance::mutate(asset_id, |balance| *balance -= fee);
+		<Balances<T>>::mutate(&fee_collector, asset_id, |balance| *balance += fee);
+		Self::deposit_event(RawEvent::FeePaid(asset_id, fee_collector, fee));
+	}
+
+	/// Spend the `senders` and add the `receivers` to the ledger.
+	fn apply_spends(senders: &[&SenderData], receivers: &[&ReceiverData], hash_param: &HashParam) {
+		// update coin list
+		// with sharding, there is no point to batch update
+		// since the commitments are likely to go to different shards
+		for receiver in receivers.iter() {
+			Self::insert_note(receiver, hash_param);
+		}
+
+		// update ledger storage
+		for sender in senders.iter() {
+			VoidNumbers::insert(&sender.void_number, ());
+		}
+	}
+
+	/// Add the commitment of `receiver` to the ledger,
+	/// and append its encrypted note to `Notes`.
+	fn insert_note(receiver: &ReceiverData, hash_param: &HashParam) {
+		Self::insert_coin(&receiver.cm, receiver.encrypted_note(), hash_param);
+	}
+
+	/// Add the commitment `cm` to the ledger, and append `encrypted_note`
+	/// to `Notes` with the position of the commitment.
+	fn insert_coin(cm: &[u8; 32], encrypted_note: EncryptedNote, hash_param: &HashParam) {
+		let (shard_index, leaf_index) = Self::update(cm, hash_param);
+		let note_index = NoteCount::get();
+		Notes::insert(
+			note_index,
+			NoteRecord {
+				cm: *cm,
+				shard_index,
+				leaf_index,
+				encrypted_note,
+			},
+		);
+		NoteIndices::insert(cm, note_index);
+		NoteCount::put(note_index + 1);
+	}
+
+	/// Get the encrypted note of the commitment `cm`.
+	pub fn encrypted_note(cm: &[u8; 32]) -> Option<EncryptedNote> {
+		let note_index = NoteIndices::get(cm)?;
+		Notes::get(note_index).map(|record| record.encrypted_note)
+	}
+
+	/// Get the notes of index `from` onwards, at most `limit` of them and
+	/// at most `MAX_NOTES_PAGE_SIZE`.
+	/// The next page starts at `from` plus the number of returned notes;
+	/// the page is shorter than `limit` only when it reaches `note_count`.
+	pub fn notes(from: u64, limit: u32) -> Vec<NoteRecord> {
+		let to = from
+			.saturating_add(limit.min(MAX_NOTES_PAGE_SIZE) as u64)
+			.min(NoteCount::get());
+		(from..to).filter_map(Notes::get).collect()
+	}
+
+	/// The identifier of the chain, i.e., the hash of its genesis block,
+	/// padded with zeros to 32 bytes.
+	/// The proofs are bound to it, so that a payload cannot be replayed on
+	/// another chain.
+	pub fn chain_id() -> [u8; 32] {
+		let genesis_hash = <frame_system::Module<T>>::block_hash(T::BlockNumber::zero());
+		let genesis_hash = genesis_hash.as_ref();
+		let len = genesis_hash.len().min(32);
+		let mut chain_id = [0u8; 32];
+		chain_id[..len].copy_from_slice(&genesis_hash[..len]);
+		chain_id
+	}
+
+	/// Decode the `recipient` of a reclaim, i.e., the encoding of an account
+	/// padded with zeros to 32 bytes, see `encode_recipient`.
+	/// The padding must be zeros, so that an account has a single encoding.
+	pub fn recipient_account(recipient: &[u8; 32]) -> Option<T::AccountId> {
+		let account = T::AccountId::decode(&mut recipient.as_ref()).ok()?;
+		let encoded_len = account.encoded_size();
+		if recipient[encoded_len..].iter().any(|byte| *byte != 0) {
+			return None;
+		}
+		Some(account)
+	}
+
+	/// Check if the void number `vn` has been revealed,
+	/// i.e., if the coin it belongs to has been spent.
+	pub fn is_void_number_spent(vn: &[u8; 32]) -> bool {
+		VoidNumbers::contains_key(vn)
+	}
+
+	/// Check if the ledger is being re-sharded, see `migration::start_reshard`.
+	/// No coin can be added to the ledger in the meantime.
+	pub fn is_resharding() -> bool {
+		ReshardState::exists()
+	}
 }
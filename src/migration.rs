@@ -0,0 +1,353 @@
+// Copyright 2019-2021 Manta Network.
+// This file is part of pallet-manta-pay.
+//
+// pallet-manta-pay is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// pallet-manta-pay is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with pallet-manta-pay.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Storage migrations for manta-pay.
+//! Each migration moves the storage from one `Releases` to the next one;
+//! `migrate` applies all the pending migrations in order.
+
+use crate::*;
+use frame_support::{
+	codec::{Decode, Encode},
+	storage::migration::{get_storage_value, remove_storage_prefix, take_storage_value},
+	traits::Get,
+	weights::Weight,
+	Blake2_128Concat, StorageHasher,
+};
+use manta_crypto::*;
+
+/// The number of steps of the re-sharding done in each block,
+/// see `continue_reshard`.
+pub(crate) const RESHARD_BATCH_SIZE: u32 = 64;
+
+/// The number of shards before `LedgerShardCount` was introduced.
+const LEGACY_SHARD_COUNT: u32 = 256;
+
+/// Apply all the pending migrations.
+pub(crate) fn migrate<T: Config>() -> Weight {
+	let mut weight: Weight = 0;
+
+	let initial_version = StorageVersion::get();
+	let version = initial_version;
+	if version == Releases::V1_0_0 || version == Releases::V2_0_0 {
+		weight = weight.saturating_add(v3::migrate::<T>(version));
+		StorageVersion::put(Releases::V3_0_0);
+	}
+
+	if StorageVersion::get() == Releases::V3_0_0 {
+		weight = weight.saturating_add(v4::migrate::<T>());
+		StorageVersion::put(Releases::V4_0_0);
+	}
+
+	// `V7_0_0` re-shards the ledger; the shard indices are `u8`s before,
+	// unless the shards have just been written by `v3::migrate`
+	let version = StorageVersion::get();
+	if version == Releases::V4_0_0 || version == Releases::V5_0_0 || version == Releases::V6_0_0 {
+		let legacy = initial_version != Releases::V1_0_0 && initial_version != Releases::V2_0_0;
+		weight = weight.saturating_add(start_reshard::<T>(LEGACY_SHARD_COUNT, legacy));
+		StorageVersion::put(Releases::V7_0_0);
+	}
+
+	if StorageVersion::get() == Releases::V7_0_0 {
+		weight = weight.saturating_add(v8::migrate::<T>());
+		StorageVersion::put(Releases::V8_0_0);
+	}
+
+	if StorageVersion::get() == Releases::V8_0_0 {
+		weight = weight.saturating_add(v9::migrate::<T>());
+		StorageVersion::put(Releases::V9_0_0);
+	}
+
+	// the number of shards has been changed
+	if LedgerShardCount::get() != T::ShardCount::get() && !Module::<T>::is_resharding() {
+		let shard_count = match LedgerShardCount::get() {
+			0 => LEGACY_SHARD_COUNT,
+			shard_count => shard_count,
+		};
+		weight = weight.saturating_add(start_reshard::<T>(shard_count, false));
+	}
+
+	weight
+}
+
+/// Migrate from `V1_0_0` or `V2_0_0` to `V3_0_0`:
+/// split the `CoinShards` value into `ShardMetas` and `ShardLeaves`.
+///
+/// In `V1_0_0` each shard holds a list of commitments and a root;
+/// `V2_0_0` adds the merkle frontier of the shard, which we need to
+/// rebuild when migrating from `V1_0_0`.
+pub(crate) mod v3 {
+	use super::*;
+	use crate::ledger::MerkleFrontier;
+
+	/// A shard as it is stored in `V1_0_0`.
+	#[derive(Decode)]
+	pub(crate) struct ShardV1 {
+		pub list: Vec<[u8; 32]>,
+		pub root: [u8; 32],
+	}
+
+	/// A shard as it is stored in `V2_0_0`.
+	#[derive(Decode)]
+	pub(crate) struct ShardV2 {
+		pub list: Vec<[u8; 32]>,
+		pub root: [u8; 32],
+		pub frontier: MerkleFrontier,
+	}
+
+	pub(crate) fn migrate<T: Config>(version: Releases) -> Weight {
+		let hash_param = HashParam::deserialize(HASH_PARAM.data);
+
+		// rebuilding the frontiers of `V1_0_0` costs `O(n * depth)` hashes,
+		// and moving the leaves is `O(n)` writes, so we simply claim
+		// the whole block for this migration
+		if version == Releases::V1_0_0 {
+			if let Some(shards) = take_coin_shards::<[ShardV1; 256]>() {
+				for (shard_index, shard) in shards.iter().enumerate() {
+					let frontier = MerkleFrontier::from_leaves(&hash_param, &shard.list);
+					insert_shard(shard_index as u32, &shard.list, shard.root, frontier);
+				}
+			}
+		} else if let Some(shards) = take_coin_shards::<[ShardV2; 256]>() {
+			for (shard_index, shard) in shards.iter().enumerate() {
+				insert_shard(
+					shard_index as u32,
+					&shard.list,
+					shard.root,
+					shard.frontier.clone(),
+				);
+			}
+		}
+
+		T::BlockWeights::get().max_block
+	}
+
+	// take the deprecated `CoinShards` value out of the storage
+	fn take_coin_shards<S: Decode>() -> Option<S> {
+		take_storage_value(b"Assets", b"CoinShards", &[])
+	}
+
+	// write a shard into `ShardMetas` and `ShardLeaves`
+	fn insert_shard(shard_index: u32, list: &[[u8; 32]], root: [u8; 32], frontier: MerkleFrontier) {
+		if list.is_empty() {
+			return;
+		}
+		for (leaf_index, cm) in list.iter().enumerate() {
+			ShardLeaves::insert(shard_index, leaf_index as u64, cm);
+			CoinCommitments::insert(cm, ());
+		}
+		LedgerRoots::insert(&root, ());
+		ShardMetas::insert(shard_index, ShardMeta { root, frontier });
+	}
+}
+
+/// Migrate from `V3_0_0` to `V4_0_0`:
+/// move the void numbers from the `VNList` vector into the `VoidNumbers` set.
+pub(crate) mod v4 {
+	use super::*;
+
+	pub(crate) fn migrate<T: Config>() -> Weight {
+		let vn_list: Vec<[u8; 32]> =
+			take_storage_value(b"Assets", b"VNList", &[]).unwrap_or_default();
+		for vn in vn_list.iter() {
+			VoidNumbers::insert(vn, ());
+		}
+
+		T::DbWeight::get().reads_writes(1, vn_list.len() as Weight + 1)
+	}
+}
+
+/// Migrate from `V7_0_0` to `V8_0_0`:
+/// store the verification keys on chain rather than their checksums.
+/// The keys compiled in the runtime become the version 0 of the keys.
+pub(crate) mod v8 {
+	use super::*;
+
+	pub(crate) fn migrate<T: Config>() -> Weight {
+		let transfer_checksum: Option<[u8; 32]> =
+			take_storage_value(b"Assets", b"TransferZKPKeyChecksum", &[]);
+		let reclaim_checksum: Option<[u8; 32]> =
+			take_storage_value(b"Assets", b"ReclaimZKPKeyChecksum", &[]);
+
+		// the ledger has not been initialized yet
+		if transfer_checksum.is_none() && reclaim_checksum.is_none() {
+			return T::DbWeight::get().reads_writes(2, 2);
+		}
+
+		Module::<T>::put_default_verification_keys();
+
+		T::DbWeight::get().reads_writes(2, 8)
+	}
+}
+
+/// Migrate from `V8_0_0` to `V9_0_0`:
+/// store the prepared form of every verification key.
+pub(crate) mod v9 {
+	use super::*;
+
+	pub(crate) fn migrate<T: Config>() -> Weight {
+		let mut key_count: Weight = 0;
+		for (kind, version, key) in VerificationKeys::iter() {
+			// the stored keys have been checked when they were set
+			if let Some(prepared_key) = prepare_verification_key(&key) {
+				PreparedVerificationKeys::insert(kind, version, prepared_key);
+			}
+			key_count += 1;
+		}
+
+		// preparing a key is a pairing precomputation
+		T::DbWeight::get()
+			.reads_writes(key_count, key_count)
+			.saturating_add(key_count.saturating_mul(T::WeightInfo::set_verification_key()))
+	}
+}
+
+/// Start rebuilding the ledger of `shard_count` shards over `T::ShardCount` shards.
+///
+/// Moving the leaves costs `O(n)` writes and re-inserting them `O(n * depth)`
+/// hashes, so both are done in the following blocks by `continue_reshard`,
+/// and the ledger does not accept new coins until then:
+/// 1. the leaves of the old shards are moved into `ReshardQueue`, shard by
+///    shard and in order, and the old shards are removed;
+/// 2. the leaves are re-inserted into the new shards in the order of the queue,
+///    so that the leaves keep their relative order in the new shards.
+///
+/// The coins get new positions nonetheless, so `LedgerResharded` is deposited
+/// once done, for the wallets to rebuild their witnesses.
+/// `T::ShardCount` must not change again until then.
+///
+/// The roots of the old shards are kept in `LedgerRoots`, so that the
+/// transactions in flight remain valid once the ledger is re-sharded.
+/// They are no longer in `ShardRootHistory` and are thus never evicted,
+/// which costs at most `RootHistorySize` roots per old shard.
+pub(crate) fn start_reshard<T: Config>(shard_count: u32, legacy: bool) -> Weight {
+	ReshardState::put(ReshardProgress::Collect {
+		shard_count,
+		legacy,
+		shard_index: 0,
+		leaf_index: 0,
+		queued: 0,
+	});
+	T::DbWeight::get().reads_writes(1, 1)
+}
+
+/// Do the next `RESHARD_BATCH_SIZE` steps of the re-sharding of the ledger,
+/// if any, see `start_reshard`.
+/// A step moves a leaf into the queue, removes an old shard,
+/// or re-inserts a leaf and updates the position of its coin in `Notes`.
+pub(crate) fn continue_reshard<T: Config>() -> Weight {
+	let mut progress = match ReshardState::get() {
+		Some(progress) => progress,
+		None => return T::DbWeight::get().reads(1),
+	};
+	let hash_param = HashParam::deserialize(HASH_PARAM.data);
+
+	let mut weight = T::DbWeight::get().reads_writes(1, 1);
+	for _ in 0..RESHARD_BATCH_SIZE {
+		progress = match progress {
+			ReshardProgress::Collect {
+				shard_count,
+				legacy,
+				shard_index,
+				leaf_index,
+				queued,
+			} => {
+				weight = weight.saturating_add(T::DbWeight::get().reads_writes(2, 2));
+				if shard_index == shard_count {
+					ReshardProgress::Replay { index: 0, queued }
+				} else {
+					collect_leaf(shard_count, legacy, shard_index, leaf_index, queued)
+				}
+			}
+			ReshardProgress::Replay { index, queued } => {
+				if index == queued {
+					ReshardState::kill();
+					LedgerShardCount::put(T::ShardCount::get());
+					Module::<T>::deposit_event(RawEvent::LedgerResharded(T::ShardCount::get()));
+					return weight.saturating_add(T::DbWeight::get().writes(2));
+				}
+				weight = weight.saturating_add(T::WeightInfo::mint_private_asset());
+				if let Some(cm) = ReshardQueue::take(index) {
+					replay_leaf::<T>(&cm, &hash_param);
+				}
+				ReshardProgress::Replay {
+					index: index + 1,
+					queued,
+				}
+			}
+		};
+	}
+	ReshardState::put(progress);
+
+	weight
+}
+
+// move the leaf `leaf_index` of the old shard `shard_index` into the queue,
+// or remove the old shard if all its leaves are moved
+fn collect_leaf(
+	shard_count: u32,
+	legacy: bool,
+	shard_index: u32,
+	leaf_index: u64,
+	mut queued: u64,
+) -> ReshardProgress {
+	let shard_key = if legacy {
+		Blake2_128Concat::hash(&(shard_index as u8).encode())
+	} else {
+		Blake2_128Concat::hash(&shard_index.encode())
+	};
+	let leaf_count = get_storage_value::<ShardMeta>(b"Assets", b"ShardMetas", &shard_key)
+		.map_or(0, |meta| meta.frontier.leaf_count);
+
+	if leaf_index < leaf_count {
+		let leaf_key = [shard_key, Blake2_128Concat::hash(&leaf_index.encode())].concat();
+		if let Some(cm) = take_storage_value::<[u8; 32]>(b"Assets", b"ShardLeaves", &leaf_key) {
+			ReshardQueue::insert(queued, cm);
+			queued += 1;
+		}
+		ReshardProgress::Collect {
+			shard_count,
+			legacy,
+			shard_index,
+			leaf_index: leaf_index + 1,
+			queued,
+		}
+	} else {
+		// the root history of a shard holds at most `RootHistorySize` roots
+		take_storage_value::<ShardMeta>(b"Assets", b"ShardMetas", &shard_key);
+		remove_storage_prefix(b"Assets", b"ShardRootHistory", &shard_key);
+		ReshardProgress::Collect {
+			shard_count,
+			legacy,
+			shard_index: shard_index + 1,
+			leaf_index: 0,
+			queued,
+		}
+	}
+}
+
+// re-insert the leaf `cm` into the new shards,
+// and keep track of the new position of its coin in the notes
+fn replay_leaf<T: Config>(cm: &[u8; 32], hash_param: &HashParam) {
+	let (shard_index, leaf_index) = <Module<T> as LedgerSharding>::update(cm, hash_param);
+	if let Some(note_index) = NoteIndices::get(cm) {
+		Notes::mutate(note_index, |record| {
+			if let Some(record) = record {
+				record.shard_index = shard_index;
+				record.leaf_index = leaf_index;
+			}
+		});
+	}
+}
@@ -16,13 +16,40 @@
 
 use super::*;
 
+impl Default for ReceiverData {
+	fn default() -> Self {
+		Self {
+			k: [0u8; 32],
+			cm: [0u8; 32],
+			sender_pk: [0u8; 32],
+			cipher: [0u8; NOTE_CIPHERTEXT_SIZE],
+		}
+	}
+}
+
 impl Default for PrivateTransferData {
 	fn default() -> Self {
 		Self {
+			asset_id: AssetId::default(),
+			fee: 0,
 			sender_1: SenderData::default(),
 			sender_2: SenderData::default(),
 			receiver_1: ReceiverData::default(),
 			receiver_2: ReceiverData::default(),
+			chain_id: [0u8; 32],
+			proof: [0u8; 192],
+		}
+	}
+}
+
+impl Default for PrivateTransferNData {
+	fn default() -> Self {
+		Self {
+			asset_id: AssetId::default(),
+			fee: 0,
+			senders: Vec::new(),
+			receivers: Vec::new(),
+			chain_id: [0u8; 32],
 			proof: [0u8; 192],
 		}
 	}
@@ -33,9 +60,11 @@ impl Default for ReclaimData {
 		Self {
 			asset_id: AssetId::default(),
 			reclaim_amount: 0,
+			recipient: [0u8; 32],
 			sender_1: SenderData::default(),
 			sender_2: SenderData::default(),
 			receiver: ReceiverData::default(),
+			chain_id: [0u8; 32],
 			proof: [0u8; 192],
 		}
 	}
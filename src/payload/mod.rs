@@ -15,17 +15,69 @@
 // along with pallet-manta-pay.  If not, see <http://www.gnu.org/licenses/>.
 
 use crate::*;
+use ark_ed_on_bls12_381::Fq;
 use ark_groth16::create_random_proof;
+use ark_relations::r1cs::ConstraintSynthesizer;
 use ark_serialize::CanonicalSerialize;
-use ark_std::rand::{CryptoRng, RngCore};
+use ark_std::{
+	io::Read,
+	rand::{CryptoRng, RngCore},
+};
 use frame_support::codec::{Decode, Encode};
 use manta_asset::*;
 use manta_crypto::*;
+use sp_runtime::traits::{BlakeTwo256, Hash as HashT};
 
 mod default;
+mod note;
 mod santiy;
 mod serdes;
 
+pub use note::{encrypt_note, try_decrypt_note, EncryptedNote, Note, NOTE_CIPHERTEXT_SIZE};
+
+/// The errors of `TryDeserialize`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PayloadError {
+	/// The payload is too short, or one of its fields is malformed.
+	InvalidPayload,
+	/// The proof is not a valid encoding of a Groth16 proof.
+	InvalidProofEncoding,
+	/// A commitment, a `k` or a merkle root is not a valid curve point.
+	InvalidCurvePoint,
+}
+
+/// The reason a payload cannot be generated, see `generate_private_transfer_payload`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PayloadGenError {
+	/// The coins are not all coins of the same asset.
+	AssetMismatch,
+	/// The value of the senders is not the value of the receivers,
+	/// plus the fee or the reclaimed value.
+	ValueMismatch,
+	/// The value of a minted coin is zero.
+	ZeroValue,
+	/// The commitment of a minted coin does not open to its value.
+	InvalidCommitment,
+	/// The membership of a sender does not open to its root,
+	/// or it is the membership of another coin.
+	InvalidMembership,
+	/// There is no circuit for this number of senders and receivers.
+	UnsupportedArity,
+	/// The encoding of the recipient of a reclaim is longer than 32 bytes.
+	RecipientTooLong,
+	/// The proving key does not fit the circuit.
+	ProofGeneration,
+	/// A root or the proof cannot be serialized.
+	Serialization,
+}
+
+/// Fallible deserialization of the extrinsic payloads.
+/// Unlike `MantaSerDes::deserialize`, this does not panic on malformed bytes,
+/// and it checks the encoding of the proof and of the curve points.
+pub trait TryDeserialize: Sized {
+	fn try_deserialize<R: Read>(reader: R) -> Result<Self, PayloadError>;
+}
+
 /// Input data to a mint extrinsic.
 #[derive(Encode, Debug, Decode, Clone, Default, PartialEq)]
 pub struct MintData {
@@ -37,26 +89,113 @@ pub struct MintData {
 }
 
 /// Input data to a private transfer extrinsic.
+/// The `fee` is paid out of the private pool of `asset_id`; without a fee,
+/// `asset_id` is zero, so that the transfer does not reveal its asset.
+/// The `chain_id` is the identifier of the chain the transfer is meant for,
+/// see `Module::chain_id`.
 #[derive(Encode, Debug, Decode, Clone, PartialEq)]
 pub struct PrivateTransferData {
+	pub asset_id: AssetId,
+	pub fee: u64,
 	pub sender_1: SenderData,
 	pub sender_2: SenderData,
 	pub receiver_1: ReceiverData,
 	pub receiver_2: ReceiverData,
+	pub chain_id: [u8; 32],
 	pub proof: [u8; 192],
 }
 
+/// Input data to a `private_transfer_n` extrinsic.
+/// It is serialized as the number of senders and the number of receivers,
+/// one byte each, followed by the asset id, the fee, the senders,
+/// the receivers, the chain id and the proof.
+#[derive(Encode, Debug, Decode, Clone, PartialEq)]
+pub struct PrivateTransferNData {
+	pub asset_id: AssetId,
+	pub fee: u64,
+	pub senders: Vec<SenderData>,
+	pub receivers: Vec<ReceiverData>,
+	pub chain_id: [u8; 32],
+	pub proof: [u8; 192],
+}
+
+impl PrivateTransferNData {
+	/// The circuit that proves this transfer.
+	pub fn circuit_kind(&self) -> CircuitKind {
+		CircuitKind::TransferN(self.senders.len() as u8, self.receivers.len() as u8)
+	}
+
+	/// The size of the serialized data.
+	pub fn payload_size(&self) -> usize {
+		2 + 16
+			+ self.senders.len() * SENDER_DATA_SIZE
+			+ self.receivers.len() * RECEIVER_DATA_SIZE
+			+ 32 + 192
+	}
+}
+
 /// Input data to a reclaim extrinsic.
+/// The `recipient` is the encoding of the account credited with the reclaimed
+/// amount, padded with zeros to 32 bytes.
 #[derive(Encode, Debug, Decode, Clone, PartialEq)]
 pub struct ReclaimData {
 	pub asset_id: AssetId,
 	pub reclaim_amount: u64,
+	pub recipient: [u8; 32],
 	pub sender_1: SenderData,
 	pub sender_2: SenderData,
 	pub receiver: ReceiverData,
+	pub chain_id: [u8; 32],
 	pub proof: [u8; 192],
 }
 
+impl PrivateTransferData {
+	/// The binding hash of the transfer, see `binding_hash`.
+	pub fn binding(&self) -> [u8; 32] {
+		binding_hash(TRANSFER_BINDING_DOMAIN, self)
+	}
+}
+
+impl PrivateTransferNData {
+	/// The binding hash of the transfer, see `binding_hash`.
+	pub fn binding(&self) -> [u8; 32] {
+		binding_hash(TRANSFER_BINDING_DOMAIN, self)
+	}
+}
+
+impl ReclaimData {
+	/// The binding hash of the reclaim, see `binding_hash`.
+	pub fn binding(&self) -> [u8; 32] {
+		binding_hash(RECLAIM_BINDING_DOMAIN, self)
+	}
+}
+
+/// The domain of the binding hash of the transfers.
+const TRANSFER_BINDING_DOMAIN: &[u8] = b"manta-pay/transfer";
+
+/// The domain of the binding hash of the reclaims.
+const RECLAIM_BINDING_DOMAIN: &[u8] = b"manta-pay/reclaim";
+
+/// The hash of the serialized `data` without its proof, under `domain`.
+/// It is a public input of the proof of `data`: the proof is bound to all the
+/// other fields of the payload, including the ciphertexts, the fee, the
+/// recipient and the chain id, which are not otherwise constrained by the
+/// circuits. Tampering with the payload, or replaying it on another chain,
+/// invalidates the proof.
+fn binding_hash<D: MantaSerDes>(domain: &[u8], data: &D) -> [u8; 32] {
+	let mut bytes = domain.to_vec();
+	data.serialize(&mut bytes);
+	// the proof is always the last field of a payload
+	bytes.truncate(bytes.len() - 192);
+	BlakeTwo256::hash(&bytes).to_fixed_bytes()
+}
+
+/// The size of a serialized `SenderData`.
+pub(crate) const SENDER_DATA_SIZE: usize = 96;
+
+/// The size of a serialized `ReceiverData`.
+pub(crate) const RECEIVER_DATA_SIZE: usize = 96 + NOTE_CIPHERTEXT_SIZE;
+
 /// Data required for a sender to spend a coin.
 #[derive(Encode, Debug, Decode, Clone, Default, PartialEq)]
 pub struct SenderData {
@@ -66,20 +205,33 @@ pub struct SenderData {
 }
 
 /// Data required for a receiver to receive a coin.
-#[derive(Encode, Debug, Decode, Clone, Default, PartialEq)]
+/// `sender_pk` and `cipher` are the encrypted note of the receiver,
+/// see `encrypt_note`.
+#[derive(Encode, Debug, Decode, Clone, PartialEq)]
 pub struct ReceiverData {
 	pub k: [u8; 32],
 	pub cm: [u8; 32],
 	pub sender_pk: [u8; 32],
-	pub cipher: [u8; 16],
+	pub cipher: [u8; NOTE_CIPHERTEXT_SIZE],
 }
 
 /// Given the inputs, generate the payload for the mint_asset extrinsic.
-pub fn generate_mint_payload(asset: &MantaAsset) -> [u8; MINT_PAYLOAD_SIZE] {
+/// Fails if the asset would not pass the checks of the extrinsic.
+pub fn generate_mint_payload(
+	asset: &MantaAsset,
+) -> Result<[u8; MINT_PAYLOAD_SIZE], PayloadGenError> {
 	let data = generate_mint_struct(asset);
+	if data.amount == 0 {
+		return Err(PayloadGenError::ZeroValue);
+	}
+	let commit_param = CommitmentParam::deserialize(COMMIT_PARAM.data);
+	if !data.sanity(&commit_param) {
+		return Err(PayloadGenError::InvalidCommitment);
+	}
+
 	let mut res = [0u8; MINT_PAYLOAD_SIZE];
 	data.serialize(res.as_mut());
-	res
+	Ok(res)
 }
 
 /// Given the inputs, generate the stuct that can be passed to
@@ -104,10 +256,13 @@ fn generate_mint_struct(asset: &MantaAsset) -> MintData {
 ///     - sender_2: meta data for the second's first coin.
 ///     - receiver_1: a __PROCESSED__ receiver.
 ///     - receiver_2: the other __PROCESSED__ receiver.
+///     - fee: the public fee paid out of the private pool.
+///     - chain_id: the identifier of the chain, see `Module::chain_id`.
 ///     - rng: a random number generator.
 /// Outputs:
 ///     - the serialized payload that can be passed to the
-///       private_transfer extrinsic.
+///       private_transfer extrinsic, or the reason it cannot be generated;
+///       the inputs are checked before the proof is generated.
 #[allow(clippy::too_many_arguments)]
 pub fn generate_private_transfer_payload<R: RngCore + CryptoRng>(
 	commit_param: CommitmentParam,
@@ -117,8 +272,10 @@ pub fn generate_private_transfer_payload<R: RngCore + CryptoRng>(
 	sender_2: SenderMetaData,
 	receiver_1: MantaAssetProcessedReceiver,
 	receiver_2: MantaAssetProcessedReceiver,
+	fee: u64,
+	chain_id: [u8; 32],
 	rng: &mut R,
-) -> [u8; PRIVATE_TRANSFER_PAYLOAD_SIZE] {
+) -> Result<[u8; PRIVATE_TRANSFER_PAYLOAD_SIZE], PayloadGenError> {
 	let data = generate_private_transfer_struct(
 		commit_param,
 		hash_param,
@@ -127,11 +284,13 @@ pub fn generate_private_transfer_payload<R: RngCore + CryptoRng>(
 		sender_2,
 		receiver_1,
 		receiver_2,
+		fee,
+		chain_id,
 		rng,
-	);
+	)?;
 	let mut res = [0u8; PRIVATE_TRANSFER_PAYLOAD_SIZE];
 	data.serialize(res.as_mut());
-	res
+	Ok(res)
 }
 
 /// Given the inputs, generate the payload for the private_transfer
@@ -144,10 +303,12 @@ pub fn generate_private_transfer_payload<R: RngCore + CryptoRng>(
 ///     - sender_2: meta data for the second's first coin.
 ///     - receiver_1: a __PROCESSED__ receiver.
 ///     - receiver_2: the other __PROCESSED__ receiver.
+///     - fee: the public fee paid out of the private pool.
+///     - chain_id: the identifier of the chain, see `Module::chain_id`.
 ///     - rng: a random number generator.
 /// Outputs:
 ///     - a data struct, once serialized, can be passed to the
-///       private_transfer extrinsic.
+///       private_transfer extrinsic, or the reason it cannot be generated.
 #[allow(clippy::too_many_arguments)]
 fn generate_private_transfer_struct<R: RngCore + CryptoRng>(
 	commit_param: CommitmentParam,
@@ -157,57 +318,159 @@ fn generate_private_transfer_struct<R: RngCore + CryptoRng>(
 	sender_2: SenderMetaData,
 	receiver_1: MantaAssetProcessedReceiver,
 	receiver_2: MantaAssetProcessedReceiver,
+	fee: u64,
+	chain_id: [u8; 32],
 	rng: &mut R,
-) -> PrivateTransferData {
+) -> Result<PrivateTransferData, PayloadGenError> {
+	let asset_id = check_transaction(
+		&hash_param,
+		&[&sender_1, &sender_2],
+		&[&receiver_1, &receiver_2],
+		fee,
+	)?;
+
+	let mut data = PrivateTransferData {
+		asset_id: public_asset_id(asset_id, fee),
+		fee,
+		sender_1: to_sender_data(&sender_1)?,
+		sender_2: to_sender_data(&sender_2)?,
+		receiver_1: note::receiver_data(&receiver_1, rng),
+		receiver_2: note::receiver_data(&receiver_2, rng),
+		chain_id,
+		proof: [0u8; 192],
+	};
+
 	// generate circuit
 	let circuit = TransferCircuit {
 		commit_param,
 		hash_param,
 
-		sender_1: sender_1.clone(),
-		sender_2: sender_2.clone(),
+		asset_id: data.asset_id,
+		fee,
+		binding: data.binding(),
+
+		sender_1,
+		sender_2,
 
-		receiver_1: receiver_1.clone(),
-		receiver_2: receiver_2.clone(),
+		receiver_1,
+		receiver_2,
 	};
 
 	// generate ZKP
-	let proof = create_random_proof(circuit, &pk, rng).unwrap();
-	let mut proof_bytes = [0u8; 192];
-	proof.serialize(proof_bytes.as_mut()).unwrap();
-
-	// serialize the roots
-	let mut root_1 = [0u8; 32];
-	sender_1.root.serialize(root_1.as_mut()).unwrap();
-
-	let mut root_2 = [0u8; 32];
-	sender_2.root.serialize(root_2.as_mut()).unwrap();
-
-	PrivateTransferData {
-		sender_1: SenderData {
-			k: sender_1.asset.pub_info.k,
-			void_number: sender_1.asset.void_number,
-			root: root_1,
-		},
-		sender_2: SenderData {
-			k: sender_2.asset.pub_info.k,
-			void_number: sender_2.asset.void_number,
-			root: root_2,
-		},
-		receiver_1: ReceiverData {
-			k: receiver_1.prepared_data.k,
-			cm: receiver_1.commitment,
-			sender_pk: receiver_1.sender_pk,
-			cipher: receiver_1.ciphertext,
-		},
-		receiver_2: ReceiverData {
-			k: receiver_2.prepared_data.k,
-			cm: receiver_2.commitment,
-			sender_pk: receiver_2.sender_pk,
-			cipher: receiver_2.ciphertext,
-		},
-		proof: proof_bytes,
+	data.proof = prove(circuit, pk, rng)?;
+
+	Ok(data)
+}
+
+/// Given the inputs, generate the payload for the private_transfer_n
+/// extrinsic.
+/// Inputs:
+///     - commit_param: commitment parameters.
+///     - hash_param: hash parameters.
+///     - pk: proving key of the `MultiTransferCircuit` for this number
+///       of senders and receivers.
+///     - senders: meta data for the sender's coins.
+///     - receivers: the __PROCESSED__ receivers.
+///     - fee: the public fee paid out of the private pool.
+///     - chain_id: the identifier of the chain, see `Module::chain_id`.
+///     - rng: a random number generator.
+/// Outputs:
+///     - the serialized payload that can be passed to the
+///       private_transfer_n extrinsic, or the reason it cannot be generated;
+///       the inputs are checked before the proof is generated.
+pub fn generate_private_transfer_n_payload<R: RngCore + CryptoRng>(
+	commit_param: CommitmentParam,
+	hash_param: HashParam,
+	pk: &Groth16Pk,
+	senders: Vec<SenderMetaData>,
+	receivers: Vec<MantaAssetProcessedReceiver>,
+	fee: u64,
+	chain_id: [u8; 32],
+	rng: &mut R,
+) -> Result<Vec<u8>, PayloadGenError> {
+	let data = generate_private_transfer_n_struct(
+		commit_param,
+		hash_param,
+		pk,
+		senders,
+		receivers,
+		fee,
+		chain_id,
+		rng,
+	)?;
+	let mut res = Vec::with_capacity(data.payload_size());
+	data.serialize(&mut res);
+	Ok(res)
+}
+
+/// Given the inputs, generate the stuct that can be passed to
+/// the private_transfer_n extrinsic once serialized.
+fn generate_private_transfer_n_struct<R: RngCore + CryptoRng>(
+	commit_param: CommitmentParam,
+	hash_param: HashParam,
+	pk: &Groth16Pk,
+	senders: Vec<SenderMetaData>,
+	receivers: Vec<MantaAssetProcessedReceiver>,
+	fee: u64,
+	chain_id: [u8; 32],
+	rng: &mut R,
+) -> Result<PrivateTransferNData, PayloadGenError> {
+	if !(1..=MAX_TRANSFER_ARITY as usize).contains(&senders.len())
+		|| !(1..=MAX_TRANSFER_ARITY as usize).contains(&receivers.len())
+	{
+		return Err(PayloadGenError::UnsupportedArity);
 	}
+	let asset_id = check_transaction(
+		&hash_param,
+		&senders.iter().collect::<Vec<_>>(),
+		&receivers.iter().collect::<Vec<_>>(),
+		fee,
+	)?;
+
+	let sender_data = senders.iter().map(to_sender_data).collect::<Result<_, _>>()?;
+	let receiver_data = receivers
+		.iter()
+		.map(|receiver| note::receiver_data(receiver, rng))
+		.collect();
+
+	let mut data = PrivateTransferNData {
+		asset_id: public_asset_id(asset_id, fee),
+		fee,
+		senders: sender_data,
+		receivers: receiver_data,
+		chain_id,
+		proof: [0u8; 192],
+	};
+
+	// generate circuit
+	let circuit = MultiTransferCircuit {
+		commit_param,
+		hash_param,
+		asset_id: data.asset_id,
+		fee,
+		binding: data.binding(),
+		senders,
+		receivers,
+	};
+
+	// generate ZKP
+	data.proof = prove(circuit, pk, rng)?;
+
+	Ok(data)
+}
+
+/// Encode the `account` as the recipient of a reclaim, i.e., pad its
+/// encoding with zeros to 32 bytes.
+/// Fails if the encoding of the account is longer than 32 bytes.
+pub fn encode_recipient<A: Encode>(account: &A) -> Result<[u8; 32], PayloadGenError> {
+	let mut recipient = [0u8; 32];
+	account.using_encoded(|encoded| {
+		recipient
+			.get_mut(..encoded.len())
+			.ok_or(PayloadGenError::RecipientTooLong)?
+			.copy_from_slice(encoded);
+		Ok(recipient)
+	})
 }
 
 /// Given the inputs, generate the payload for the reclaim extrinsic.
@@ -219,10 +482,14 @@ fn generate_private_transfer_struct<R: RngCore + CryptoRng>(
 ///     - sender_2: meta data for the second's first coin.
 ///     - receiver: a __PROCESSED__ receiver.
 ///     - reclaimed_value: the number of reclaimed assets.
+///     - recipient: the encoding of the account credited with the
+///       reclaimed assets, padded with zeros to 32 bytes.
+///     - chain_id: the identifier of the chain, see `Module::chain_id`.
 ///     - rng: a random number generator.
 /// Outputs:
 ///     - the serialized payload that can be passed to the
-///       reclaim extrinsic.
+///       reclaim extrinsic, or the reason it cannot be generated;
+///       the inputs are checked before the proof is generated.
 #[allow(clippy::too_many_arguments)]
 pub fn generate_reclaim_payload<R: RngCore + CryptoRng>(
 	commit_param: CommitmentParam,
@@ -232,8 +499,10 @@ pub fn generate_reclaim_payload<R: RngCore + CryptoRng>(
 	sender_2: SenderMetaData,
 	receiver: MantaAssetProcessedReceiver,
 	reclaim_value: u64,
+	recipient: [u8; 32],
+	chain_id: [u8; 32],
 	rng: &mut R,
-) -> [u8; RECLAIM_PAYLOAD_SIZE] {
+) -> Result<[u8; RECLAIM_PAYLOAD_SIZE], PayloadGenError> {
 	let data = generate_reclaim_struct(
 		commit_param,
 		hash_param,
@@ -242,11 +511,13 @@ pub fn generate_reclaim_payload<R: RngCore + CryptoRng>(
 		sender_2,
 		receiver,
 		reclaim_value,
+		recipient,
+		chain_id,
 		rng,
-	);
+	)?;
 	let mut res = [0u8; RECLAIM_PAYLOAD_SIZE];
 	data.serialize(res.as_mut());
-	res
+	Ok(res)
 }
 
 /// Given the inputs, generate the payload for the reclaim extrinsic.
@@ -258,10 +529,13 @@ pub fn generate_reclaim_payload<R: RngCore + CryptoRng>(
 ///     - sender_2: meta data for the second's first coin.
 ///     - receiver: a __PROCESSED__ receiver.
 ///     - reclaimed_value: the number of reclaimed assets.
+///     - recipient: the encoding of the account credited with the
+///       reclaimed assets, padded with zeros to 32 bytes.
+///     - chain_id: the identifier of the chain, see `Module::chain_id`.
 ///     - rng: a random number generator.
 /// Outputs:
 ///     - a data struct, once serialized, can be passed to the
-///       reclaim extrinsic.
+///       reclaim extrinsic, or the reason it cannot be generated.
 #[allow(clippy::too_many_arguments)]
 fn generate_reclaim_struct<R: RngCore + CryptoRng>(
 	commit_param: CommitmentParam,
@@ -271,63 +545,134 @@ fn generate_reclaim_struct<R: RngCore + CryptoRng>(
 	sender_2: SenderMetaData,
 	receiver: MantaAssetProcessedReceiver,
 	reclaim_value: u64,
+	recipient: [u8; 32],
+	chain_id: [u8; 32],
 	rng: &mut R,
-) -> ReclaimData {
-	// check the asset_ids match
-	assert_eq!(
-		sender_1.asset.asset_id, sender_2.asset.asset_id,
-		"Asset_ids do not match"
-	);
-	assert_eq!(
-		sender_1.asset.asset_id, receiver.prepared_data.asset_id,
-		"Asset_ids do not match"
-	);
+) -> Result<ReclaimData, PayloadGenError> {
+	let asset_id = check_transaction(
+		&hash_param,
+		&[&sender_1, &sender_2],
+		&[&receiver],
+		reclaim_value,
+	)?;
+
+	let mut data = ReclaimData {
+		asset_id,
+		reclaim_amount: reclaim_value,
+		recipient,
+		sender_1: to_sender_data(&sender_1)?,
+		sender_2: to_sender_data(&sender_2)?,
+		receiver: note::receiver_data(&receiver, rng),
+		chain_id,
+		proof: [0u8; 192],
+	};
 
 	// generate circuit
 	let circuit = ReclaimCircuit {
 		commit_param,
 		hash_param,
 
-		sender_1: sender_1.clone(),
-		sender_2: sender_2.clone(),
+		sender_1,
+		sender_2,
 
-		receiver: receiver.clone(),
+		receiver,
 
-		asset_id: sender_1.asset.asset_id,
+		asset_id: data.asset_id,
 		reclaim_value,
+		recipient,
+		binding: data.binding(),
 	};
 
 	// generate ZKP
-	let proof = create_random_proof(circuit, &pk, rng).unwrap();
-	let mut proof_bytes = [0u8; 192];
-	proof.serialize(proof_bytes.as_mut()).unwrap();
+	data.proof = prove(circuit, pk, rng)?;
+
+	Ok(data)
+}
 
-	// serialize the roots
-	let mut root_1 = [0u8; 32];
-	sender_1.root.serialize(root_1.as_mut()).unwrap();
+// The asset id that a transfer of coins of `asset_id` reveals: the asset of the fee,
+// if there is one, see `TransferCircuit`.
+fn public_asset_id(asset_id: AssetId, fee: u64) -> AssetId {
+	if fee == 0 {
+		0
+	} else {
+		asset_id
+	}
+}
 
-	let mut root_2 = [0u8; 32];
-	sender_2.root.serialize(root_2.as_mut()).unwrap();
+// check that the senders and the receivers are coins of the same asset,
+// that the senders are in the ledger under their roots, and that the value of
+// the senders is the value of the receivers plus `public_value`, i.e., the fee
+// or the reclaimed value; these are the statements the proof would fail on,
+// and they are cheap to check compared to the proof
+fn check_transaction(
+	hash_param: &HashParam,
+	senders: &[&SenderMetaData],
+	receivers: &[&MantaAssetProcessedReceiver],
+	public_value: u64,
+) -> Result<AssetId, PayloadGenError> {
+	let asset_id = senders
+		.first()
+		.ok_or(PayloadGenError::UnsupportedArity)?
+		.asset
+		.asset_id;
+	if senders
+		.iter()
+		.any(|sender| sender.asset.asset_id != asset_id)
+		|| receivers
+			.iter()
+			.any(|receiver| receiver.prepared_data.asset_id != asset_id)
+	{
+		return Err(PayloadGenError::AssetMismatch);
+	}
 
-	ReclaimData {
-		asset_id: sender_1.asset.asset_id,
-		reclaim_amount: reclaim_value,
-		sender_1: SenderData {
-			k: sender_1.asset.pub_info.k,
-			void_number: sender_1.asset.void_number,
-			root: root_1,
-		},
-		sender_2: SenderData {
-			k: sender_2.asset.pub_info.k,
-			void_number: sender_2.asset.void_number,
-			root: root_2,
-		},
-		receiver: ReceiverData {
-			k: receiver.prepared_data.k,
-			cm: receiver.commitment,
-			sender_pk: receiver.sender_pk,
-			cipher: receiver.ciphertext,
-		},
-		proof: proof_bytes,
+	// the values are summed as `u128`, so the sums cannot overflow
+	let input: u128 = senders
+		.iter()
+		.map(|sender| sender.asset.priv_info.value as u128)
+		.sum();
+	let output: u128 = receivers
+		.iter()
+		.map(|receiver| receiver.value as u128)
+		.sum();
+	if input != output + public_value as u128 {
+		return Err(PayloadGenError::ValueMismatch);
+	}
+
+	for sender in senders.iter() {
+		if sender.membership.leaf != sender.asset.commitment
+			|| sender.membership.root(hash_param) != sender.root
+		{
+			return Err(PayloadGenError::InvalidMembership);
+		}
 	}
+	Ok(asset_id)
+}
+
+// the public data of a sender, i.e., its `k`, its void number and its root
+fn to_sender_data(sender: &SenderMetaData) -> Result<SenderData, PayloadGenError> {
+	let mut root = [0u8; 32];
+	sender
+		.root
+		.serialize(root.as_mut())
+		.map_err(|_| PayloadGenError::Serialization)?;
+	Ok(SenderData {
+		k: sender.asset.pub_info.k,
+		void_number: sender.asset.void_number,
+		root,
+	})
+}
+
+// generate the proof of `circuit`, and serialize it
+fn prove<C: ConstraintSynthesizer<Fq>, R: RngCore + CryptoRng>(
+	circuit: C,
+	pk: &Groth16Pk,
+	rng: &mut R,
+) -> Result<[u8; 192], PayloadGenError> {
+	let proof =
+		create_random_proof(circuit, pk, rng).map_err(|_| PayloadGenError::ProofGeneration)?;
+	let mut res = [0u8; 192];
+	proof
+		.serialize(res.as_mut())
+		.map_err(|_| PayloadGenError::Serialization)?;
+	Ok(res)
 }
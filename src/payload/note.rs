@@ -0,0 +1,213 @@
+// Copyright 2019-2021 Manta Network.
+// This file is part of pallet-manta-pay.
+//
+// pallet-manta-pay is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// pallet-manta-pay is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with pallet-manta-pay.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Encryption of the notes of the receivers.
+//!
+//! The note of a receiver is the opening of its commitment
+//! `cm = com(value || k, s)`, together with the asset id of the coin.
+//! It is encrypted to the ECIES public key `ecpk` of the receiver:
+//! 1. the sender samples an ephemeral x25519 key pair `(esk, epk)`;
+//! 2. the symmetric key is derived with HKDF-SHA256 from the
+//!    Diffie-Hellman secret `esk * ecpk`, `epk` and `ecpk`;
+//! 3. the note is encrypted with ChaCha20-Poly1305 under this key,
+//!    with the commitment as associated data.
+//!
+//! Each key encrypts a single note, so the nonce is fixed.
+//! `epk` is the `sender_pk` of the `ReceiverData`, and the ciphertext is its
+//! `cipher`; both are stored on chain under the commitment of the coin.
+
+use super::*;
+use chacha20poly1305::{
+	aead::{AeadInPlace, NewAead},
+	ChaCha20Poly1305, Key, Nonce, Tag,
+};
+use hkdf::Hkdf;
+use sha2::Sha256;
+use x25519_dalek::{PublicKey, SharedSecret, StaticSecret};
+
+/// The size of a serialized `Note`.
+pub const NOTE_PLAINTEXT_SIZE: usize = 80;
+
+/// The size of an encrypted `Note`, i.e., of the note and of its tag.
+pub const NOTE_CIPHERTEXT_SIZE: usize = NOTE_PLAINTEXT_SIZE + 16;
+
+/// The salt of the key derivation of the note encryption.
+const NOTE_KDF_SALT: &[u8] = b"manta-pay/note";
+
+/// The opening of the commitment of a received coin.
+#[derive(Encode, Debug, Decode, Clone, Default, PartialEq)]
+pub struct Note {
+	pub asset_id: AssetId,
+	pub value: u64,
+	pub k: [u8; 32],
+	pub s: [u8; 32],
+}
+
+/// An encrypted `Note`, as it is stored on chain.
+#[derive(Encode, Debug, Decode, Clone, PartialEq)]
+pub struct EncryptedNote {
+	/// The ephemeral public key of the sender.
+	pub sender_pk: [u8; 32],
+	pub cipher: [u8; NOTE_CIPHERTEXT_SIZE],
+}
+
+impl Default for EncryptedNote {
+	fn default() -> Self {
+		Self {
+			sender_pk: [0u8; 32],
+			cipher: [0u8; NOTE_CIPHERTEXT_SIZE],
+		}
+	}
+}
+
+impl Note {
+	/// Serialize the note into an array of 80 bytes.
+	fn to_bytes(&self) -> [u8; NOTE_PLAINTEXT_SIZE] {
+		let mut bytes = [0u8; NOTE_PLAINTEXT_SIZE];
+		bytes[0..8].copy_from_slice(&self.asset_id.to_le_bytes());
+		bytes[8..16].copy_from_slice(&self.value.to_le_bytes());
+		bytes[16..48].copy_from_slice(&self.k);
+		bytes[48..80].copy_from_slice(&self.s);
+		bytes
+	}
+
+	/// Deserialize an array of 80 bytes into a note.
+	fn from_bytes(bytes: &[u8; NOTE_PLAINTEXT_SIZE]) -> Self {
+		let mut asset_id = [0u8; 8];
+		asset_id.copy_from_slice(&bytes[0..8]);
+		let mut value = [0u8; 8];
+		value.copy_from_slice(&bytes[8..16]);
+		let mut note = Note {
+			asset_id: AssetId::from_le_bytes(asset_id),
+			value: u64::from_le_bytes(value),
+			..Default::default()
+		};
+		note.k.copy_from_slice(&bytes[16..48]);
+		note.s.copy_from_slice(&bytes[48..80]);
+		note
+	}
+}
+
+impl ReceiverData {
+	/// The encrypted note of the receiver.
+	pub fn encrypted_note(&self) -> EncryptedNote {
+		EncryptedNote {
+			sender_pk: self.sender_pk,
+			cipher: self.cipher,
+		}
+	}
+}
+
+/// Build the `ReceiverData` of a __PROCESSED__ receiver,
+/// encrypting its note to the receiver.
+pub(crate) fn receiver_data<R: RngCore + CryptoRng>(
+	receiver: &MantaAssetProcessedReceiver,
+	rng: &mut R,
+) -> ReceiverData {
+	let note = Note {
+		asset_id: receiver.prepared_data.asset_id,
+		value: receiver.value,
+		k: receiver.prepared_data.k,
+		s: receiver.prepared_data.s,
+	};
+	let encrypted_note = encrypt_note(
+		&note,
+		&receiver.prepared_data.ecpk,
+		&receiver.commitment,
+		rng,
+	);
+	ReceiverData {
+		k: receiver.prepared_data.k,
+		cm: receiver.commitment,
+		sender_pk: encrypted_note.sender_pk,
+		cipher: encrypted_note.cipher,
+	}
+}
+
+/// Encrypt `note` to the receiver public key `ecpk`,
+/// binding the ciphertext to the commitment `cm` of the note.
+pub fn encrypt_note<R: RngCore + CryptoRng>(
+	note: &Note,
+	ecpk: &[u8; 32],
+	cm: &[u8; 32],
+	rng: &mut R,
+) -> EncryptedNote {
+	let mut esk = [0u8; 32];
+	rng.fill_bytes(&mut esk);
+	let esk = StaticSecret::from(esk);
+	let epk = PublicKey::from(&esk);
+	let ecpk = PublicKey::from(*ecpk);
+
+	let cipher = note_cipher(&esk.diffie_hellman(&ecpk), &epk, &ecpk);
+	let mut buf = note.to_bytes();
+	// encryption only fails on messages longer than 2^38 bytes
+	let tag = cipher
+		.encrypt_in_place_detached(Nonce::from_slice(&[0u8; 12]), cm, &mut buf)
+		.unwrap();
+
+	let mut encrypted_note = EncryptedNote {
+		sender_pk: epk.to_bytes(),
+		..Default::default()
+	};
+	encrypted_note.cipher[..NOTE_PLAINTEXT_SIZE].copy_from_slice(&buf);
+	encrypted_note.cipher[NOTE_PLAINTEXT_SIZE..].copy_from_slice(&tag);
+	encrypted_note
+}
+
+/// Try to decrypt the note of the commitment `cm` with the secret key `ecsk`
+/// of a receiver.
+/// Returns `None` if the note was not encrypted to this receiver,
+/// or if the note or the commitment have been tampered with.
+pub fn try_decrypt_note(
+	ecsk: &MantaEciesSecretKey,
+	cm: &[u8; 32],
+	encrypted_note: &EncryptedNote,
+) -> Option<Note> {
+	let ecpk = PublicKey::from(ecsk);
+	let epk = PublicKey::from(encrypted_note.sender_pk);
+
+	let cipher = note_cipher(&ecsk.diffie_hellman(&epk), &epk, &ecpk);
+	let mut buf = [0u8; NOTE_PLAINTEXT_SIZE];
+	buf.copy_from_slice(&encrypted_note.cipher[..NOTE_PLAINTEXT_SIZE]);
+	let tag = Tag::from_slice(&encrypted_note.cipher[NOTE_PLAINTEXT_SIZE..]);
+	cipher
+		.decrypt_in_place_detached(Nonce::from_slice(&[0u8; 12]), cm, &mut buf, tag)
+		.ok()?;
+
+	Some(Note::from_bytes(&buf))
+}
+
+// derive the note cipher from the Diffie-Hellman secret of the sender and the
+// receiver; `epk` and `ecpk` are the ephemeral and the receiver public keys
+fn note_cipher(
+	shared_secret: &SharedSecret,
+	epk: &PublicKey,
+	ecpk: &PublicKey,
+) -> ChaCha20Poly1305 {
+	let ikm = [
+		shared_secret.as_bytes().as_ref(),
+		epk.as_bytes().as_ref(),
+		ecpk.as_bytes().as_ref(),
+	]
+	.concat();
+
+	let mut key = [0u8; 32];
+	// the output of the expansion is short enough to never fail
+	Hkdf::<Sha256>::new(Some(NOTE_KDF_SALT), &ikm)
+		.expand(&[], &mut key)
+		.unwrap();
+	ChaCha20Poly1305::new(Key::from_slice(&key))
+}
@@ -15,10 +15,12 @@
 // along with pallet-manta-pay.  If not, see <http://www.gnu.org/licenses/>.
 
 use super::*;
+use ark_ed_on_bls12_381::Fr;
+use ark_serialize::CanonicalDeserialize;
 use ark_std::io::{Read, Write};
 
 impl MantaSerDes for MintData {
-	/// Serialize the mint data into an array of 104 bytes.
+	/// Serialize the mint data into an array of `MINT_PAYLOAD_SIZE`, i.e., 112 bytes.
 	fn serialize<W: Write>(&self, mut writer: W) {
 		writer
 			.write_all(&(self.asset_id as u64).to_le_bytes())
@@ -31,7 +33,7 @@ impl MantaSerDes for MintData {
 		writer.write_all(&self.s).unwrap();
 	}
 
-	/// Deserialize an array of 104 bytes into a MintData.
+	/// Deserialize an array of `MINT_PAYLOAD_SIZE`, i.e., 112 bytes, into a MintData.
 	fn deserialize<R: Read>(mut reader: R) -> Self {
 		let mut data = MintData::default();
 
@@ -53,28 +55,102 @@ impl MantaSerDes for MintData {
 impl MantaSerDes for PrivateTransferData {
 	/// Serialize the private transfer data
 	fn serialize<W: Write>(&self, mut writer: W) {
+		writer
+			.write_all(&(self.asset_id as u64).to_le_bytes())
+			.unwrap();
+		writer.write_all(self.fee.to_le_bytes().as_ref()).unwrap();
 		self.sender_1.serialize(&mut writer);
 		self.sender_2.serialize(&mut writer);
 		self.receiver_1.serialize(&mut writer);
 		self.receiver_2.serialize(&mut writer);
+		writer.write_all(self.chain_id.as_ref()).unwrap();
 		writer.write_all(&self.proof.as_ref()).unwrap();
 	}
 
 	/// Deserialize the private transfer data
 	fn deserialize<R: Read>(mut reader: R) -> Self {
+		let mut buf = [0u8; 8];
+		reader.read_exact(buf.as_mut()).unwrap();
+		let asset_id = u64::from_le_bytes(buf);
+
+		reader.read_exact(buf.as_mut()).unwrap();
+		let fee = u64::from_le_bytes(buf);
+
 		let sender_1 = SenderData::deserialize(&mut reader);
 		let sender_2 = SenderData::deserialize(&mut reader);
 		let receiver_1 = ReceiverData::deserialize(&mut reader);
 		let receiver_2 = ReceiverData::deserialize(&mut reader);
 
+		let mut chain_id = [0u8; 32];
+		reader.read_exact(chain_id.as_mut()).unwrap();
+
 		let mut proof = [0u8; 192];
 		reader.read_exact(proof.as_mut()).unwrap();
 
 		Self {
+			asset_id,
+			fee,
 			sender_1,
 			sender_2,
 			receiver_1,
 			receiver_2,
+			chain_id,
+			proof,
+		}
+	}
+}
+
+impl MantaSerDes for PrivateTransferNData {
+	/// Serialize the private transfer data
+	fn serialize<W: Write>(&self, mut writer: W) {
+		writer
+			.write_all(&[self.senders.len() as u8, self.receivers.len() as u8])
+			.unwrap();
+		writer
+			.write_all(&(self.asset_id as u64).to_le_bytes())
+			.unwrap();
+		writer.write_all(self.fee.to_le_bytes().as_ref()).unwrap();
+		for sender in self.senders.iter() {
+			sender.serialize(&mut writer);
+		}
+		for receiver in self.receivers.iter() {
+			receiver.serialize(&mut writer);
+		}
+		writer.write_all(self.chain_id.as_ref()).unwrap();
+		writer.write_all(&self.proof.as_ref()).unwrap();
+	}
+
+	/// Deserialize the private transfer data
+	fn deserialize<R: Read>(mut reader: R) -> Self {
+		let mut shape = [0u8; 2];
+		reader.read_exact(shape.as_mut()).unwrap();
+
+		let mut buf = [0u8; 8];
+		reader.read_exact(buf.as_mut()).unwrap();
+		let asset_id = u64::from_le_bytes(buf);
+
+		reader.read_exact(buf.as_mut()).unwrap();
+		let fee = u64::from_le_bytes(buf);
+
+		let senders = (0..shape[0])
+			.map(|_| SenderData::deserialize(&mut reader))
+			.collect();
+		let receivers = (0..shape[1])
+			.map(|_| ReceiverData::deserialize(&mut reader))
+			.collect();
+
+		let mut chain_id = [0u8; 32];
+		reader.read_exact(chain_id.as_mut()).unwrap();
+
+		let mut proof = [0u8; 192];
+		reader.read_exact(proof.as_mut()).unwrap();
+
+		Self {
+			asset_id,
+			fee,
+			senders,
+			receivers,
+			chain_id,
 			proof,
 		}
 	}
@@ -89,9 +165,11 @@ impl MantaSerDes for ReclaimData {
 		writer
 			.write_all(self.reclaim_amount.to_le_bytes().as_ref())
 			.unwrap();
+		writer.write_all(self.recipient.as_ref()).unwrap();
 		self.sender_1.serialize(&mut writer);
 		self.sender_2.serialize(&mut writer);
 		self.receiver.serialize(&mut writer);
+		writer.write_all(self.chain_id.as_ref()).unwrap();
 		writer.write_all(&self.proof.as_ref()).unwrap();
 	}
 
@@ -106,10 +184,14 @@ impl MantaSerDes for ReclaimData {
 		reader.read_exact(buf.as_mut()).unwrap();
 		data.reclaim_amount = u64::from_le_bytes(buf);
 
+		reader.read_exact(data.recipient.as_mut()).unwrap();
+
 		data.sender_1 = SenderData::deserialize(&mut reader);
 		data.sender_2 = SenderData::deserialize(&mut reader);
 		data.receiver = ReceiverData::deserialize(&mut reader);
 
+		reader.read_exact(data.chain_id.as_mut()).unwrap();
+
 		let mut buf = [0u8; 192];
 		reader.read_exact(&mut buf).unwrap();
 		data.proof.copy_from_slice(buf.as_ref());
@@ -119,14 +201,15 @@ impl MantaSerDes for ReclaimData {
 }
 
 impl MantaSerDes for SenderData {
-	/// Serialize the sender data into an array of 64 bytes.
+	/// Serialize the sender data into an array of `SENDER_DATA_SIZE`, i.e., 96 bytes:
+	/// `k`, the void number and the root.
 	fn serialize<W: Write>(&self, mut writer: W) {
 		writer.write_all(&self.k).unwrap();
 		writer.write_all(&self.void_number).unwrap();
 		writer.write_all(&self.root).unwrap();
 	}
 
-	/// Deserialize an array of 64 bytes into a SenderData.
+	/// Deserialize an array of `SENDER_DATA_SIZE`, i.e., 96 bytes, into a SenderData.
 	fn deserialize<R: Read>(mut reader: R) -> Self {
 		let mut data = SenderData::default();
 		reader.read_exact(&mut data.k).unwrap();
@@ -137,7 +220,9 @@ impl MantaSerDes for SenderData {
 }
 
 impl MantaSerDes for ReceiverData {
-	/// Serialize the receiver data into an array of 80 bytes.
+	/// Serialize the receiver data into an array of `RECEIVER_DATA_SIZE`, i.e., 192 bytes:
+	/// `k`, the commitment, and the encrypted note, i.e., the 32 bytes public key
+	/// of the sender and the 80 bytes note followed by its 16 bytes tag.
 	fn serialize<W: Write>(&self, mut writer: W) {
 		writer.write_all(&self.k).unwrap();
 		writer.write_all(&self.cm).unwrap();
@@ -145,7 +230,7 @@ impl MantaSerDes for ReceiverData {
 		writer.write_all(&self.cipher).unwrap();
 	}
 
-	/// Deserialize an array of 80 bytes into a receiver data.
+	/// Deserialize an array of `RECEIVER_DATA_SIZE`, i.e., 192 bytes, into a receiver data.
 	fn deserialize<R: Read>(mut reader: R) -> Self {
 		let mut data = ReceiverData::default();
 		reader.read_exact(&mut data.k).unwrap();
@@ -155,3 +240,165 @@ impl MantaSerDes for ReceiverData {
 		data
 	}
 }
+
+impl TryDeserialize for MintData {
+	fn try_deserialize<R: Read>(mut reader: R) -> Result<Self, PayloadError> {
+		let mut data = MintData::default();
+
+		let mut buf = [0u8; 8];
+		read_bytes(&mut reader, &mut buf)?;
+		data.asset_id = u64::from_le_bytes(buf);
+
+		read_bytes(&mut reader, &mut buf)?;
+		data.amount = u64::from_le_bytes(buf);
+
+		read_bytes(&mut reader, &mut data.cm)?;
+		read_bytes(&mut reader, &mut data.k)?;
+		read_bytes(&mut reader, &mut data.s)?;
+
+		check_commitment_point(&data.cm)?;
+		check_commitment_point(&data.k)?;
+		Fr::deserialize(data.s.as_ref()).map_err(|_| PayloadError::InvalidPayload)?;
+		Ok(data)
+	}
+}
+
+impl TryDeserialize for PrivateTransferData {
+	fn try_deserialize<R: Read>(mut reader: R) -> Result<Self, PayloadError> {
+		let mut buf = [0u8; 8];
+		read_bytes(&mut reader, &mut buf)?;
+		let asset_id = u64::from_le_bytes(buf);
+
+		read_bytes(&mut reader, &mut buf)?;
+		let fee = u64::from_le_bytes(buf);
+
+		let sender_1 = SenderData::try_deserialize(&mut reader)?;
+		let sender_2 = SenderData::try_deserialize(&mut reader)?;
+		let receiver_1 = ReceiverData::try_deserialize(&mut reader)?;
+		let receiver_2 = ReceiverData::try_deserialize(&mut reader)?;
+
+		let mut chain_id = [0u8; 32];
+		read_bytes(&mut reader, &mut chain_id)?;
+
+		let mut proof = [0u8; 192];
+		read_bytes(&mut reader, &mut proof)?;
+		check_proof(&proof)?;
+
+		Ok(Self {
+			asset_id,
+			fee,
+			sender_1,
+			sender_2,
+			receiver_1,
+			receiver_2,
+			chain_id,
+			proof,
+		})
+	}
+}
+
+impl TryDeserialize for PrivateTransferNData {
+	fn try_deserialize<R: Read>(mut reader: R) -> Result<Self, PayloadError> {
+		let mut shape = [0u8; 2];
+		read_bytes(&mut reader, &mut shape)?;
+		if !CircuitKind::TransferN(shape[0], shape[1]).is_supported() {
+			return Err(PayloadError::InvalidPayload);
+		}
+
+		let mut data = PrivateTransferNData::default();
+
+		let mut buf = [0u8; 8];
+		read_bytes(&mut reader, &mut buf)?;
+		data.asset_id = u64::from_le_bytes(buf);
+
+		read_bytes(&mut reader, &mut buf)?;
+		data.fee = u64::from_le_bytes(buf);
+
+		for _ in 0..shape[0] {
+			data.senders.push(SenderData::try_deserialize(&mut reader)?);
+		}
+		for _ in 0..shape[1] {
+			data.receivers
+				.push(ReceiverData::try_deserialize(&mut reader)?);
+		}
+
+		read_bytes(&mut reader, &mut data.chain_id)?;
+		read_bytes(&mut reader, &mut data.proof)?;
+		check_proof(&data.proof)?;
+
+		Ok(data)
+	}
+}
+
+impl TryDeserialize for ReclaimData {
+	fn try_deserialize<R: Read>(mut reader: R) -> Result<Self, PayloadError> {
+		let mut data = ReclaimData::default();
+
+		let mut buf = [0u8; 8];
+		read_bytes(&mut reader, &mut buf)?;
+		data.asset_id = u64::from_le_bytes(buf);
+
+		read_bytes(&mut reader, &mut buf)?;
+		data.reclaim_amount = u64::from_le_bytes(buf);
+
+		read_bytes(&mut reader, &mut data.recipient)?;
+
+		data.sender_1 = SenderData::try_deserialize(&mut reader)?;
+		data.sender_2 = SenderData::try_deserialize(&mut reader)?;
+		data.receiver = ReceiverData::try_deserialize(&mut reader)?;
+
+		read_bytes(&mut reader, &mut data.chain_id)?;
+		read_bytes(&mut reader, &mut data.proof)?;
+		check_proof(&data.proof)?;
+
+		Ok(data)
+	}
+}
+
+impl TryDeserialize for SenderData {
+	fn try_deserialize<R: Read>(mut reader: R) -> Result<Self, PayloadError> {
+		let mut data = SenderData::default();
+		read_bytes(&mut reader, &mut data.k)?;
+		read_bytes(&mut reader, &mut data.void_number)?;
+		read_bytes(&mut reader, &mut data.root)?;
+
+		check_commitment_point(&data.k)?;
+		HashOutput::deserialize(data.root.as_ref()).map_err(|_| PayloadError::InvalidCurvePoint)?;
+		Ok(data)
+	}
+}
+
+impl TryDeserialize for ReceiverData {
+	fn try_deserialize<R: Read>(mut reader: R) -> Result<Self, PayloadError> {
+		let mut data = ReceiverData::default();
+		read_bytes(&mut reader, &mut data.k)?;
+		read_bytes(&mut reader, &mut data.cm)?;
+		read_bytes(&mut reader, &mut data.sender_pk)?;
+		read_bytes(&mut reader, &mut data.cipher)?;
+
+		check_commitment_point(&data.k)?;
+		check_commitment_point(&data.cm)?;
+		Ok(data)
+	}
+}
+
+// fill `buf` from the reader
+fn read_bytes<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<(), PayloadError> {
+	reader
+		.read_exact(buf)
+		.map_err(|_| PayloadError::InvalidPayload)
+}
+
+// check that `bytes` is a commitment, i.e., a point of the commitment group
+fn check_commitment_point(bytes: &[u8; 32]) -> Result<(), PayloadError> {
+	CommitmentOutput::deserialize(bytes.as_ref())
+		.map(|_| ())
+		.map_err(|_| PayloadError::InvalidCurvePoint)
+}
+
+// check that `bytes` is a Groth16 proof
+fn check_proof(bytes: &[u8; 192]) -> Result<(), PayloadError> {
+	Groth16Proof::deserialize(bytes.as_ref())
+		.map(|_| ())
+		.map_err(|_| PayloadError::InvalidProofEncoding)
+}
@@ -0,0 +1,411 @@
+// Copyright 2019-2021 Manta Network.
+// This file is part of pallet-manta-pay.
+//
+// pallet-manta-pay is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// pallet-manta-pay is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with pallet-manta-pay.  If not, see <http://www.gnu.org/licenses/>.
+
+//! A planner for the payments out of a set of owned coins.
+//!
+//! The `private_transfer` and `reclaim` circuits spend exactly two coins.
+//! `plan_transfer` and `plan_reclaim` select the coins of a payment, and
+//! split it into steps:
+//! * merges, i.e., private transfers of two coins to the wallet itself,
+//!   until two coins are left;
+//! * the payment itself, from the two remaining coins, where the remainder
+//!   goes back to the wallet as change.
+//!
+//! Steps are padded with zero-value coins: a merge creates the merged coin
+//! and a zero-value coin, and a coin that pays on its own is spent along with
+//! the smallest coin of the wallet, e.g., the zero-value coin of a merge.
+//!
+//! A merge spends coins that are created by the steps of the previous rounds,
+//! so the payloads of a round can only be proven once the previous rounds
+//! are on chain, and the wallet has found their coins, see `PlanExecution`.
+//!
+//! Every change coin must go to a fresh address of the wallet: the void
+//! number of a coin only depends on its address.
+
+use crate::*;
+use ark_std::rand::{CryptoRng, RngCore};
+use manta_asset::*;
+use manta_crypto::*;
+
+/// The errors of the planner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlanError {
+	/// There are fewer than two coins of the asset.
+	NotEnoughCoins,
+	/// The coins of the asset do not cover the payment and the fees.
+	InsufficientFunds,
+	/// An address is not an address of the asset of the plan.
+	AssetMismatch,
+	/// There is no such step in the plan.
+	UnknownStep,
+	/// The step spends a coin of a step that has not been proven yet.
+	StepNotReady,
+	/// The step has already been proven.
+	StepProven,
+	/// The senders are not the coins spent by the step.
+	SenderMismatch,
+	/// The number of change addresses is not the number of change coins of
+	/// the step.
+	ChangeMismatch,
+	/// The payload of the step cannot be generated.
+	Payload(PayloadGenError),
+}
+
+/// A coin spent by a step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepInput {
+	/// An owned coin, given by its commitment.
+	Coin([u8; 32]),
+	/// The first change coin of a previous step, given by its index.
+	Change(usize),
+}
+
+/// What a step does with the coins it spends.
+#[derive(Debug, Clone)]
+pub enum StepKind {
+	/// A private transfer of the coins to the wallet itself.
+	Merge,
+	/// A private transfer of `value` to `recipient`.
+	Transfer {
+		recipient: MantaAssetShieldedAddress,
+		value: u64,
+	},
+	/// A reclaim of `value` to the account `recipient`,
+	/// see `encode_recipient`.
+	Reclaim { recipient: [u8; 32], value: u64 },
+}
+
+/// A step of a `Plan`, i.e., a single `private_transfer` or `reclaim`.
+#[derive(Debug, Clone)]
+pub struct Step {
+	/// The steps of a round only spend coins of the previous rounds.
+	pub round: u32,
+	pub inputs: [StepInput; 2],
+	pub kind: StepKind,
+	/// The values of the coins that go back to the wallet.
+	pub change: Vec<u64>,
+}
+
+impl Step {
+	/// The circuit of the step: `CircuitKind::Reclaim` for a `reclaim`,
+	/// and `CircuitKind::Transfer` for a `private_transfer`.
+	pub fn circuit(&self) -> CircuitKind {
+		match self.kind {
+			StepKind::Reclaim { .. } => CircuitKind::Reclaim,
+			_ => CircuitKind::Transfer,
+		}
+	}
+}
+
+/// The steps of a payment, ordered by round.
+#[derive(Debug, Clone)]
+pub struct Plan {
+	pub asset_id: AssetId,
+	/// The fee of every private transfer of the plan.
+	pub fee: u64,
+	pub steps: Vec<Step>,
+}
+
+impl Plan {
+	/// The number of rounds of the plan.
+	pub fn rounds(&self) -> u32 {
+		self.steps.last().map_or(0, |step| step.round + 1)
+	}
+
+	/// The indices of the steps of `round`.
+	pub fn round(&self, round: u32) -> Vec<usize> {
+		self.steps
+			.iter()
+			.enumerate()
+			.filter(|(_, step)| step.round == round)
+			.map(|(index, _)| index)
+			.collect()
+	}
+}
+
+/// Plan a private transfer of `value` to `recipient`, out of `coins`.
+/// The coins of other assets than the asset of `recipient` are ignored.
+pub fn plan_transfer(
+	coins: &[MantaAsset],
+	recipient: MantaAssetShieldedAddress,
+	value: u64,
+	fee: u64,
+) -> Result<Plan, PlanError> {
+	let asset_id = recipient.asset_id;
+	let kind = StepKind::Transfer { recipient, value };
+	plan(coins, asset_id, kind, value, fee, fee)
+}
+
+/// Plan a reclaim of `value` of `asset_id` to the account `recipient`,
+/// out of `coins`; `fee` is paid by the merges.
+pub fn plan_reclaim(
+	coins: &[MantaAsset],
+	asset_id: AssetId,
+	recipient: [u8; 32],
+	value: u64,
+	fee: u64,
+) -> Result<Plan, PlanError> {
+	let kind = StepKind::Reclaim { recipient, value };
+	plan(coins, asset_id, kind, value, fee, 0)
+}
+
+// select the fewest coins that pay for `value`, merge them two by two until
+// two coins are left, and pay with the last step
+fn plan(
+	coins: &[MantaAsset],
+	asset_id: AssetId,
+	kind: StepKind,
+	value: u64,
+	fee: u64,
+	last_fee: u64,
+) -> Result<Plan, PlanError> {
+	let mut coins: Vec<&MantaAsset> = coins
+		.iter()
+		.filter(|coin| coin.asset_id == asset_id)
+		.collect();
+	if coins.len() < 2 {
+		return Err(PlanError::NotEnoughCoins);
+	}
+	coins.sort_by(|a, b| b.priv_info.value.cmp(&a.priv_info.value));
+
+	// a coin that pays on its own is padded with the smallest coin,
+	// otherwise `n` coins take `n - 2` merges
+	let payment = value as u128 + last_fee as u128;
+	let selected: Vec<&MantaAsset> = if coins[0].priv_info.value as u128 >= payment {
+		vec![coins[0], coins[coins.len() - 1]]
+	} else {
+		let mut total = coins[0].priv_info.value as u128;
+		let count = (2..=coins.len())
+			.find(|&n| {
+				total += coins[n - 1].priv_info.value as u128;
+				total >= payment + (n as u128 - 2) * fee as u128
+			})
+			.ok_or(PlanError::InsufficientFunds)?;
+		coins[..count].to_vec()
+	};
+
+	let mut steps = Vec::new();
+	let mut inputs: Vec<(StepInput, u64)> = selected
+		.into_iter()
+		.map(|coin| (StepInput::Coin(coin.commitment), coin.priv_info.value))
+		.collect();
+	let mut round = 0;
+	while inputs.len() > 2 {
+		// pair the largest coins with the smallest ones,
+		// so that every merge covers its fee
+		inputs.sort_by(|a, b| b.1.cmp(&a.1));
+		let count = inputs.len();
+		let mut merged = Vec::with_capacity((count + 1) / 2);
+		for i in 0..count / 2 {
+			let (input_1, value_1) = inputs[i];
+			let (input_2, value_2) = inputs[count - 1 - i];
+			let change = value_1
+				.checked_add(value_2)
+				.and_then(|sum| sum.checked_sub(fee))
+				.ok_or(PlanError::InsufficientFunds)?;
+			merged.push((StepInput::Change(steps.len()), change));
+			steps.push(Step {
+				round,
+				inputs: [input_1, input_2],
+				kind: StepKind::Merge,
+				change: vec![change, 0],
+			});
+		}
+		if count % 2 == 1 {
+			merged.push(inputs[count / 2]);
+		}
+		inputs = merged;
+		round += 1;
+	}
+
+	// the change coin of the payment is kept even when its value is zero,
+	// both circuits have a receiver for it
+	let change = inputs[0]
+		.1
+		.checked_add(inputs[1].1)
+		.and_then(|sum| sum.checked_sub(value))
+		.and_then(|change| change.checked_sub(last_fee))
+		.ok_or(PlanError::InsufficientFunds)?;
+	steps.push(Step {
+		round,
+		inputs: [inputs[0].0, inputs[1].0],
+		kind,
+		change: vec![change],
+	});
+
+	Ok(Plan {
+		asset_id,
+		fee,
+		steps,
+	})
+}
+
+/// The parameters to prove the steps of a plan.
+pub struct ProvingContext<'a> {
+	pub commit_param: CommitmentParam,
+	pub hash_param: HashParam,
+	pub transfer_pk: &'a Groth16Pk,
+	pub reclaim_pk: &'a Groth16Pk,
+	/// The identifier of the chain, see `Module::chain_id`.
+	pub chain_id: [u8; 32],
+}
+
+/// The payload of a step.
+#[derive(Clone)]
+pub enum StepPayload {
+	/// The payload of the `private_transfer` extrinsic.
+	Transfer([u8; PRIVATE_TRANSFER_PAYLOAD_SIZE]),
+	/// The payload of the `reclaim` extrinsic.
+	Reclaim([u8; RECLAIM_PAYLOAD_SIZE]),
+}
+
+/// The execution of a `Plan`, round by round:
+/// 1. the commitments of the coins spent by a step are given by `inputs`,
+///    e.g., to get their `SenderMetaData` with `Wallet::sender_meta_data`;
+/// 2. `prove_step` generates the payload of the step;
+/// 3. once the payloads of a round are on chain, and the wallet has synced
+///    them, the steps of the next round are ready.
+pub struct PlanExecution {
+	plan: Plan,
+	// the commitments of the change coins of the proven steps
+	change: Vec<Option<Vec<[u8; 32]>>>,
+}
+
+impl PlanExecution {
+	/// Start the execution of `plan`, where no step is proven yet.
+	pub fn new(plan: Plan) -> Self {
+		let change = vec![None; plan.steps.len()];
+		Self { plan, change }
+	}
+
+	/// The plan being executed.
+	pub fn plan(&self) -> &Plan {
+		&self.plan
+	}
+
+	/// The commitments of the change coins of the step `index`,
+	/// once it is proven.
+	pub fn change(&self, index: usize) -> Option<&[[u8; 32]]> {
+		self.change.get(index)?.as_deref()
+	}
+
+	/// The commitments of the coins spent by the step `index`.
+	pub fn inputs(&self, index: usize) -> Result<[[u8; 32]; 2], PlanError> {
+		let step = self.plan.steps.get(index).ok_or(PlanError::UnknownStep)?;
+		let commitment = |input: &StepInput| match input {
+			StepInput::Coin(cm) => Ok(*cm),
+			StepInput::Change(step) => self
+				.change(*step)
+				.map(|change| change[0])
+				.ok_or(PlanError::StepNotReady),
+		};
+		Ok([commitment(&step.inputs[0])?, commitment(&step.inputs[1])?])
+	}
+
+	/// Generate the payload of the step `index`, spending `senders`,
+	/// with a fresh address of the wallet for every change coin.
+	pub fn prove_step<R: RngCore + CryptoRng>(
+		&mut self,
+		context: &ProvingContext,
+		index: usize,
+		senders: [SenderMetaData; 2],
+		change_addresses: &[MantaAssetShieldedAddress],
+		rng: &mut R,
+	) -> Result<StepPayload, PlanError> {
+		let inputs = self.inputs(index)?;
+		if self.change[index].is_some() {
+			return Err(PlanError::StepProven);
+		}
+		if senders[0].asset.commitment != inputs[0] || senders[1].asset.commitment != inputs[1] {
+			return Err(PlanError::SenderMismatch);
+		}
+		let step = &self.plan.steps[index];
+		if change_addresses.len() != step.change.len() {
+			return Err(PlanError::ChangeMismatch);
+		}
+		if change_addresses
+			.iter()
+			.any(|address| address.asset_id != self.plan.asset_id)
+		{
+			return Err(PlanError::AssetMismatch);
+		}
+
+		let mut change: Vec<MantaAssetProcessedReceiver> = change_addresses
+			.iter()
+			.zip(step.change.iter())
+			.map(|(address, value)| address.process(value, rng))
+			.collect();
+		let commitments = change.iter().map(|receiver| receiver.commitment).collect();
+
+		let [sender_1, sender_2] = senders;
+		let payload = match &step.kind {
+			StepKind::Merge => {
+				let receiver_2 = change.pop().unwrap();
+				let receiver_1 = change.pop().unwrap();
+				StepPayload::Transfer(generate_private_transfer_payload(
+					context.commit_param.clone(),
+					context.hash_param.clone(),
+					context.transfer_pk,
+					sender_1,
+					sender_2,
+					receiver_1,
+					receiver_2,
+					self.plan.fee,
+					context.chain_id,
+					rng,
+				)?)
+			}
+			StepKind::Transfer { recipient, value } => {
+				let receiver = recipient.process(value, rng);
+				StepPayload::Transfer(generate_private_transfer_payload(
+					context.commit_param.clone(),
+					context.hash_param.clone(),
+					context.transfer_pk,
+					sender_1,
+					sender_2,
+					receiver,
+					change.pop().unwrap(),
+					self.plan.fee,
+					context.chain_id,
+					rng,
+				)?)
+			}
+			StepKind::Reclaim { recipient, value } => {
+				StepPayload::Reclaim(generate_reclaim_payload(
+					context.commit_param.clone(),
+					context.hash_param.clone(),
+					context.reclaim_pk,
+					sender_1,
+					sender_2,
+					change.pop().unwrap(),
+					*value,
+					*recipient,
+					context.chain_id,
+					rng,
+				)?)
+			}
+		};
+
+		self.change[index] = Some(commitments);
+		Ok(payload)
+	}
+}
+
+impl From<PayloadGenError> for PlanError {
+	fn from(error: PayloadGenError) -> Self {
+		PlanError::Payload(error)
+	}
+}
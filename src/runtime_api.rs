@@ -0,0 +1,56 @@
+// Copyright 2019-2021 Manta Network.
+// This file is part of pallet-manta-pay.
+//
+// pallet-manta-pay is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// pallet-manta-pay is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with pallet-manta-pay.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Runtime API of manta-pay, for the light wallets to sync the notes
+//! and to find out which of their coins are spent.
+//!
+//! A runtime implements it with `Module::note_count`, `Module::notes`
+//! and `Module::is_void_number_spent`:
+//! ```ignore
+//! impl pallet_manta_pay::runtime_api::MantaPayApi<Block> for Runtime {
+//!     fn note_count() -> u64 {
+//!         Assets::note_count()
+//!     }
+//!
+//!     fn notes(from: u64, limit: u32) -> Vec<NoteRecord> {
+//!         Assets::notes(from, limit)
+//!     }
+//!
+//!     fn is_void_number_spent(vn: [u8; 32]) -> bool {
+//!         Assets::is_void_number_spent(&vn)
+//!     }
+//! }
+//! ```
+
+use crate::NoteRecord;
+use sp_std::prelude::*;
+
+sp_api::decl_runtime_apis! {
+	/// The notes and the void numbers of the private ledger.
+	pub trait MantaPayApi {
+		/// The number of notes, i.e., the index of the next note.
+		fn note_count() -> u64;
+
+		/// The notes of index `from` onwards, at most `limit` of them and at
+		/// most `MAX_NOTES_PAGE_SIZE`; a wallet resumes its sync from the
+		/// index following the last note it got.
+		fn notes(from: u64, limit: u32) -> Vec<NoteRecord>;
+
+		/// Whether the void number `vn` has been revealed,
+		/// i.e., whether the coin it belongs to has been spent.
+		fn is_void_number_spent(vn: [u8; 32]) -> bool;
+	}
+}
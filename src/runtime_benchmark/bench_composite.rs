@@ -40,7 +40,7 @@ frame_support::construct_runtime!(
 		UncheckedExtrinsic = UncheckedExtrinsic,
 	{
 		System: frame_system::{Module, Call, Config, Storage, Event<T>},
-		MantaModule: pallet_manta_pay::{Module, Call, Storage, Event<T>},
+		MantaModule: pallet_manta_pay::{Module, Call, Storage, Event<T>, ValidateUnsigned},
 	}
 );
 type BlockNumber = u64;
@@ -48,6 +48,10 @@ type BlockNumber = u64;
 parameter_types! {
 	pub const BlockHashCount: BlockNumber = 250;
 	pub const SS58Prefix: u8 = 42;
+	pub const RootHistorySize: u32 = 8;
+	pub const ShardCount: u32 = 256;
+	pub const MaxBatchSize: u32 = 4;
+	pub const FeeCollector: u64 = 100;
 }
 
 impl frame_system::Config for Test {
@@ -78,6 +82,10 @@ impl frame_system::Config for Test {
 impl Config for Test {
 	type Event = ();
 	type WeightInfo = ();
+	type RootHistorySize = RootHistorySize;
+	type ShardCount = ShardCount;
+	type MaxBatchSize = MaxBatchSize;
+	type FeeCollector = FeeCollector;
 }
 
 pub struct ExtBuilder {}
@@ -14,18 +14,35 @@
 // You should have received a copy of the GNU General Public License
 // along with pallet-manta-pay.  If not, see <http://www.gnu.org/licenses/>.
 
-use crate::*;
+use crate::{zkp::merkle_membership_circuit_proof, *};
 use ark_bls12_381::Bls12_381;
 use ark_ed_on_bls12_381::Fq;
-use ark_ff::ToConstraintField;
+use ark_ff::{ToConstraintField, Zero};
 use ark_groth16::{create_random_proof, generate_random_parameters, verify_proof};
+use ark_r1cs_std::alloc::AllocVar;
 use ark_relations::r1cs::{ConstraintSynthesizer, ConstraintSystem};
-use ark_serialize::CanonicalDeserialize;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
 use ark_std::rand::{RngCore, SeedableRng};
 use manta_asset::*;
 use manta_crypto::*;
 use rand_chacha::ChaCha20Rng;
 
+/// the compiled verification keys are the ones of the default proving keys:
+/// they are regenerated with `write_zkp_keys` whenever a circuit changes
+#[test]
+fn test_compiled_verification_keys() {
+	for (pk, compiled) in [
+		(default_transfer_zkp_key(), TRANSFER_PK),
+		(default_reclaim_zkp_key(), RECLAIM_PK),
+	]
+	.iter()
+	{
+		let mut vk_bytes = Vec::new();
+		pk.vk.serialize_uncompressed(&mut vk_bytes).unwrap();
+		assert_eq!(vk_bytes, compiled.data.to_vec());
+	}
+}
+
 /// this is a local test on zero knowledge proof generation and verifications
 #[test]
 fn test_transfer_zkp_local() {
@@ -74,6 +91,10 @@ fn test_transfer_zkp_local() {
 		commit_param: commit_param.clone(),
 		hash_param: hash_param.clone(),
 
+		asset_id: TEST_ASSET,
+		fee: 0,
+		binding: [0u8; 32],
+
 		sender_1: sender_1,
 		sender_2: sender_2,
 
@@ -235,6 +256,10 @@ fn test_transfer_helper(
 		commit_param: commit_param.clone(),
 		hash_param,
 
+		asset_id: TEST_ASSET,
+		fee: 0,
+		binding: [0u8; 32],
+
 		sender_1: sender_1.clone(),
 		sender_2: sender_2.clone(),
 
@@ -275,12 +300,329 @@ fn test_transfer_helper(
 		sn_2.as_ref(),
 		mr.as_ref(),
 		mr.as_ref(),
+		// no fee, the asset id and a zero binding hash
+		[Fq::zero(), Fq::from(TEST_ASSET as u64), Fq::zero()].as_ref(),
 	]
 	.concat();
 	let pvk = Groth16Pvk::from(pk.vk.clone());
 	assert!(verify_proof(&pvk, &proof, &inputs[..]).unwrap());
 }
 
+/// the transfer circuit sums up the values the coins commit to:
+/// a witness with values that do not open the commitments,
+/// or with values that only balance modulo 2^64, is rejected
+#[test]
+fn test_transfer_circuit_rejects_crafted_values() {
+	let hash_param = HashParam::deserialize(HASH_PARAM.data);
+	let commit_param = CommitmentParam::deserialize(COMMIT_PARAM.data);
+
+	let mut rng = ChaCha20Rng::from_seed([3u8; 32]);
+	let (sender_1, sender_2) = sample_senders(&commit_param, &hash_param, &mut rng);
+	let receivers = sample_receivers(&commit_param, &[150, 250, u64::MAX, 401], &mut rng);
+
+	let circuit = TransferCircuit {
+		commit_param,
+		hash_param,
+
+		asset_id: TEST_ASSET,
+		fee: 0,
+		binding: [0u8; 32],
+
+		sender_1,
+		sender_2,
+
+		receiver_1: receivers[0].clone(),
+		receiver_2: receivers[1].clone(),
+	};
+	assert!(is_satisfied(circuit.clone()));
+
+	// the receivers claim balanced values they do not commit to
+	let mut crafted = circuit.clone();
+	crafted.receiver_1.value = 100;
+	crafted.receiver_2.value = 300;
+	assert!(!is_satisfied(crafted));
+
+	// 100 + 300 = u64::MAX + 401 modulo 2^64
+	let mut crafted = circuit.clone();
+	crafted.receiver_1 = receivers[2].clone();
+	crafted.receiver_2 = receivers[3].clone();
+	assert!(!is_satisfied(crafted));
+
+	// 100 + 300 = 150 + 401 + (u64::MAX - 150) modulo 2^64
+	let mut crafted = circuit;
+	crafted.receiver_2 = receivers[3].clone();
+	crafted.fee = u64::MAX - 150;
+	assert!(!is_satisfied(crafted));
+}
+
+/// the transfer circuit checks the asset ids the coins commit to:
+/// a coin of another asset, even if it claims the public asset id, is rejected,
+/// and the public asset id is the one of the coins when a fee is paid
+#[test]
+fn test_transfer_circuit_rejects_crafted_asset_ids() {
+	let hash_param = HashParam::deserialize(HASH_PARAM.data);
+	let commit_param = CommitmentParam::deserialize(COMMIT_PARAM.data);
+
+	let mut rng = ChaCha20Rng::from_seed([3u8; 32]);
+	let (sender_1, sender_2) = sample_senders(&commit_param, &hash_param, &mut rng);
+	let receivers = sample_receivers(&commit_param, &[150, 250], &mut rng);
+	let mut sk = [0u8; 32];
+	rng.fill_bytes(&mut sk);
+	let other_receiver_full =
+		MantaAssetFullReceiver::sample(&commit_param, &sk, &(TEST_ASSET + 1), &(), &mut rng);
+	let other_receiver = other_receiver_full.prepared.process(&250, &mut rng);
+	let paid_receiver = sample_receivers(&commit_param, &[240], &mut rng).remove(0);
+
+	let circuit = TransferCircuit {
+		commit_param,
+		hash_param,
+
+		asset_id: TEST_ASSET,
+		fee: 0,
+		binding: [0u8; 32],
+
+		sender_1,
+		sender_2,
+
+		receiver_1: receivers[0].clone(),
+		receiver_2: receivers[1].clone(),
+	};
+	assert!(is_satisfied(circuit.clone()));
+
+	// without a fee, the public asset id is not bound to the coins
+	let mut unbound = circuit.clone();
+	unbound.asset_id = 0;
+	assert!(is_satisfied(unbound));
+
+	// with a fee, the public asset id is the one of the coins
+	let mut paid = circuit.clone();
+	paid.receiver_2 = paid_receiver;
+	paid.fee = 10;
+	assert!(is_satisfied(paid.clone()));
+	paid.asset_id = TEST_ASSET + 1;
+	assert!(!is_satisfied(paid));
+
+	// a receiver of another asset
+	let mut crafted = circuit.clone();
+	crafted.receiver_2 = other_receiver.clone();
+	assert!(!is_satisfied(crafted));
+
+	// a receiver of another asset that claims the public asset id
+	let mut crafted = circuit;
+	crafted.receiver_2 = other_receiver;
+	crafted.receiver_2.prepared_data.asset_id = TEST_ASSET;
+	assert!(!is_satisfied(crafted));
+}
+
+/// the reclaim circuit sums up the values the coins commit to:
+/// a witness with values that do not open the commitments,
+/// or with values that only balance modulo 2^64, is rejected
+#[test]
+fn test_reclaim_circuit_rejects_crafted_values() {
+	let hash_param = HashParam::deserialize(HASH_PARAM.data);
+	let commit_param = CommitmentParam::deserialize(COMMIT_PARAM.data);
+
+	let mut rng = ChaCha20Rng::from_seed([3u8; 32]);
+	let (sender_1, sender_2) = sample_senders(&commit_param, &hash_param, &mut rng);
+	let receivers = sample_receivers(&commit_param, &[150, u64::MAX], &mut rng);
+
+	let circuit = ReclaimCircuit {
+		commit_param,
+		hash_param,
+
+		sender_1,
+		sender_2,
+
+		receiver: receivers[0].clone(),
+
+		asset_id: TEST_ASSET,
+		reclaim_value: 250,
+
+		recipient: [1u8; 32],
+		binding: [0u8; 32],
+	};
+	assert!(is_satisfied(circuit.clone()));
+
+	// the receiver claims a balanced value it does not commit to
+	let mut crafted = circuit.clone();
+	crafted.receiver.value = 50;
+	crafted.reclaim_value = 350;
+	assert!(!is_satisfied(crafted));
+
+	// 100 + 300 = u64::MAX + 401 modulo 2^64
+	let mut crafted = circuit;
+	crafted.receiver = receivers[1].clone();
+	crafted.reclaim_value = 401;
+	assert!(!is_satisfied(crafted));
+}
+
+// two senders of 100 and 300 units, on a list of their two commitments
+fn sample_senders(
+	commit_param: &CommitmentParam,
+	hash_param: &HashParam,
+	rng: &mut ChaCha20Rng,
+) -> (SenderMetaData, SenderMetaData) {
+	let mut sk = [0u8; 32];
+	rng.fill_bytes(&mut sk);
+	let sender_1 = MantaAsset::sample(commit_param, &sk, &TEST_ASSET, &100, rng);
+	rng.fill_bytes(&mut sk);
+	let sender_2 = MantaAsset::sample(commit_param, &sk, &TEST_ASSET, &300, rng);
+
+	let list = [sender_1.commitment, sender_2.commitment];
+	(
+		SenderMetaData::build(hash_param.clone(), sender_1, &list),
+		SenderMetaData::build(hash_param.clone(), sender_2, &list),
+	)
+}
+
+// a receiver for each of the `values`
+fn sample_receivers(
+	commit_param: &CommitmentParam,
+	values: &[u64],
+	rng: &mut ChaCha20Rng,
+) -> Vec<MantaAssetProcessedReceiver> {
+	let mut sk = [0u8; 32];
+	values
+		.iter()
+		.map(|value| {
+			rng.fill_bytes(&mut sk);
+			let receiver_full =
+				MantaAssetFullReceiver::sample(commit_param, &sk, &TEST_ASSET, &(), rng);
+			receiver_full.prepared.process(value, rng)
+		})
+		.collect()
+}
+
+fn is_satisfied<C: ConstraintSynthesizer<Fq>>(circuit: C) -> bool {
+	let cs = ConstraintSystem::<Fq>::new_ref();
+	circuit.generate_constraints(cs.clone()).unwrap();
+	cs.is_satisfied().unwrap()
+}
+
+/// the merkle membership gadget accepts the witnesses kept by a `WitnessTracker`,
+/// at any position of the shard, and only for their leaf and their root
+#[test]
+fn test_merkle_membership_gadget_with_tracked_witnesses() {
+	let hash_param = HashParam::deserialize(HASH_PARAM.data);
+	let mut rng = ChaCha20Rng::from_seed([3u8; 32]);
+
+	let mut tracker = WitnessTracker::default();
+	let mut leaves = Vec::new();
+	let mut old_root = [0u8; 32];
+	for i in 0..11 {
+		let mut cm = [0u8; 32];
+		rng.fill_bytes(&mut cm);
+		let root = tracker.append(&hash_param, &cm, true);
+		leaves.push(cm);
+		if i == 5 {
+			old_root = root;
+		}
+	}
+	let old_root = HashOutput::deserialize(old_root.as_ref()).unwrap();
+
+	for (leaf_index, leaf) in leaves.iter().enumerate() {
+		let witness = tracker.witness(leaf).unwrap();
+		let root = witness.root(&hash_param);
+		assert!(is_member(&hash_param, leaf, witness, root));
+
+		// the witness of another leaf, or under an older root
+		let other = &leaves[(leaf_index + 1) % leaves.len()];
+		assert!(!is_member(&hash_param, other, witness, root));
+		assert!(!is_member(&hash_param, leaf, witness, old_root));
+	}
+}
+
+// whether the merkle membership gadget is satisfied by `witness` for `leaf` and `root`
+fn is_member(
+	hash_param: &HashParam,
+	leaf: &[u8; 32],
+	witness: &MerkleWitness,
+	root: HashOutput,
+) -> bool {
+	let cs = ConstraintSystem::<Fq>::new_ref();
+	let param_var =
+		HashParamVar::new_constant(ark_relations::ns!(cs, "parameter"), hash_param.clone())
+			.unwrap();
+	merkle_membership_circuit_proof(leaf, witness, param_var, root, cs.clone()).unwrap();
+	cs.is_satisfied().unwrap()
+}
+
+/// the multi transfer circuit is satisfied for any shape,
+/// as long as the values are balanced
+#[test]
+fn test_multi_transfer_circuit_shapes() {
+	let hash_param = HashParam::deserialize(HASH_PARAM.data);
+	let commit_param = CommitmentParam::deserialize(COMMIT_PARAM.data);
+
+	let mut rng = ChaCha20Rng::from_seed([3u8; 32]);
+	let mut sk = [0u8; 32];
+
+	for (sender_values, receiver_values) in [
+		(vec![100u64], vec![100u64]),
+		(vec![100], vec![40, 60]),
+		(vec![100, 400, 10, 1], vec![500, 11]),
+	]
+	.iter()
+	{
+		let mut senders = Vec::new();
+		for value in sender_values.iter() {
+			rng.fill_bytes(&mut sk);
+			senders.push(MantaAsset::sample(
+				&commit_param,
+				&sk,
+				&TEST_ASSET,
+				value,
+				&mut rng,
+			));
+		}
+		let list: Vec<[u8; 32]> = senders.iter().map(|sender| sender.commitment).collect();
+		let senders: Vec<SenderMetaData> = senders
+			.into_iter()
+			.map(|sender| SenderMetaData::build(hash_param.clone(), sender, &list))
+			.collect();
+
+		let mut receivers = Vec::new();
+		for value in receiver_values.iter() {
+			rng.fill_bytes(&mut sk);
+			let receiver_full =
+				MantaAssetFullReceiver::sample(&commit_param, &sk, &TEST_ASSET, &(), &mut rng);
+			receivers.push(receiver_full.prepared.process(value, &mut rng));
+		}
+
+		let circuit = MultiTransferCircuit {
+			commit_param: commit_param.clone(),
+			hash_param: hash_param.clone(),
+			asset_id: TEST_ASSET,
+			fee: 0,
+			binding: [0u8; 32],
+			senders: senders.clone(),
+			receivers: receivers.clone(),
+		};
+		let sanity_cs = ConstraintSystem::<Fq>::new_ref();
+		circuit.generate_constraints(sanity_cs.clone()).unwrap();
+		assert!(sanity_cs.is_satisfied().unwrap());
+
+		// the receivers get one more unit than the senders spend
+		let value = receivers[0].value + 1;
+		rng.fill_bytes(&mut sk);
+		let receiver_full =
+			MantaAssetFullReceiver::sample(&commit_param, &sk, &TEST_ASSET, &(), &mut rng);
+		receivers[0] = receiver_full.prepared.process(&value, &mut rng);
+		let circuit = MultiTransferCircuit {
+			commit_param: commit_param.clone(),
+			hash_param: hash_param.clone(),
+			asset_id: TEST_ASSET,
+			fee: 0,
+			binding: [0u8; 32],
+			senders,
+			receivers,
+		};
+		let sanity_cs = ConstraintSystem::<Fq>::new_ref();
+		circuit.generate_constraints(sanity_cs.clone()).unwrap();
+		assert!(!sanity_cs.is_satisfied().unwrap());
+	}
+}
+
 /// this is a local test on zero knowledge proof generation and verifications
 #[test]
 fn test_reclaim_zkp_local() {
@@ -325,6 +667,8 @@ fn test_reclaim_zkp_local() {
 		receiver: receiver,
 		asset_id: sender_1.asset.asset_id,
 		reclaim_value: 260,
+		recipient: [1u8; 32],
+		binding: [0u8; 32],
 	};
 
 	let sanity_cs = ConstraintSystem::<Fq>::new_ref();
@@ -469,6 +813,8 @@ fn test_reclaim_helper(
 		receiver: receiver.clone(),
 		asset_id: sender_1.asset.asset_id,
 		reclaim_value,
+		recipient: [1u8; 32],
+		binding: [0u8; 32],
 	};
 
 	let sanity_cs = ConstraintSystem::<Fq>::new_ref();
@@ -508,6 +854,24 @@ fn test_reclaim_helper(
 		&[asset_id_fq],
 	]
 	.concat();
+	let recipient: Vec<Fq> =
+		ToConstraintField::<Fq>::to_field_elements([1u8; 32].as_ref()).unwrap();
+	let other_recipient: Vec<Fq> =
+		ToConstraintField::<Fq>::to_field_elements([2u8; 32].as_ref()).unwrap();
+	// the inputs end with the recipient and a zero binding hash
 	let pvk = Groth16Pvk::from(pk.vk.clone());
-	assert!(verify_proof(&pvk, &proof, &inputs[..]).unwrap());
+	assert!(verify_proof(
+		&pvk,
+		&proof,
+		&[inputs.as_ref(), recipient.as_ref(), &[Fq::zero()]].concat()
+	)
+	.unwrap());
+
+	// the proof is bound to its recipient
+	assert!(!verify_proof(
+		&pvk,
+		&proof,
+		&[inputs.as_ref(), other_recipient.as_ref(), &[Fq::zero()]].concat()
+	)
+	.unwrap());
 }
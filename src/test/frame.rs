@@ -18,16 +18,28 @@ use crate as pallet_manta_pay;
 use crate::*;
 use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
 use ark_std::rand::{RngCore, SeedableRng};
-use frame_support::{assert_noop, assert_ok, parameter_types};
+use frame_support::{
+	assert_noop, assert_ok,
+	codec::Encode,
+	parameter_types,
+	storage::{migration::put_storage_value, unhashed},
+	traits::{Get, OnInitialize},
+	unsigned::ValidateUnsigned,
+	weights::GetDispatchInfo,
+	Blake2_128Concat, StorageHasher,
+};
 use manta_asset::*;
 use manta_crypto::*;
 use rand_chacha::ChaCha20Rng;
 use sp_core::H256;
+use sp_io::hashing::twox_128;
 use sp_runtime::{
 	testing::Header,
 	traits::{BlakeTwo256, IdentityLookup},
+	transaction_validity::{InvalidTransaction, TransactionSource},
+	DispatchError,
 };
-use std::{boxed::Box, fs::File, io::prelude::*, string::String};
+use std::boxed::Box;
 
 type UncheckedExtrinsic = frame_system::mocking::MockUncheckedExtrinsic<Test>;
 type Block = frame_system::mocking::MockBlock<Test>;
@@ -40,7 +52,7 @@ frame_support::construct_runtime!(
 		UncheckedExtrinsic = UncheckedExtrinsic,
 	{
 		System: frame_system::{Module, Call, Config, Storage, Event<T>},
-		MantaModule: pallet_manta_pay::{Module, Call, Storage, Event<T>},
+		MantaModule: pallet_manta_pay::{Module, Call, Config<T>, Storage, Event<T>, ValidateUnsigned},
 	}
 );
 type BlockNumber = u64;
@@ -48,6 +60,10 @@ type BlockNumber = u64;
 parameter_types! {
 	pub const BlockHashCount: BlockNumber = 250;
 	pub const SS58Prefix: u8 = 42;
+	pub const RootHistorySize: u32 = 8;
+	pub storage ShardCount: u32 = 256;
+	pub const MaxBatchSize: u32 = 4;
+	pub const FeeCollector: u64 = 100;
 }
 
 impl frame_system::Config for Test {
@@ -78,6 +94,10 @@ impl frame_system::Config for Test {
 impl Config for Test {
 	type Event = ();
 	type WeightInfo = ();
+	type RootHistorySize = RootHistorySize;
+	type ShardCount = ShardCount;
+	type MaxBatchSize = MaxBatchSize;
+	type FeeCollector = FeeCollector;
 }
 type Assets = Module<Test>;
 
@@ -93,6 +113,7 @@ fn new_test_ext() -> sp_io::TestExternalities {
 #[test]
 fn test_constants_should_work() {
 	new_test_ext().execute_with(|| {
+		assert_ok!(Assets::initialize_ledger(Origin::root()));
 		assert_ok!(Assets::init_asset(Origin::signed(1), TEST_ASSET, 100));
 		assert_eq!(Assets::balance(1, TEST_ASSET), 100);
 		let hash_param = HashParam::deserialize(HASH_PARAM.data);
@@ -109,6 +130,7 @@ fn test_constants_should_work() {
 #[test]
 fn test_mint_should_work() {
 	new_test_ext().execute_with(|| {
+		assert_ok!(Assets::initialize_ledger(Origin::root()));
 		assert_ok!(Assets::init_asset(Origin::signed(1), TEST_ASSET, 1000));
 		assert_eq!(Assets::balance(1, TEST_ASSET), 1000);
 		assert_eq!(PoolBalance::get(TEST_ASSET), 0);
@@ -119,15 +141,24 @@ fn test_mint_should_work() {
 		rng.fill_bytes(&mut sk);
 		let asset = MantaAsset::sample(&commit_param, &sk, &TEST_ASSET, &10, &mut rng);
 
-		let payload = generate_mint_payload(&asset);
+		let payload = generate_mint_payload(&asset).unwrap();
 		assert_ok!(Assets::mint_private_asset(Origin::signed(1), payload));
 
 		assert_eq!(TotalSupply::get(TEST_ASSET), 1000);
 		assert_eq!(PoolBalance::get(TEST_ASSET), 10);
-		let coin_shards = CoinShards::get();
-		assert!(coin_shards.exist(&asset.commitment));
-		let sn_list = VNList::get();
-		assert_eq!(sn_list.len(), 0);
+		assert!(<Assets as LedgerSharding>::exist(&asset.commitment));
+		assert_eq!(VoidNumbers::iter().count(), 0);
+
+		// the minted coin is listed in the notes, with an empty note
+		assert_eq!(Assets::note_count(), 1);
+		let record = Assets::note(0).unwrap();
+		assert_eq!(record.cm, asset.commitment);
+		assert_eq!(
+			record.shard_index,
+			Assets::shard_index_of(&asset.commitment)
+		);
+		assert_eq!(record.leaf_index, 0);
+		assert_eq!(record.encrypted_note, EncryptedNote::default());
 	});
 }
 
@@ -246,6 +277,1339 @@ fn cannot_init_twice() {
 	});
 }
 
+#[test]
+fn cannot_initialize_ledger_twice() {
+	new_test_ext().execute_with(|| {
+		assert_noop!(
+			Assets::initialize_ledger(Origin::signed(1)),
+			DispatchError::BadOrigin
+		);
+		assert_ok!(Assets::initialize_ledger(Origin::root()));
+		assert_noop!(
+			Assets::initialize_ledger(Origin::root()),
+			Error::<Test>::LedgerAlreadyInitialized
+		);
+	});
+}
+
+#[test]
+fn init_asset_keeps_the_private_ledger() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Assets::initialize_ledger(Origin::root()));
+		assert_ok!(Assets::init_asset(Origin::signed(1), TEST_ASSET, 1000));
+
+		let commit_param = CommitmentParam::deserialize(COMMIT_PARAM.data);
+		let mut rng = ChaCha20Rng::from_seed([3u8; 32]);
+		let mut sk = [0u8; 32];
+		rng.fill_bytes(&mut sk);
+		let asset = MantaAsset::sample(&commit_param, &sk, &TEST_ASSET, &10, &mut rng);
+		assert_ok!(Assets::mint_private_asset(
+			Origin::signed(1),
+			generate_mint_payload(&asset).unwrap()
+		));
+		let shard_meta = ShardMetas::get(Assets::shard_index_of(&asset.commitment));
+
+		// issuing another asset leaves the ledger untouched
+		let other_asset = TEST_ASSET + 1;
+		assert_ok!(Assets::init_asset(Origin::signed(2), other_asset, 1000));
+		assert!(<Assets as LedgerSharding>::exist(&asset.commitment));
+		assert!(<Assets as LedgerSharding>::check_root(&shard_meta.root));
+		assert_eq!(
+			ShardMetas::get(Assets::shard_index_of(&asset.commitment)),
+			shard_meta
+		);
+		assert_eq!(PoolBalance::get(TEST_ASSET), 10);
+		assert_eq!(HashParamChecksum::get(), HASH_PARAM.get_checksum());
+
+		// and both assets can be minted
+		rng.fill_bytes(&mut sk);
+		let asset = MantaAsset::sample(&commit_param, &sk, &other_asset, &20, &mut rng);
+		assert_ok!(Assets::mint_private_asset(
+			Origin::signed(2),
+			generate_mint_payload(&asset).unwrap()
+		));
+		assert_eq!(PoolBalance::get(other_asset), 20);
+		assert_eq!(PoolBalance::get(TEST_ASSET), 10);
+	});
+}
+
+#[test]
+fn test_set_verification_key() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Assets::initialize_ledger(Origin::root()));
+		let vk = RECLAIM_PK.data.to_vec();
+
+		assert_noop!(
+			Assets::set_verification_key(Origin::signed(1), CircuitKind::Reclaim, 1, vk.clone()),
+			DispatchError::BadOrigin
+		);
+		assert_noop!(
+			Assets::set_verification_key(Origin::root(), CircuitKind::Reclaim, 0, vk.clone()),
+			Error::<Test>::StaleVerificationKey
+		);
+		assert_noop!(
+			Assets::set_verification_key(Origin::root(), CircuitKind::Reclaim, 1, vec![0u8; 8]),
+			Error::<Test>::InvalidVerificationKey
+		);
+
+		assert_ok!(Assets::set_verification_key(
+			Origin::root(),
+			CircuitKind::Reclaim,
+			3,
+			vk.clone()
+		));
+		assert_eq!(
+			Assets::active_verification_key_version(CircuitKind::Reclaim),
+			Some(3)
+		);
+		assert_eq!(
+			Assets::active_prepared_verification_key(CircuitKind::Reclaim),
+			prepare_verification_key(&vk)
+		);
+		assert_eq!(
+			Assets::active_verification_key(CircuitKind::Reclaim),
+			Some(vk)
+		);
+		// the previous key is kept in the storage
+		assert_eq!(
+			Assets::verification_key(CircuitKind::Reclaim, 0),
+			Some(RECLAIM_PK.data.to_vec())
+		);
+		// the other circuit is not affected
+		assert_eq!(
+			Assets::active_verification_key_version(CircuitKind::Transfer),
+			Some(0)
+		);
+	});
+}
+
+#[test]
+fn test_migration_stores_verification_keys() {
+	new_test_ext().execute_with(|| {
+		StorageVersion::put(Releases::V7_0_0);
+		LedgerShardCount::put(ShardCount::get());
+
+		let transfer_key = [twox_128(b"Assets"), twox_128(b"TransferZKPKeyChecksum")].concat();
+		let reclaim_key = [twox_128(b"Assets"), twox_128(b"ReclaimZKPKeyChecksum")].concat();
+		unhashed::put(&transfer_key, &TRANSFER_PK.get_checksum());
+		unhashed::put(&reclaim_key, &RECLAIM_PK.get_checksum());
+
+		crate::migration::migrate::<Test>();
+		assert_eq!(StorageVersion::get(), Releases::V9_0_0);
+		assert!(unhashed::get_raw(&transfer_key).is_none());
+		assert!(unhashed::get_raw(&reclaim_key).is_none());
+		assert_eq!(
+			Assets::active_verification_key(CircuitKind::Transfer),
+			Some(TRANSFER_PK.data.to_vec())
+		);
+		assert_eq!(
+			Assets::active_verification_key(CircuitKind::Reclaim),
+			Some(RECLAIM_PK.data.to_vec())
+		);
+		assert_eq!(
+			Assets::active_prepared_verification_key(CircuitKind::Transfer),
+			prepare_verification_key(TRANSFER_PK.data)
+		);
+	});
+}
+
+#[test]
+fn test_random_payloads_should_not_work() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Assets::initialize_ledger(Origin::root()));
+		assert_ok!(Assets::init_asset(Origin::signed(1), TEST_ASSET, 1000));
+		let mut rng = ChaCha20Rng::from_seed([3u8; 32]);
+
+		for _ in 0..100 {
+			let mut payload = [0u8; MINT_PAYLOAD_SIZE];
+			rng.fill_bytes(&mut payload);
+			// make sure that the payload is not rejected for its asset id
+			payload[..8].copy_from_slice(&TEST_ASSET.to_le_bytes());
+			assert!(Assets::mint_private_asset(Origin::signed(1), payload).is_err());
+
+			let mut payload = [0u8; PRIVATE_TRANSFER_PAYLOAD_SIZE];
+			rng.fill_bytes(&mut payload);
+			assert!(Assets::private_transfer(Origin::signed(1), payload).is_err());
+
+			let mut payload = [0u8; PRIVATE_TRANSFER_PAYLOAD_SIZE];
+			rng.fill_bytes(&mut payload);
+			let call = pallet_manta_pay::Call::<Test>::private_transfer_unsigned(payload);
+			assert!(Assets::validate_unsigned(TransactionSource::External, &call).is_err());
+			assert!(Assets::private_transfer_unsigned(Origin::none(), payload).is_err());
+
+			let mut payloads =
+				vec![[0u8; PRIVATE_TRANSFER_PAYLOAD_SIZE]; 1 + rng.next_u32() as usize % 3];
+			for payload in payloads.iter_mut() {
+				rng.fill_bytes(payload);
+			}
+			assert!(Assets::private_transfer_batch(Origin::signed(1), payloads).is_err());
+
+			// a payload of the size of its shape, so that it is not rejected for its size
+			let senders = 1 + rng.next_u32() as usize % MAX_TRANSFER_ARITY as usize;
+			let receivers = 1 + rng.next_u32() as usize % MAX_TRANSFER_ARITY as usize;
+			let size = 2 + 16 + senders * SENDER_DATA_SIZE + receivers * RECEIVER_DATA_SIZE + 224;
+			let mut payload = vec![0u8; size];
+			rng.fill_bytes(&mut payload);
+			payload[0] = senders as u8;
+			payload[1] = receivers as u8;
+			assert!(Assets::private_transfer_n(Origin::signed(1), payload).is_err());
+
+			let mut payload = [0u8; RECLAIM_PAYLOAD_SIZE];
+			rng.fill_bytes(&mut payload);
+			payload[..8].copy_from_slice(&TEST_ASSET.to_le_bytes());
+			assert!(Assets::reclaim(Origin::signed(1), payload).is_err());
+
+			let mut key = vec![0u8; TRANSFER_PK.data.len()];
+			rng.fill_bytes(&mut key);
+			assert_noop!(
+				Assets::set_verification_key(Origin::root(), CircuitKind::Transfer, 1, key),
+				Error::<Test>::InvalidVerificationKey
+			);
+		}
+
+		// a key whose points are not on the curve is rejected
+		let mut key = TRANSFER_PK.data.to_vec();
+		key[0] ^= 1;
+		assert_noop!(
+			Assets::set_verification_key(Origin::root(), CircuitKind::Transfer, 1, key),
+			Error::<Test>::InvalidVerificationKey
+		);
+		let key = TRANSFER_PK.data[..TRANSFER_PK.data.len() - 1].to_vec();
+		assert_noop!(
+			Assets::set_verification_key(Origin::root(), CircuitKind::Transfer, 1, key),
+			Error::<Test>::InvalidVerificationKey
+		);
+
+		// the ledger is untouched
+		assert_eq!(Assets::balance(1, TEST_ASSET), 1000);
+		assert_eq!(PoolBalance::get(TEST_ASSET), 0);
+		assert_eq!(CoinCommitments::iter().count(), 0);
+		assert_eq!(VoidNumbers::iter().count(), 0);
+		assert_eq!(Assets::note_count(), 0);
+		assert_eq!(
+			Assets::active_verification_key(CircuitKind::Transfer),
+			Some(TRANSFER_PK.data.to_vec())
+		);
+	});
+}
+
+#[test]
+fn test_migration_splits_coin_shards() {
+	new_test_ext().execute_with(|| {
+		let hash_param = HashParam::deserialize(HASH_PARAM.data);
+		let mut rng = ChaCha20Rng::from_seed([3u8; 32]);
+
+		// write a ledger in the `V1_0_0` format,
+		// i.e., every shard is a list of commitments and a root
+		let mut lists = vec![Vec::<[u8; 32]>::new(); 256];
+		for _ in 0..20 {
+			let mut cm = [0u8; 32];
+			rng.fill_bytes(&mut cm);
+			lists[cm[0] as usize].push(cm);
+		}
+		let mut encoded = Vec::new();
+		for list in lists.iter() {
+			let root = if list.is_empty() {
+				[0u8; 32]
+			} else {
+				<MantaCrypto as MerkleTree>::root(hash_param.clone(), list)
+			};
+			(list, root).encode_to(&mut encoded);
+		}
+		let coin_shards_key = [twox_128(b"Assets"), twox_128(b"CoinShards")].concat();
+		unhashed::put_raw(&coin_shards_key, &encoded);
+
+		crate::migration::migrate::<Test>();
+		assert_eq!(StorageVersion::get(), Releases::V9_0_0);
+		assert!(unhashed::get_raw(&coin_shards_key).is_none());
+		reshard_ledger();
+
+		// the commitments are re-sharded with `shard_index_of`
+		let mut resharded_lists = vec![Vec::<[u8; 32]>::new(); ShardCount::get() as usize];
+		for cm in lists.iter().flatten() {
+			assert!(<Assets as LedgerSharding>::exist(cm));
+			resharded_lists[Assets::shard_index_of(cm) as usize].push(*cm);
+		}
+		for (shard_index, list) in resharded_lists.iter().enumerate() {
+			assert_eq!(&Assets::shard_leaves(shard_index as u32), list);
+			if !list.is_empty() {
+				let root = ShardMetas::get(shard_index as u32).root;
+				assert!(<Assets as LedgerSharding>::check_root(&root));
+			}
+		}
+
+		// the migrated frontiers keep producing the roots of the full tree
+		for _ in 0..20 {
+			let mut cm = [0u8; 32];
+			rng.fill_bytes(&mut cm);
+			<Assets as LedgerSharding>::update(&cm, &hash_param);
+			let shard_index = Assets::shard_index_of(&cm);
+			assert_eq!(
+				ShardMetas::get(shard_index).root,
+				<MantaCrypto as MerkleTree>::root(
+					hash_param.clone(),
+					&Assets::shard_leaves(shard_index)
+				)
+			);
+		}
+	});
+}
+
+#[test]
+fn test_migration_reshards_legacy_shards() {
+	new_test_ext().execute_with(|| {
+		let hash_param = HashParam::deserialize(HASH_PARAM.data);
+		let mut rng = ChaCha20Rng::from_seed([3u8; 32]);
+		StorageVersion::put(Releases::V6_0_0);
+
+		// write the shards in the `V6_0_0` format, i.e., indexed by `u8`s
+		let mut lists = vec![Vec::<[u8; 32]>::new(); 256];
+		for _ in 0..20 {
+			let mut cm = [0u8; 32];
+			rng.fill_bytes(&mut cm);
+			lists[cm[0] as usize].push(cm);
+		}
+		for (shard_index, list) in lists.iter().enumerate() {
+			if list.is_empty() {
+				continue;
+			}
+			let shard_key = Blake2_128Concat::hash(&(shard_index as u8).encode());
+			for (leaf_index, cm) in list.iter().enumerate() {
+				let leaf_key = [
+					shard_key.clone(),
+					Blake2_128Concat::hash(&(leaf_index as u64).encode()),
+				]
+				.concat();
+				put_storage_value(b"Assets", b"ShardLeaves", &leaf_key, cm);
+				CoinCommitments::insert(cm, ());
+			}
+			let root = <MantaCrypto as MerkleTree>::root(hash_param.clone(), list);
+			let meta = ShardMeta {
+				root,
+				frontier: MerkleFrontier::from_leaves(&hash_param, list),
+			};
+			put_storage_value(b"Assets", b"ShardMetas", &shard_key, meta);
+			LedgerRoots::insert(&root, ());
+		}
+
+		crate::migration::migrate::<Test>();
+		assert_eq!(StorageVersion::get(), Releases::V9_0_0);
+		reshard_ledger();
+		assert_eq!(Assets::ledger_shard_count(), ShardCount::get());
+
+		// the old shards are gone, and their leaves are in the new shards, in order
+		let mut resharded_lists = vec![Vec::<[u8; 32]>::new(); ShardCount::get() as usize];
+		for cm in lists.iter().flatten() {
+			resharded_lists[Assets::shard_index_of(cm) as usize].push(*cm);
+		}
+		for (shard_index, list) in resharded_lists.iter().enumerate() {
+			assert_eq!(&Assets::shard_leaves(shard_index as u32), list);
+		}
+		assert_eq!(
+			ShardLeaves::iter().count(),
+			lists.iter().map(|list| list.len()).sum::<usize>()
+		);
+	});
+}
+
+#[test]
+fn test_migration_moves_void_numbers() {
+	new_test_ext().execute_with(|| {
+		StorageVersion::put(Releases::V3_0_0);
+
+		let mut rng = ChaCha20Rng::from_seed([3u8; 32]);
+		let mut vn_list = Vec::new();
+		for _ in 0..10 {
+			let mut vn = [0u8; 32];
+			rng.fill_bytes(&mut vn);
+			vn_list.push(vn);
+		}
+		let vn_list_key = [twox_128(b"Assets"), twox_128(b"VNList")].concat();
+		unhashed::put(&vn_list_key, &vn_list);
+
+		crate::migration::migrate::<Test>();
+		assert_eq!(StorageVersion::get(), Releases::V9_0_0);
+		assert!(unhashed::get_raw(&vn_list_key).is_none());
+
+		for vn in vn_list.iter() {
+			assert!(Assets::is_void_number_spent(vn));
+		}
+		assert!(!Assets::is_void_number_spent(&[0u8; 32]));
+		assert_eq!(VoidNumbers::iter().count(), vn_list.len());
+	});
+}
+
+#[test]
+fn test_root_history_window() {
+	new_test_ext().execute_with(|| {
+		let hash_param = HashParam::deserialize(HASH_PARAM.data);
+		let mut rng = ChaCha20Rng::from_seed([3u8; 32]);
+		let shard_index = 7u32;
+		let mut append = || {
+			let cm = sample_commitment_in_shard(&mut rng, shard_index);
+			<Assets as LedgerSharding>::update(&cm, &hash_param);
+			ShardMetas::get(shard_index).root
+		};
+
+		let root = append();
+		for _ in 1..RootHistorySize::get() {
+			let current_root = append();
+			assert!(<Assets as LedgerSharding>::check_root(&current_root));
+			assert!(<Assets as LedgerSharding>::check_root(&root));
+		}
+
+		// the root falls out of the window
+		let current_root = append();
+		assert!(<Assets as LedgerSharding>::check_root(&current_root));
+		assert!(!<Assets as LedgerSharding>::check_root(&root));
+		assert_eq!(LedgerRoots::iter().count(), RootHistorySize::get() as usize);
+	});
+}
+
+#[test]
+fn test_transfer_with_superseded_root_should_work() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Assets::initialize_ledger(Origin::root()));
+		assert_ok!(Assets::init_asset(
+			Origin::signed(1),
+			TEST_ASSET,
+			10_000_000
+		));
+
+		let hash_param = HashParam::deserialize(HASH_PARAM.data);
+		let commit_param = CommitmentParam::deserialize(COMMIT_PARAM.data);
+
+		let pk = default_transfer_zkp_key();
+
+		let mut rng = ChaCha20Rng::from_seed([3u8; 32]);
+		let mut sk = [0u8; 32];
+
+		let senders = mint_tokens_helper(2);
+		let list_1 = Assets::shard_leaves(Assets::shard_index_of(&senders[0].commitment));
+		let sender_1 = SenderMetaData::build(hash_param.clone(), senders[0].clone(), &list_1);
+		let list_2 = Assets::shard_leaves(Assets::shard_index_of(&senders[1].commitment));
+		let sender_2 = SenderMetaData::build(hash_param.clone(), senders[1].clone(), &list_2);
+
+		let mut receivers = Vec::new();
+		for value in [10u64, 11].iter() {
+			rng.fill_bytes(&mut sk);
+			let receiver_full =
+				MantaAssetFullReceiver::sample(&commit_param, &sk, &TEST_ASSET, &(), &mut rng);
+			receivers.push(receiver_full.prepared.process(value, &mut rng));
+		}
+
+		let payload = generate_private_transfer_payload(
+			commit_param.clone(),
+			hash_param.clone(),
+			&pk,
+			sender_1,
+			sender_2,
+			receivers[0].clone(),
+			receivers[1].clone(),
+			0,
+			Assets::chain_id(),
+			&mut rng,
+		)
+		.unwrap();
+
+		// other coins land in the shards of the senders
+		// after the payload has been generated
+		for sender in senders.iter() {
+			let shard_index = Assets::shard_index_of(&sender.commitment);
+			let cm = sample_commitment_in_shard(&mut rng, shard_index);
+			<Assets as LedgerSharding>::update(&cm, &hash_param);
+		}
+
+		assert_ok!(Assets::private_transfer(Origin::signed(1), payload));
+		assert!(Assets::is_void_number_spent(&senders[0].void_number));
+		assert!(Assets::is_void_number_spent(&senders[1].void_number));
+	});
+}
+
+#[test]
+fn test_transfer_with_fee() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Assets::initialize_ledger(Origin::root()));
+		assert_ok!(Assets::init_asset(
+			Origin::signed(1),
+			TEST_ASSET,
+			10_000_000
+		));
+
+		let hash_param = HashParam::deserialize(HASH_PARAM.data);
+		let commit_param = CommitmentParam::deserialize(COMMIT_PARAM.data);
+
+		let pk = default_transfer_zkp_key();
+
+		let mut rng = ChaCha20Rng::from_seed([3u8; 32]);
+		let mut sk = [0u8; 32];
+
+		// the senders hold 10 + 11, the receivers get 10 + 9 and the fee is 2
+		let senders = mint_tokens_helper(2);
+		let list_1 = Assets::shard_leaves(Assets::shard_index_of(&senders[0].commitment));
+		let sender_1 = SenderMetaData::build(hash_param.clone(), senders[0].clone(), &list_1);
+		let list_2 = Assets::shard_leaves(Assets::shard_index_of(&senders[1].commitment));
+		let sender_2 = SenderMetaData::build(hash_param.clone(), senders[1].clone(), &list_2);
+
+		let mut receivers = Vec::new();
+		for value in [10u64, 9].iter() {
+			rng.fill_bytes(&mut sk);
+			let receiver_full =
+				MantaAssetFullReceiver::sample(&commit_param, &sk, &TEST_ASSET, &(), &mut rng);
+			receivers.push(receiver_full.prepared.process(value, &mut rng));
+		}
+
+		let payload = generate_private_transfer_payload(
+			commit_param.clone(),
+			hash_param.clone(),
+			&pk,
+			sender_1,
+			sender_2,
+			receivers[0].clone(),
+			receivers[1].clone(),
+			2,
+			Assets::chain_id(),
+			&mut rng,
+		)
+		.unwrap();
+
+		// the fee is bound to the proof
+		let mut tampered = PrivateTransferData::deserialize(payload.as_ref());
+		tampered.fee = 1;
+		let mut tampered_payload = [0u8; PRIVATE_TRANSFER_PAYLOAD_SIZE];
+		tampered.serialize(tampered_payload.as_mut());
+		assert_noop!(
+			Assets::private_transfer(Origin::signed(1), tampered_payload),
+			Error::<Test>::ZkpVerificationFail
+		);
+
+		// a transfer without a fee does not reveal its asset id
+		assert_eq!(tampered.asset_id, TEST_ASSET);
+		tampered.fee = 0;
+		tampered.serialize(tampered_payload.as_mut());
+		assert_noop!(
+			Assets::private_transfer(Origin::signed(1), tampered_payload),
+			Error::<Test>::InvalidPayload
+		);
+
+		// the caller does not need to own any asset
+		assert_eq!(Assets::balance(2, TEST_ASSET), 0);
+		assert_ok!(Assets::private_transfer(Origin::signed(2), payload));
+		assert_eq!(PoolBalance::get(TEST_ASSET), 19);
+		assert_eq!(Assets::balance(FeeCollector::get(), TEST_ASSET), 2);
+		assert_eq!(TotalSupply::get(TEST_ASSET), 10_000_000);
+	});
+}
+
+#[test]
+fn test_unsigned_private_transfer() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Assets::initialize_ledger(Origin::root()));
+		assert_ok!(Assets::init_asset(
+			Origin::signed(1),
+			TEST_ASSET,
+			10_000_000
+		));
+
+		let hash_param = HashParam::deserialize(HASH_PARAM.data);
+		let commit_param = CommitmentParam::deserialize(COMMIT_PARAM.data);
+
+		let pk = default_transfer_zkp_key();
+
+		let mut rng = ChaCha20Rng::from_seed([3u8; 32]);
+		let mut sk = [0u8; 32];
+
+		let senders = mint_tokens_helper(2);
+		let list_1 = Assets::shard_leaves(Assets::shard_index_of(&senders[0].commitment));
+		let sender_1 = SenderMetaData::build(hash_param.clone(), senders[0].clone(), &list_1);
+		let list_2 = Assets::shard_leaves(Assets::shard_index_of(&senders[1].commitment));
+		let sender_2 = SenderMetaData::build(hash_param.clone(), senders[1].clone(), &list_2);
+
+		let mut receivers = Vec::new();
+		for value in [10u64, 10].iter() {
+			rng.fill_bytes(&mut sk);
+			let receiver_full =
+				MantaAssetFullReceiver::sample(&commit_param, &sk, &TEST_ASSET, &(), &mut rng);
+			receivers.push(receiver_full.prepared.process(value, &mut rng));
+		}
+
+		let payload = generate_private_transfer_payload(
+			commit_param.clone(),
+			hash_param.clone(),
+			&pk,
+			sender_1,
+			sender_2,
+			receivers[0].clone(),
+			receivers[1].clone(),
+			1,
+			Assets::chain_id(),
+			&mut rng,
+		)
+		.unwrap();
+		let call = pallet_manta_pay::Call::<Test>::private_transfer_unsigned(payload);
+
+		// the transfer is valid in the pool, and provides its void numbers
+		let valid = Assets::validate_unsigned(TransactionSource::External, &call).unwrap();
+		assert_eq!(valid.priority, 1);
+		assert_eq!(valid.provides.len(), 2);
+
+		// a malformed payload is rejected in the pool
+		let mut malformed = payload;
+		malformed[16..48].copy_from_slice(&[0xffu8; 32]);
+		assert_eq!(
+			Assets::validate_unsigned(
+				TransactionSource::External,
+				&pallet_manta_pay::Call::<Test>::private_transfer_unsigned(malformed)
+			),
+			InvalidTransaction::Call.into()
+		);
+
+		// a transfer with a bad proof is rejected in the pool,
+		// here the fee is not the one bound to the proof
+		let mut tampered = payload;
+		tampered[8..16].copy_from_slice(&0u64.to_le_bytes());
+		let tampered_call = pallet_manta_pay::Call::<Test>::private_transfer_unsigned(tampered);
+		for source in [TransactionSource::External, TransactionSource::Local].iter() {
+			assert_eq!(
+				Assets::validate_unsigned(*source, &tampered_call),
+				InvalidTransaction::BadProof.into()
+			);
+		}
+		// in a block, the proof is verified on dispatch
+		assert!(Assets::validate_unsigned(TransactionSource::InBlock, &tampered_call).is_ok());
+		assert_noop!(
+			Assets::private_transfer_unsigned(Origin::none(), tampered),
+			Error::<Test>::ZkpVerificationFail
+		);
+
+		// the transfer must not be signed
+		assert_noop!(
+			Assets::private_transfer_unsigned(Origin::signed(1), payload),
+			DispatchError::BadOrigin
+		);
+		assert_ok!(Assets::private_transfer_unsigned(Origin::none(), payload));
+		assert_eq!(Assets::balance(FeeCollector::get(), TEST_ASSET), 1);
+
+		// the coins are spent: the transfer is stale
+		assert_eq!(
+			Assets::validate_unsigned(TransactionSource::External, &call),
+			InvalidTransaction::Stale.into()
+		);
+	});
+}
+
+#[test]
+fn test_tampered_transfer_should_not_work() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Assets::initialize_ledger(Origin::root()));
+		assert_ok!(Assets::init_asset(
+			Origin::signed(1),
+			TEST_ASSET,
+			10_000_000
+		));
+
+		let hash_param = HashParam::deserialize(HASH_PARAM.data);
+		let commit_param = CommitmentParam::deserialize(COMMIT_PARAM.data);
+
+		let pk = default_transfer_zkp_key();
+
+		let mut rng = ChaCha20Rng::from_seed([3u8; 32]);
+		let mut sk = [0u8; 32];
+
+		let senders = mint_tokens_helper(2);
+		let list_1 = Assets::shard_leaves(Assets::shard_index_of(&senders[0].commitment));
+		let sender_1 = SenderMetaData::build(hash_param.clone(), senders[0].clone(), &list_1);
+		let list_2 = Assets::shard_leaves(Assets::shard_index_of(&senders[1].commitment));
+		let sender_2 = SenderMetaData::build(hash_param.clone(), senders[1].clone(), &list_2);
+
+		let mut receivers = Vec::new();
+		for value in [10u64, 10].iter() {
+			rng.fill_bytes(&mut sk);
+			let receiver_full =
+				MantaAssetFullReceiver::sample(&commit_param, &sk, &TEST_ASSET, &(), &mut rng);
+			receivers.push(receiver_full.prepared.process(value, &mut rng));
+		}
+
+		let payload = generate_private_transfer_payload(
+			commit_param.clone(),
+			hash_param.clone(),
+			&pk,
+			sender_1,
+			sender_2,
+			receivers[0].clone(),
+			receivers[1].clone(),
+			1,
+			Assets::chain_id(),
+			&mut rng,
+		)
+		.unwrap();
+		let data = PrivateTransferData::deserialize(payload.as_ref());
+		let transfer_pvk = Assets::active_prepared_verification_key(CircuitKind::Transfer).unwrap();
+		assert_eq!(data.try_verify(&transfer_pvk), Ok(()));
+
+		// tampering with any field of the payload invalidates the proof
+		let tamperings: Vec<Box<dyn Fn(&mut PrivateTransferData)>> = vec![
+			Box::new(|data| data.asset_id += 1),
+			Box::new(|data| data.fee += 1),
+			Box::new(|data| data.sender_1.k = data.sender_2.k),
+			Box::new(|data| data.sender_1.void_number[0] ^= 1),
+			Box::new(|data| data.sender_2.root = data.receiver_1.cm),
+			Box::new(|data| data.receiver_1.k = data.receiver_2.k),
+			Box::new(|data| data.receiver_2.cm = data.receiver_1.cm),
+			Box::new(|data| data.receiver_1.sender_pk[0] ^= 1),
+			Box::new(|data| data.receiver_2.cipher[0] ^= 1),
+			Box::new(|data| data.chain_id[0] ^= 1),
+		];
+		for tamper in tamperings.iter() {
+			let mut tampered = data.clone();
+			tamper(&mut tampered);
+			assert_ne!(tampered, data);
+			assert!(tampered.try_verify(&transfer_pvk).is_err());
+		}
+
+		// the fields that are not otherwise constrained are bound by the binding hash
+		let mut tampered = data.clone();
+		tampered.receiver_1.cipher[0] ^= 1;
+		assert_eq!(
+			tampered.try_verify(&transfer_pvk),
+			Err(VerifyError::PairingCheckFailed)
+		);
+		let mut tampered_payload = [0u8; PRIVATE_TRANSFER_PAYLOAD_SIZE];
+		tampered.serialize(tampered_payload.as_mut());
+		assert_noop!(
+			Assets::private_transfer(Origin::signed(1), tampered_payload),
+			Error::<Test>::ZkpVerificationFail
+		);
+
+		// the payload is meant for another chain
+		let mut tampered = data;
+		tampered.chain_id[0] ^= 1;
+		tampered.serialize(tampered_payload.as_mut());
+		assert_noop!(
+			Assets::private_transfer(Origin::signed(1), tampered_payload),
+			Error::<Test>::WrongChain
+		);
+
+		assert_ok!(Assets::private_transfer(Origin::signed(1), payload));
+	});
+}
+
+#[test]
+fn test_transfer_with_invalid_proof_should_not_work() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Assets::initialize_ledger(Origin::root()));
+		assert_ok!(Assets::init_asset(
+			Origin::signed(1),
+			TEST_ASSET,
+			10_000_000
+		));
+
+		let hash_param = HashParam::deserialize(HASH_PARAM.data);
+		let commit_param = CommitmentParam::deserialize(COMMIT_PARAM.data);
+
+		let pk = default_transfer_zkp_key();
+
+		let mut rng = ChaCha20Rng::from_seed([3u8; 32]);
+		let mut sk = [0u8; 32];
+
+		let senders = mint_tokens_helper(2);
+		let list_1 = Assets::shard_leaves(Assets::shard_index_of(&senders[0].commitment));
+		let sender_1 = SenderMetaData::build(hash_param.clone(), senders[0].clone(), &list_1);
+		let list_2 = Assets::shard_leaves(Assets::shard_index_of(&senders[1].commitment));
+		let sender_2 = SenderMetaData::build(hash_param.clone(), senders[1].clone(), &list_2);
+
+		let mut receivers = Vec::new();
+		for value in [10u64, 11].iter() {
+			rng.fill_bytes(&mut sk);
+			let receiver_full =
+				MantaAssetFullReceiver::sample(&commit_param, &sk, &TEST_ASSET, &(), &mut rng);
+			receivers.push(receiver_full.prepared.process(value, &mut rng));
+		}
+
+		let payload = generate_private_transfer_payload(
+			commit_param.clone(),
+			hash_param.clone(),
+			&pk,
+			sender_1,
+			sender_2,
+			receivers[0].clone(),
+			receivers[1].clone(),
+			0,
+			Assets::chain_id(),
+			&mut rng,
+		)
+		.unwrap();
+		let data = PrivateTransferData::deserialize(payload.as_ref());
+		let transfer_pvk = Assets::active_prepared_verification_key(CircuitKind::Transfer).unwrap();
+		let reclaim_pvk = Assets::active_prepared_verification_key(CircuitKind::Reclaim).unwrap();
+		assert_eq!(data.try_verify(&transfer_pvk), Ok(()));
+
+		// the key is malformed, or it is the key of another circuit
+		assert_eq!(
+			data.try_verify(&transfer_pvk[1..]),
+			Err(VerifyError::BadVerificationKey)
+		);
+		assert_eq!(
+			data.try_verify(&reclaim_pvk),
+			Err(VerifyError::BadVerificationKey)
+		);
+
+		// the proof is not a valid encoding
+		let mut tampered = data.clone();
+		tampered.proof = [0xffu8; 192];
+		assert_eq!(
+			tampered.try_verify(&transfer_pvk),
+			Err(VerifyError::BadProofEncoding)
+		);
+
+		// a public input is not a curve point
+		let mut tampered = data.clone();
+		tampered.sender_1.root = [0xffu8; 32];
+		assert_eq!(
+			tampered.try_verify(&transfer_pvk),
+			Err(VerifyError::BadPublicInput)
+		);
+
+		// the receivers are swapped: the proof no longer matches the inputs
+		let mut tampered = data.clone();
+		tampered.receiver_1 = data.receiver_2.clone();
+		tampered.receiver_2 = data.receiver_1.clone();
+		assert_eq!(
+			tampered.try_verify(&transfer_pvk),
+			Err(VerifyError::PairingCheckFailed)
+		);
+		let mut tampered_payload = [0u8; PRIVATE_TRANSFER_PAYLOAD_SIZE];
+		tampered.serialize(tampered_payload.as_mut());
+		assert_noop!(
+			Assets::private_transfer(Origin::signed(1), tampered_payload),
+			Error::<Test>::ZkpVerificationFail
+		);
+
+		assert_ok!(Assets::private_transfer(Origin::signed(1), payload));
+	});
+}
+
+#[test]
+fn test_private_transfer_batch() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Assets::initialize_ledger(Origin::root()));
+		assert_ok!(Assets::init_asset(
+			Origin::signed(1),
+			TEST_ASSET,
+			10_000_000
+		));
+
+		let hash_param = HashParam::deserialize(HASH_PARAM.data);
+		let commit_param = CommitmentParam::deserialize(COMMIT_PARAM.data);
+
+		let pk = default_transfer_zkp_key();
+
+		let mut rng = ChaCha20Rng::from_seed([3u8; 32]);
+		let mut sk = [0u8; 32];
+
+		let batch_size = 3;
+		let senders = mint_tokens_helper(batch_size * 2);
+		let mut payloads = Vec::new();
+		let mut receivers = Vec::new();
+		for i in 0..batch_size {
+			let mut sender_data = Vec::new();
+			for sender in senders[i * 2..i * 2 + 2].iter() {
+				let list = Assets::shard_leaves(Assets::shard_index_of(&sender.commitment));
+				sender_data.push(SenderMetaData::build(
+					hash_param.clone(),
+					sender.clone(),
+					&list,
+				));
+			}
+			let mut receiver_data = Vec::new();
+			for value in [10u64, 11].iter() {
+				rng.fill_bytes(&mut sk);
+				let receiver_full =
+					MantaAssetFullReceiver::sample(&commit_param, &sk, &TEST_ASSET, &(), &mut rng);
+				receiver_data.push(receiver_full.prepared.process(value, &mut rng));
+			}
+			payloads.push(
+				generate_private_transfer_payload(
+					commit_param.clone(),
+					hash_param.clone(),
+					&pk,
+					sender_data[0].clone(),
+					sender_data[1].clone(),
+					receiver_data[0].clone(),
+					receiver_data[1].clone(),
+					0,
+					Assets::chain_id(),
+					&mut rng,
+				)
+				.unwrap(),
+			);
+			receivers.extend(receiver_data);
+		}
+
+		let data: Vec<PrivateTransferData> = payloads
+			.iter()
+			.map(|payload| PrivateTransferData::deserialize(payload.as_ref()))
+			.collect();
+		let transfer_pvk = Assets::active_prepared_verification_key(CircuitKind::Transfer).unwrap();
+		assert_eq!(batch_verify(&transfer_pvk, &data), Ok(()));
+
+		// the invalid proof is found by the fallback
+		let mut tampered = data.clone();
+		tampered[1].receiver_1 = data[1].receiver_2.clone();
+		tampered[1].receiver_2 = data[1].receiver_1.clone();
+		assert_eq!(
+			batch_verify(&transfer_pvk, &tampered),
+			Err((1, VerifyError::PairingCheckFailed))
+		);
+		let mut tampered_payload = [0u8; PRIVATE_TRANSFER_PAYLOAD_SIZE];
+		tampered[1].serialize(tampered_payload.as_mut());
+		assert_noop!(
+			Assets::private_transfer_batch(
+				Origin::signed(1),
+				vec![payloads[0], tampered_payload, payloads[2]]
+			),
+			Error::<Test>::ZkpVerificationFail
+		);
+
+		// the transfers of a batch cannot spend the same coins
+		assert_noop!(
+			Assets::private_transfer_batch(Origin::signed(1), vec![payloads[0], payloads[0]]),
+			Error::<Test>::MantaCoinSpent
+		);
+
+		assert_noop!(
+			Assets::private_transfer_batch(
+				Origin::signed(1),
+				vec![payloads[0]; MaxBatchSize::get() as usize + 1]
+			),
+			Error::<Test>::BatchTooLarge
+		);
+
+		assert_ok!(Assets::private_transfer_batch(
+			Origin::signed(1),
+			payloads.clone()
+		));
+		for sender in senders.iter() {
+			assert!(Assets::is_void_number_spent(&sender.void_number));
+		}
+		for receiver in receivers.iter() {
+			assert!(<Assets as LedgerSharding>::exist(&receiver.commitment));
+		}
+		// the notes of the minted coins, then of the received ones
+		assert_eq!(Assets::note_count(), batch_size as u64 * 4);
+	});
+}
+
+#[test]
+fn test_private_transfer_n() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Assets::initialize_ledger(Origin::root()));
+		assert_ok!(Assets::init_asset(
+			Origin::signed(1),
+			TEST_ASSET,
+			10_000_000
+		));
+
+		let hash_param = HashParam::deserialize(HASH_PARAM.data);
+		let commit_param = CommitmentParam::deserialize(COMMIT_PARAM.data);
+
+		// a key for the transfers of one coin to two coins
+		let mut rng = ChaCha20Rng::from_seed([3u8; 32]);
+		let pk = generate_transfer_n_zkp_key(&commit_param, &hash_param, 1, 2, &mut rng);
+		let mut vk_bytes = Vec::new();
+		pk.vk.serialize_uncompressed(&mut vk_bytes).unwrap();
+
+		assert_noop!(
+			Assets::set_verification_key(
+				Origin::root(),
+				CircuitKind::TransferN(0, 2),
+				0,
+				vk_bytes.clone()
+			),
+			Error::<Test>::UnsupportedCircuit
+		);
+		assert_noop!(
+			Assets::set_verification_key(
+				Origin::root(),
+				CircuitKind::TransferN(1, MAX_TRANSFER_ARITY + 1),
+				0,
+				vk_bytes.clone()
+			),
+			Error::<Test>::UnsupportedCircuit
+		);
+
+		let senders = mint_tokens_helper(1);
+		let list = Assets::shard_leaves(Assets::shard_index_of(&senders[0].commitment));
+		let sender = SenderMetaData::build(hash_param.clone(), senders[0].clone(), &list);
+
+		let mut sk = [0u8; 32];
+		let mut receivers = Vec::new();
+		for value in [4u64, 6].iter() {
+			rng.fill_bytes(&mut sk);
+			let receiver_full =
+				MantaAssetFullReceiver::sample(&commit_param, &sk, &TEST_ASSET, &(), &mut rng);
+			receivers.push(receiver_full.prepared.process(value, &mut rng));
+		}
+
+		let payload = generate_private_transfer_n_payload(
+			commit_param.clone(),
+			hash_param.clone(),
+			&pk,
+			vec![sender],
+			receivers.clone(),
+			0,
+			Assets::chain_id(),
+			&mut rng,
+		)
+		.unwrap();
+		assert_eq!(payload.len(), 2 + 16 + 96 + 2 * 192 + 32 + 192);
+
+		// the transfer is charged for its shape
+		let call = pallet_manta_pay::Call::<Test>::private_transfer_n(payload.clone());
+		assert_eq!(
+			call.get_dispatch_info().weight,
+			<() as WeightInfo>::private_transfer_n(1, 2)
+		);
+		let mut oversized = payload.clone();
+		oversized[1] = u8::MAX;
+		let call = pallet_manta_pay::Call::<Test>::private_transfer_n(oversized.clone());
+		assert_eq!(
+			call.get_dispatch_info().weight,
+			<() as WeightInfo>::private_transfer_n(1, MAX_TRANSFER_ARITY as u32)
+		);
+		assert_noop!(
+			Assets::private_transfer_n(Origin::signed(1), oversized),
+			Error::<Test>::InvalidPayload
+		);
+
+		// no key is registered for this shape yet
+		assert_noop!(
+			Assets::private_transfer_n(Origin::signed(1), payload.clone()),
+			Error::<Test>::ZkpParamFail
+		);
+		assert_ok!(Assets::set_verification_key(
+			Origin::root(),
+			CircuitKind::TransferN(1, 2),
+			0,
+			vk_bytes
+		));
+
+		let mut long_payload = payload.clone();
+		long_payload.push(0);
+		assert_noop!(
+			Assets::private_transfer_n(Origin::signed(1), long_payload),
+			Error::<Test>::InvalidPayload
+		);
+
+		assert_ok!(Assets::private_transfer_n(
+			Origin::signed(1),
+			payload.clone()
+		));
+		assert!(Assets::is_void_number_spent(&senders[0].void_number));
+		for receiver in receivers.iter() {
+			assert!(<Assets as LedgerSharding>::exist(&receiver.commitment));
+		}
+		assert_eq!(Assets::note_count(), 3);
+		assert_eq!(PoolBalance::get(TEST_ASSET), 10);
+
+		assert_noop!(
+			Assets::private_transfer_n(Origin::signed(1), payload),
+			Error::<Test>::MantaCoinSpent
+		);
+	});
+}
+
+#[test]
+fn test_shard_index_distribution() {
+	new_test_ext().execute_with(|| {
+		let commit_param = CommitmentParam::deserialize(COMMIT_PARAM.data);
+		let mut rng = ChaCha20Rng::from_seed([3u8; 32]);
+		let mut sk = [0u8; 32];
+
+		let shard_count = ShardCount::get() as usize;
+		let size = 8 * shard_count;
+		let mut counts = vec![0usize; shard_count];
+		for i in 0..size {
+			rng.fill_bytes(&mut sk);
+			let asset = MantaAsset::sample(&commit_param, &sk, &TEST_ASSET, &(i as u64), &mut rng);
+			counts[Assets::shard_index_of(&asset.commitment) as usize] += 1;
+		}
+
+		// chi-squared test with 255 degrees of freedom:
+		// the statistic has mean 255 and standard deviation ~22.6
+		let expected = size as f64 / shard_count as f64;
+		let chi_squared: f64 = counts
+			.iter()
+			.map(|&count| (count as f64 - expected).powi(2) / expected)
+			.sum();
+		assert!(chi_squared < 350.0, "chi squared: {}", chi_squared);
+	});
+}
+
+// run `on_initialize` until the ledger is re-sharded,
+// and return the number of blocks it took
+fn reshard_ledger() -> u64 {
+	let mut block_number = 0;
+	while Assets::is_resharding() {
+		block_number += 1;
+		System::set_block_number(block_number);
+		Assets::on_initialize(block_number);
+	}
+	block_number
+}
+
+#[test]
+fn test_reshard_on_shard_count_change() {
+	new_test_ext().execute_with(|| {
+		let hash_param = HashParam::deserialize(HASH_PARAM.data);
+		let mut rng = ChaCha20Rng::from_seed([3u8; 32]);
+
+		StorageVersion::put(Releases::V9_0_0);
+		LedgerShardCount::put(ShardCount::get());
+		let mut commitments = Vec::new();
+		for i in 0..150 {
+			let mut cm = [0u8; 32];
+			rng.fill_bytes(&mut cm);
+			// half of the coins have a note
+			if i % 2 == 0 {
+				<Assets as LedgerSharding>::update(&cm, &hash_param);
+			} else {
+				let receiver = ReceiverData {
+					cm,
+					..Default::default()
+				};
+				Assets::insert_note(&receiver, &hash_param);
+			}
+			commitments.push(cm);
+		}
+
+		let old_roots: Vec<[u8; 32]> = LedgerRoots::iter().map(|(root, _)| root).collect();
+		let old_leaves: Vec<[u8; 32]> = (0..ShardCount::get())
+			.flat_map(Assets::shard_leaves)
+			.collect();
+
+		ShardCount::set(&16);
+		crate::migration::migrate::<Test>();
+		assert!(Assets::is_resharding());
+		assert_eq!(Assets::ledger_shard_count(), 256);
+
+		// no coin is added to the ledger until it is re-sharded
+		let receiver = ReceiverData::default();
+		assert_eq!(
+			Assets::check_spends(
+				&[],
+				&[&receiver],
+				&mut BTreeSet::new(),
+				&mut BTreeSet::new()
+			),
+			Err(Error::<Test>::LedgerResharding.into())
+		);
+
+		// the commitments are moved, then re-inserted, over several blocks
+		assert!(reshard_ledger() > 2);
+		assert!(!Assets::is_resharding());
+		assert_eq!(Assets::ledger_shard_count(), 16);
+		assert!(ShardMetas::iter().all(|(shard_index, _)| shard_index < 16));
+		assert_eq!(ReshardQueue::iter().count(), 0);
+		let resharded: <Test as frame_system::Config>::Event = RawEvent::LedgerResharded(16).into();
+		assert!(System::events()
+			.iter()
+			.any(|record| record.event == resharded));
+
+		// the leaves keep their order of the old shards
+		let mut resharded_lists = vec![Vec::<[u8; 32]>::new(); 16];
+		for cm in old_leaves.iter() {
+			resharded_lists[Assets::shard_index_of(cm) as usize].push(*cm);
+		}
+
+		let mut leaf_count = 0;
+		for shard_index in 0..16 {
+			let leaves = Assets::shard_leaves(shard_index);
+			assert_eq!(leaves, resharded_lists[shard_index as usize]);
+			let root = ShardMetas::get(shard_index).root;
+			assert!(<Assets as LedgerSharding>::check_root(&root));
+			assert_eq!(
+				root,
+				<MantaCrypto as MerkleTree>::root(hash_param.clone(), &leaves)
+			);
+			leaf_count += leaves.len();
+		}
+		assert_eq!(leaf_count, commitments.len());
+		for cm in commitments.iter() {
+			assert!(<Assets as LedgerSharding>::exist(cm));
+		}
+
+		// the transactions in flight can still refer to the old roots
+		for root in old_roots.iter() {
+			assert!(<Assets as LedgerSharding>::check_root(root));
+		}
+
+		// the notes point to the new positions of their coins
+		assert_eq!(Assets::note_count(), 75);
+		for (_, record) in Notes::iter() {
+			assert_eq!(record.shard_index, Assets::shard_index_of(&record.cm));
+			assert_eq!(
+				ShardLeaves::get(record.shard_index, record.leaf_index),
+				record.cm
+			);
+		}
+	});
+}
+
+#[test]
+fn test_notes_pagination() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Assets::initialize_ledger(Origin::root()));
+		let hash_param = HashParam::deserialize(HASH_PARAM.data);
+		let mut rng = ChaCha20Rng::from_seed([3u8; 32]);
+
+		let mut receivers = Vec::new();
+		for _ in 0..10 {
+			let mut receiver = ReceiverData::default();
+			rng.fill_bytes(&mut receiver.cm);
+			rng.fill_bytes(&mut receiver.sender_pk);
+			rng.fill_bytes(&mut receiver.cipher);
+			Assets::insert_note(&receiver, &hash_param);
+			receivers.push(receiver);
+		}
+		assert_eq!(Assets::note_count(), 10);
+
+		// the notes are in the order the coins have been received,
+		// along with the position of the coins in the ledger
+		for (note_index, receiver) in receivers.iter().enumerate() {
+			let record = Assets::note(note_index as u64).unwrap();
+			assert_eq!(record.cm, receiver.cm);
+			assert_eq!(record.encrypted_note, receiver.encrypted_note());
+			assert_eq!(record.shard_index, Assets::shard_index_of(&receiver.cm));
+			assert_eq!(
+				ShardLeaves::get(record.shard_index, record.leaf_index),
+				receiver.cm
+			);
+			assert_eq!(
+				Assets::encrypted_note(&receiver.cm),
+				Some(receiver.encrypted_note())
+			);
+		}
+		assert_eq!(Assets::encrypted_note(&[0u8; 32]), None);
+
+		// sync the notes page by page
+		let mut synced = Vec::new();
+		let mut from = 0;
+		loop {
+			let page = Assets::notes(from, 4);
+			assert!(page.len() <= 4);
+			if page.is_empty() {
+				break;
+			}
+			from += page.len() as u64;
+			synced.extend(page);
+		}
+		assert_eq!(from, 10);
+		assert_eq!(synced, Assets::notes(0, 100));
+		assert_eq!(synced.len(), 10);
+		assert_eq!(Assets::notes(8, 4).len(), 2);
+		assert!(Assets::notes(11, 4).is_empty());
+		assert!(Assets::notes(0, 0).is_empty());
+		assert_eq!(Assets::notes(u64::MAX, u32::MAX), vec![]);
+	});
+}
+
+// sample a random commitment that falls into the shard `shard_index`
+fn sample_commitment_in_shard<R: RngCore>(rng: &mut R, shard_index: u32) -> [u8; 32] {
+	let mut cm = [0u8; 32];
+	loop {
+		rng.fill_bytes(&mut cm);
+		if Assets::shard_index_of(&cm) == shard_index {
+			return cm;
+		}
+	}
+}
+
+#[test]
+fn test_genesis_config() {
+	let hash_param = HashParam::deserialize(HASH_PARAM.data);
+	let commit_param = CommitmentParam::deserialize(COMMIT_PARAM.data);
+	let mut rng = ChaCha20Rng::from_seed([3u8; 32]);
+	let mut sk = [0u8; 32];
+
+	let mut coins = Vec::new();
+	for value in 10..15 {
+		rng.fill_bytes(&mut sk);
+		coins.push(MantaAsset::sample(
+			&commit_param,
+			&sk,
+			&TEST_ASSET,
+			&value,
+			&mut rng,
+		));
+	}
+
+	let mut storage = frame_system::GenesisConfig::default()
+		.build_storage::<Test>()
+		.unwrap();
+	pallet_manta_pay::GenesisConfig::<Test> {
+		assets: vec![(TEST_ASSET, 1, 1000), (TEST_ASSET + 1, 2, 500)],
+		minted_coins: coins
+			.iter()
+			.map(|coin| (TEST_ASSET, coin.priv_info.value, coin.commitment))
+			.collect(),
+		hash_param: Vec::new(),
+		commit_param: Vec::new(),
+	}
+	.assimilate_storage(&mut storage)
+	.unwrap();
+
+	sp_io::TestExternalities::from(storage).execute_with(|| {
+		assert_eq!(TotalSupply::get(TEST_ASSET), 1000);
+		assert_eq!(PoolBalance::get(TEST_ASSET), 60);
+		assert_eq!(Assets::balance(1, TEST_ASSET), 940);
+		assert_eq!(TotalSupply::get(TEST_ASSET + 1), 500);
+		assert_eq!(PoolBalance::get(TEST_ASSET + 1), 0);
+		assert_eq!(Assets::balance(2, TEST_ASSET + 1), 500);
+
+		assert_eq!(HashParamChecksum::get(), hash_param.get_checksum());
+		assert_eq!(CommitParamChecksum::get(), commit_param.get_checksum());
+		assert_eq!(
+			Assets::active_verification_key(CircuitKind::Transfer),
+			Some(TRANSFER_PK.data.to_vec())
+		);
+		assert_eq!(
+			Assets::active_verification_key(CircuitKind::Reclaim),
+			Some(RECLAIM_PK.data.to_vec())
+		);
+
+		for coin in coins.iter() {
+			assert!(<Assets as LedgerSharding>::exist(&coin.commitment));
+			let shard_index = Assets::shard_index_of(&coin.commitment);
+			let root = <MantaCrypto as MerkleTree>::root(
+				hash_param.clone(),
+				&Assets::shard_leaves(shard_index),
+			);
+			assert!(<Assets as LedgerSharding>::check_root(&root));
+		}
+
+		// the genesis coins are listed in the notes, like the minted ones
+		assert_eq!(Assets::note_count(), coins.len() as u64);
+		for (note_index, coin) in coins.iter().enumerate() {
+			let record = Assets::note(note_index as u64).unwrap();
+			assert_eq!(record.cm, coin.commitment);
+			assert_eq!(record.encrypted_note, EncryptedNote::default());
+		}
+
+		// the ledger accepts new coins on top of the genesis ones
+		rng.fill_bytes(&mut sk);
+		let coin = MantaAsset::sample(&commit_param, &sk, &TEST_ASSET, &100, &mut rng);
+		assert_ok!(Assets::mint_private_asset(
+			Origin::signed(1),
+			generate_mint_payload(&coin).unwrap()
+		));
+		assert_eq!(PoolBalance::get(TEST_ASSET), 160);
+		assert_eq!(Assets::balance(1, TEST_ASSET), 840);
+		assert!(<Assets as LedgerSharding>::exist(&coin.commitment));
+		assert_eq!(Assets::note_count(), coins.len() as u64 + 1);
+	});
+}
+
 fn mint_tokens_helper(size: usize) -> Vec<MantaAsset> {
 	let commit_param = CommitmentParam::deserialize(COMMIT_PARAM.data);
 
@@ -260,7 +1624,7 @@ fn mint_tokens_helper(size: usize) -> Vec<MantaAsset> {
 		let token_value = 10 + i as u64;
 		rng.fill_bytes(&mut sk);
 		let asset = MantaAsset::sample(&commit_param, &sk, &TEST_ASSET, &token_value, &mut rng);
-		let payload = generate_mint_payload(&asset);
+		let payload = generate_mint_payload(&asset).unwrap();
 
 		// mint a sender token
 		assert_ok!(Assets::mint_private_asset(Origin::signed(1), payload));
@@ -269,8 +1633,7 @@ fn mint_tokens_helper(size: usize) -> Vec<MantaAsset> {
 
 		// sanity checks
 		assert_eq!(PoolBalance::get(TEST_ASSET), pool);
-		let coin_shards = CoinShards::get();
-		assert!(coin_shards.exist(&asset.commitment));
+		assert!(<Assets as LedgerSharding>::exist(&asset.commitment));
 		senders.push(asset);
 	}
 	senders
@@ -278,6 +1641,7 @@ fn mint_tokens_helper(size: usize) -> Vec<MantaAsset> {
 
 fn transfer_test_helper(iter: usize) {
 	// setup
+	assert_ok!(Assets::initialize_ledger(Origin::root()));
 	assert_ok!(Assets::init_asset(
 		Origin::signed(1),
 		TEST_ASSET,
@@ -290,17 +1654,17 @@ fn transfer_test_helper(iter: usize) {
 	let commit_param = CommitmentParam::deserialize(COMMIT_PARAM.data);
 
 	// load the ZKP keys
-	let mut file = File::open("transfer_pk.bin").unwrap();
-	let mut transfer_key_bytes: Vec<u8> = vec![];
-	file.read_to_end(&mut transfer_key_bytes).unwrap();
-	let buf: &[u8] = transfer_key_bytes.as_ref();
-	let pk = Groth16Pk::deserialize_unchecked(buf).unwrap();
+	let pk = default_transfer_zkp_key();
 	let vk = pk.vk.clone();
 	let mut vk_bytes = Vec::new();
 	vk.serialize_uncompressed(&mut vk_bytes).unwrap();
-	let vk = TRANSFER_PK;
-	let vk_checksum = TransferZKPKeyChecksum::get();
-	assert_eq!(vk.get_checksum(), vk_checksum);
+	// the proofs are verified against the default key: it must be
+	// the one of the proving key, regenerated with `write_zkp_keys`
+	assert_eq!(vk_bytes, TRANSFER_PK.data.to_vec());
+	assert_eq!(
+		Assets::active_verification_key(CircuitKind::Transfer),
+		Some(TRANSFER_PK.data.to_vec())
+	);
 
 	let mut rng = ChaCha20Rng::from_seed([3u8; 32]);
 	let mut sk = [0u8; 32];
@@ -308,9 +1672,9 @@ fn transfer_test_helper(iter: usize) {
 	let size = iter << 1;
 	let senders = mint_tokens_helper(size);
 	let pool = PoolBalance::get(TEST_ASSET);
+	let minted = Assets::note_count();
 
-	let sn_list = VNList::get();
-	assert_eq!(sn_list.len(), 0);
+	assert_eq!(VoidNumbers::iter().count(), 0);
 
 	// build receivers
 	let mut receivers_full = Vec::new();
@@ -326,16 +1690,14 @@ fn transfer_test_helper(iter: usize) {
 	}
 
 	for i in 0usize..iter {
-		let coin_shards = CoinShards::get();
-
 		// build sender mata data
 		let sender_1 = senders[i * 2].clone();
 		let sender_2 = senders[i * 2 + 1].clone();
-		let shard_index_1 = sender_1.commitment[0] as usize;
-		let shard_index_2 = sender_2.commitment[0] as usize;
-		let list_1 = coin_shards.shard[shard_index_1].list.clone();
+		let shard_index_1 = Assets::shard_index_of(&sender_1.commitment);
+		let shard_index_2 = Assets::shard_index_of(&sender_2.commitment);
+		let list_1 = Assets::shard_leaves(shard_index_1);
 		let sender_1 = SenderMetaData::build(hash_param.clone(), sender_1, &list_1);
-		let list_2 = coin_shards.shard[shard_index_2].list.clone();
+		let list_2 = Assets::shard_leaves(shard_index_2);
 		let sender_2 = SenderMetaData::build(hash_param.clone(), sender_2, &list_2);
 
 		// extract the receivers
@@ -351,51 +1713,67 @@ fn transfer_test_helper(iter: usize) {
 			sender_2,
 			receiver_1.clone(),
 			receiver_2.clone(),
+			0,
+			Assets::chain_id(),
 			&mut rng,
+		)
+		.unwrap();
+
+		// the transfer has no fee, so it does not reveal its asset id
+		assert_eq!(
+			PrivateTransferData::deserialize(payload.as_ref()).asset_id,
+			0
 		);
 
 		// invoke the transfer event
 		assert_ok!(Assets::private_transfer(Origin::signed(1), payload));
 
-		// check the ciphertexts
-		let enc_value_list = EncValueList::get();
-		assert_eq!(enc_value_list.len(), 2 * (i + 1));
-		assert_eq!(enc_value_list[2 * i], receiver_1.ciphertext);
-		assert_eq!(enc_value_list[2 * i + 1], receiver_2.ciphertext);
-
-		let mut ciphertext_1 = [0u8; 48];
-		ciphertext_1[0..16].copy_from_slice(receiver_1.ciphertext.as_ref());
-		ciphertext_1[16..48].copy_from_slice(receiver_1.sender_pk.as_ref());
-		let sk_1 = receivers_full[i * 2 + 1].spend.ecsk.clone();
+		// check the encrypted notes
+		for (receiver, receiver_full) in [
+			(&receiver_1, &receivers_full[i * 2 + 1]),
+			(&receiver_2, &receivers_full[i * 2]),
+		]
+		.iter()
+		{
+			let encrypted_note = Assets::encrypted_note(&receiver.commitment).unwrap();
+			let note = try_decrypt_note(
+				&receiver_full.spend.ecsk,
+				&receiver.commitment,
+				&encrypted_note,
+			)
+			.unwrap();
+			assert_eq!(note.asset_id, TEST_ASSET);
+			assert_eq!(note.value, receiver.value);
+			assert_eq!(note.k, receiver.prepared_data.k);
+			assert_eq!(note.s, receiver.prepared_data.s);
+		}
+		assert_eq!(Assets::note_count(), minted + 2 * (i as u64 + 1));
 		assert_eq!(
-			<MantaCrypto as Ecies>::decrypt(&sk_1, &ciphertext_1),
-			receiver_1.value
+			Assets::note(minted + 2 * i as u64).unwrap().cm,
+			receiver_1.commitment
 		);
-
-		let mut ciphertext_2 = [0u8; 48];
-		ciphertext_2[0..16].copy_from_slice(receiver_2.ciphertext.as_ref());
-		ciphertext_2[16..48].copy_from_slice(receiver_2.sender_pk.as_ref());
-		let sk_2 = receivers_full[i * 2].spend.ecsk.clone();
 		assert_eq!(
-			<MantaCrypto as Ecies>::decrypt(&sk_2, &ciphertext_2),
-			receiver_2.value
+			Assets::note(minted + 2 * i as u64 + 1).unwrap().cm,
+			receiver_2.commitment
 		);
+		assert!(EncValueList::get().is_empty());
 		assert_eq!(PoolBalance::get(TEST_ASSET), pool);
 	}
 
 	// check the resulting status of the ledger storage
 	assert_eq!(TotalSupply::get(TEST_ASSET), 10_000_000);
-	let coin_shards = CoinShards::get();
-	let sn_list = VNList::get();
 	for i in 0usize..size {
-		assert!(coin_shards.exist(&senders[i].commitment));
-		assert!(coin_shards.exist(&receivers_processed[i].commitment));
-		assert_eq!(sn_list[i], senders[i].void_number);
+		assert!(<Assets as LedgerSharding>::exist(&senders[i].commitment));
+		assert!(<Assets as LedgerSharding>::exist(
+			&receivers_processed[i].commitment
+		));
+		assert!(Assets::is_void_number_spent(&senders[i].void_number));
 	}
 }
 
 fn reclaim_test_helper(iter: usize) {
 	// setup
+	assert_ok!(Assets::initialize_ledger(Origin::root()));
 	assert_ok!(Assets::init_asset(
 		Origin::signed(1),
 		TEST_ASSET,
@@ -415,35 +1793,34 @@ fn reclaim_test_helper(iter: usize) {
 	let mut sk = [0u8; 32];
 
 	// load the ZKP keys
-	let mut file = File::open("reclaim_pk.bin").unwrap();
-	let mut reclaim_pk_bytes: Vec<u8> = vec![];
-	file.read_to_end(&mut reclaim_pk_bytes).unwrap();
-	let buf: &[u8] = reclaim_pk_bytes.as_ref();
-	let pk = Groth16Pk::deserialize_unchecked(buf).unwrap();
+	let pk = default_reclaim_zkp_key();
 	let vk = pk.vk.clone();
 	let mut vk_bytes = Vec::new();
 	vk.serialize_uncompressed(&mut vk_bytes).unwrap();
-	let vk = RECLAIM_PK;
-	let vk_checksum = ReclaimZKPKeyChecksum::get();
-	assert_eq!(vk.get_checksum(), vk_checksum);
+	// the proofs are verified against the default key: it must be
+	// the one of the proving key, regenerated with `write_zkp_keys`
+	assert_eq!(vk_bytes, RECLAIM_PK.data.to_vec());
+	assert_eq!(
+		Assets::active_verification_key(CircuitKind::Reclaim),
+		Some(RECLAIM_PK.data.to_vec())
+	);
 
 	for i in 0usize..iter {
-		let coin_shards = CoinShards::get();
-
 		// build sender mata data
 		let sender_1 = senders[i * 2].clone();
 		let sender_2 = senders[i * 2 + 1].clone();
-		let shard_index_1 = sender_1.commitment[0] as usize;
-		let shard_index_2 = sender_2.commitment[0] as usize;
-		let list_1 = coin_shards.shard[shard_index_1].list.clone();
+		let shard_index_1 = Assets::shard_index_of(&sender_1.commitment);
+		let shard_index_2 = Assets::shard_index_of(&sender_2.commitment);
+		let list_1 = Assets::shard_leaves(shard_index_1);
 		let sender_1 = SenderMetaData::build(hash_param.clone(), sender_1, &list_1);
-		let list_2 = coin_shards.shard[shard_index_2].list.clone();
+		let list_2 = Assets::shard_leaves(shard_index_2);
 		let sender_2 = SenderMetaData::build(hash_param.clone(), sender_2, &list_2);
 
 		rng.fill_bytes(&mut sk);
 		let receiver_full =
 			MantaAssetFullReceiver::sample(&commit_param, &sk, &TEST_ASSET, &(), &mut rng);
 		let receiver = receiver_full.prepared.process(&10, &mut rng);
+		let cm = receiver.commitment;
 
 		let reclaim_value =
 			sender_1.asset.priv_info.value + sender_2.asset.priv_info.value - receiver.value;
@@ -457,22 +1834,76 @@ fn reclaim_test_helper(iter: usize) {
 			sender_2.clone(),
 			receiver,
 			reclaim_value,
+			encode_recipient(&3u64).unwrap(),
+			Assets::chain_id(),
 			&mut rng,
+		)
+		.unwrap();
+
+		// tampering with any field of the payload invalidates the proof
+		if i == 0 {
+			let data = ReclaimData::deserialize(payload.as_ref());
+			let reclaim_pvk =
+				Assets::active_prepared_verification_key(CircuitKind::Reclaim).unwrap();
+			assert_eq!(data.try_verify(&reclaim_pvk), Ok(()));
+
+			let tamperings: Vec<Box<dyn Fn(&mut ReclaimData)>> = vec![
+				Box::new(|data| data.asset_id += 1),
+				Box::new(|data| data.reclaim_amount += 1),
+				Box::new(|data| data.recipient[0] ^= 1),
+				Box::new(|data| data.sender_2.void_number[0] ^= 1),
+				Box::new(|data| data.receiver.sender_pk[0] ^= 1),
+				Box::new(|data| data.receiver.cipher[0] ^= 1),
+				Box::new(|data| data.chain_id[0] ^= 1),
+			];
+			for tamper in tamperings.iter() {
+				let mut tampered = data.clone();
+				tamper(&mut tampered);
+				assert_eq!(
+					tampered.try_verify(&reclaim_pvk),
+					Err(VerifyError::PairingCheckFailed)
+				);
+			}
+		}
+
+		// the recipient is bound to the proof
+		let mut tampered = payload;
+		tampered[16..48].copy_from_slice(&encode_recipient(&1u64).unwrap());
+		assert_noop!(
+			Assets::reclaim(Origin::signed(1), tampered),
+			Error::<Test>::ZkpVerificationFail
+		);
+		tampered[47] = 1;
+		assert_noop!(
+			Assets::reclaim(Origin::signed(1), tampered),
+			Error::<Test>::InvalidRecipient
 		);
 
 		// invoke the reclaim event
+		let recipient_balance = Assets::balance(3, TEST_ASSET);
 		assert_ok!(Assets::reclaim(Origin::signed(1), payload));
+		assert_eq!(
+			Assets::balance(3, TEST_ASSET),
+			recipient_balance + reclaim_value
+		);
+
+		// the receiver recovers its note
+		let note = try_decrypt_note(
+			&receiver_full.spend.ecsk,
+			&cm,
+			&Assets::encrypted_note(&cm).unwrap(),
+		)
+		.unwrap();
+		assert_eq!(note.value, 10);
 
 		// check the resulting status of the ledger storage
 		assert_eq!(TotalSupply::get(TEST_ASSET), 10_000_000);
 		pool -= reclaim_value;
 		assert_eq!(PoolBalance::get(TEST_ASSET), pool);
 
-		let sn_list = VNList::get();
-		assert_eq!(sn_list.len(), 2 * (i + 1));
-		assert_eq!(sn_list[i * 2], sender_1.asset.void_number);
-		assert_eq!(sn_list[i * 2 + 1], sender_2.asset.void_number);
+		assert_eq!(VoidNumbers::iter().count(), 2 * (i + 1));
+		assert!(Assets::is_void_number_spent(&sender_1.asset.void_number));
+		assert!(Assets::is_void_number_spent(&sender_2.asset.void_number));
 	}
-	let enc_value_list = EncValueList::get();
-	assert_eq!(enc_value_list.len(), iter);
+	assert_eq!(Assets::note_count(), (size + iter) as u64);
 }
@@ -0,0 +1,154 @@
+// Copyright 2019-2021 Manta Network.
+// This file is part of pallet-manta-pay.
+//
+// pallet-manta-pay is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// pallet-manta-pay is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with pallet-manta-pay.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::*;
+use ark_serialize::CanonicalSerialize;
+use ark_std::rand::{RngCore, SeedableRng};
+use manta_asset::*;
+use manta_crypto::*;
+use rand_chacha::ChaCha20Rng;
+
+/// the incremental merkle tree should agree with the full merkle tree
+/// after every single insertion
+#[test]
+fn test_frontier_root_matches_full_tree() {
+	let hash_param = HashParam::deserialize(HASH_PARAM.data);
+	let mut rng = ChaCha20Rng::from_seed([3u8; 32]);
+
+	let mut frontier = MerkleFrontier::default();
+	let mut list = Vec::new();
+	for _ in 0..33 {
+		let mut cm = [0u8; 32];
+		rng.fill_bytes(&mut cm);
+		list.push(cm);
+
+		let root = frontier.append(&hash_param, &cm);
+		assert_eq!(
+			root,
+			<MantaCrypto as MerkleTree>::root(hash_param.clone(), &list)
+		);
+		assert_eq!(frontier.leaf_count, list.len() as u64);
+	}
+}
+
+/// a frontier rebuilt from a list of leaves should continue
+/// exactly where the original one stopped
+#[test]
+fn test_frontier_from_leaves() {
+	let hash_param = HashParam::deserialize(HASH_PARAM.data);
+	let mut rng = ChaCha20Rng::from_seed([3u8; 32]);
+
+	let mut frontier = MerkleFrontier::default();
+	let mut list = Vec::new();
+	for _ in 0..11 {
+		let mut cm = [0u8; 32];
+		rng.fill_bytes(&mut cm);
+		list.push(cm);
+		frontier.append(&hash_param, &cm);
+	}
+
+	let mut rebuilt = MerkleFrontier::from_leaves(&hash_param, &list);
+	assert_eq!(rebuilt, frontier);
+
+	let mut cm = [0u8; 32];
+	rng.fill_bytes(&mut cm);
+	assert_eq!(
+		rebuilt.append(&hash_param, &cm),
+		frontier.append(&hash_param, &cm)
+	);
+}
+
+/// the tracked witnesses should open to the root of the full merkle tree
+/// after every single insertion
+#[test]
+fn test_witness_tracker_matches_full_tree() {
+	let hash_param = HashParam::deserialize(HASH_PARAM.data);
+	let mut rng = ChaCha20Rng::from_seed([3u8; 32]);
+
+	let mut tracker = WitnessTracker::default();
+	let mut list = Vec::new();
+	for i in 0..33 {
+		let mut cm = [0u8; 32];
+		rng.fill_bytes(&mut cm);
+		list.push(cm);
+
+		// track one leaf out of three
+		let root = tracker.append(&hash_param, &cm, i % 3 == 0);
+		let expected = <MantaCrypto as MerkleTree>::root(hash_param.clone(), &list);
+		assert_eq!(root, expected);
+		assert_eq!(tracker.leaf_count(), list.len() as u64);
+
+		for (leaf_index, leaf) in list.iter().enumerate() {
+			if leaf_index % 3 != 0 {
+				assert_eq!(tracker.witness(leaf), Err(WitnessError::UnknownLeaf));
+				continue;
+			}
+			let witness = tracker.witness(leaf).unwrap();
+			assert_eq!(witness.leaf_index, leaf_index as u64);
+			let mut witness_root = [0u8; 32];
+			witness
+				.root(&hash_param)
+				.serialize(witness_root.as_mut())
+				.unwrap();
+			assert_eq!(witness_root, expected);
+		}
+	}
+
+	// the witness of an untracked leaf is gone
+	assert!(tracker.untrack(&list[0]).is_some());
+	assert_eq!(tracker.witness(&list[0]), Err(WitnessError::UnknownLeaf));
+}
+
+/// the sender meta data built from a tracked witness should be the same
+/// as the one built from the list of leaves
+#[test]
+fn test_sender_meta_data_from_witness() {
+	let hash_param = HashParam::deserialize(HASH_PARAM.data);
+	let commit_param = CommitmentParam::deserialize(COMMIT_PARAM.data);
+	let mut rng = ChaCha20Rng::from_seed([3u8; 32]);
+	let mut sk = [0u8; 32];
+
+	rng.fill_bytes(&mut sk);
+	let sender = MantaAsset::sample(&commit_param, &sk, &TEST_ASSET, &10, &mut rng);
+	rng.fill_bytes(&mut sk);
+	let other = MantaAsset::sample(&commit_param, &sk, &TEST_ASSET, &10, &mut rng);
+
+	let mut list = Vec::new();
+	for _ in 0..5 {
+		let mut cm = [0u8; 32];
+		rng.fill_bytes(&mut cm);
+		list.push(cm);
+	}
+	list.insert(2, sender.commitment);
+
+	let tracker =
+		WitnessTracker::from_leaves(&hash_param, &list, |_, leaf| *leaf == sender.commitment);
+	let witness = tracker.witness(&sender.commitment).unwrap().clone();
+	let expected = SenderMetaData::build(hash_param.clone(), sender.clone(), &list);
+	let built = SenderMetaData::from_witness(&hash_param, sender.clone(), witness.clone()).unwrap();
+	assert_eq!(built.root, expected.root);
+	assert_eq!(built.membership, expected.membership);
+
+	// the witness of another coin, or a coin that is not in the ledger
+	assert_eq!(
+		SenderMetaData::from_witness(&hash_param, other.clone(), witness).err(),
+		Some(WitnessError::LeafMismatch)
+	);
+	assert_eq!(
+		SenderMetaData::try_build(hash_param, other, &list).err(),
+		Some(WitnessError::UnknownLeaf)
+	);
+}
@@ -16,3 +16,7 @@
 
 mod crypto;
 mod frame;
+mod ledger;
+mod payload;
+mod planner;
+mod wallet;
@@ -0,0 +1,389 @@
+// Copyright 2019-2021 Manta Network.
+// This file is part of pallet-manta-pay.
+//
+// pallet-manta-pay is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// pallet-manta-pay is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with pallet-manta-pay.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::*;
+use ark_serialize::CanonicalSerialize;
+use ark_std::rand::{RngCore, SeedableRng};
+use manta_asset::*;
+use manta_crypto::*;
+use rand_chacha::ChaCha20Rng;
+
+// a well formed private transfer payload; the proof is not a valid proof
+// of the statement, but it is a valid encoding of a proof
+fn well_formed_transfer_data() -> PrivateTransferData {
+	let hash_param = HashParam::deserialize(HASH_PARAM.data);
+	let commit_param = CommitmentParam::deserialize(COMMIT_PARAM.data);
+	let mut rng = ChaCha20Rng::from_seed([3u8; 32]);
+	let mut sk = [0u8; 32];
+
+	let mut senders = Vec::new();
+	for value in [10u64, 11].iter() {
+		rng.fill_bytes(&mut sk);
+		let asset = MantaAsset::sample(&commit_param, &sk, &TEST_ASSET, value, &mut rng);
+		senders.push(SenderData {
+			k: asset.pub_info.k,
+			void_number: asset.void_number,
+			root: <MantaCrypto as MerkleTree>::root(hash_param.clone(), &[asset.commitment]),
+		});
+	}
+
+	let mut receivers = Vec::new();
+	for value in [11u64, 9].iter() {
+		rng.fill_bytes(&mut sk);
+		let receiver_full =
+			MantaAssetFullReceiver::sample(&commit_param, &sk, &TEST_ASSET, &(), &mut rng);
+		let receiver = receiver_full.prepared.process(value, &mut rng);
+		let note = Note {
+			asset_id: TEST_ASSET,
+			value: *value,
+			k: receiver.prepared_data.k,
+			s: receiver.prepared_data.s,
+		};
+		let encrypted_note = encrypt_note(
+			&note,
+			&receiver.prepared_data.ecpk,
+			&receiver.commitment,
+			&mut rng,
+		);
+		receivers.push(ReceiverData {
+			k: receiver.prepared_data.k,
+			cm: receiver.commitment,
+			sender_pk: encrypted_note.sender_pk,
+			cipher: encrypted_note.cipher,
+		});
+	}
+
+	let mut proof = [0u8; 192];
+	Groth16Proof::default().serialize(proof.as_mut()).unwrap();
+
+	PrivateTransferData {
+		asset_id: TEST_ASSET,
+		fee: 1,
+		sender_1: senders[0].clone(),
+		sender_2: senders[1].clone(),
+		receiver_1: receivers[0].clone(),
+		receiver_2: receivers[1].clone(),
+		chain_id: [5u8; 32],
+		proof,
+	}
+}
+
+// the sizes in the docs of the serialization are the sizes of the serialized data
+#[test]
+fn test_serialized_sizes() {
+	assert_eq!(MINT_PAYLOAD_SIZE, 112);
+	assert_eq!(SENDER_DATA_SIZE, 96);
+	assert_eq!(RECEIVER_DATA_SIZE, 192);
+	assert_eq!(NOTE_CIPHERTEXT_SIZE, 80 + 16);
+
+	let mut bytes = Vec::new();
+	MintData::default().serialize(&mut bytes);
+	assert_eq!(bytes.len(), MINT_PAYLOAD_SIZE);
+
+	let data = well_formed_transfer_data();
+	let mut bytes = Vec::new();
+	data.sender_1.serialize(&mut bytes);
+	assert_eq!(bytes.len(), SENDER_DATA_SIZE);
+	let mut bytes = Vec::new();
+	data.receiver_1.serialize(&mut bytes);
+	assert_eq!(bytes.len(), RECEIVER_DATA_SIZE);
+	let mut bytes = Vec::new();
+	data.serialize(&mut bytes);
+	assert_eq!(bytes.len(), PRIVATE_TRANSFER_PAYLOAD_SIZE);
+
+	let mut bytes = Vec::new();
+	ReclaimData::default().serialize(&mut bytes);
+	assert_eq!(bytes.len(), RECLAIM_PAYLOAD_SIZE);
+}
+
+#[test]
+fn test_try_deserialize_mint_data() {
+	let commit_param = CommitmentParam::deserialize(COMMIT_PARAM.data);
+	let mut rng = ChaCha20Rng::from_seed([3u8; 32]);
+	let mut sk = [0u8; 32];
+	rng.fill_bytes(&mut sk);
+	let asset = MantaAsset::sample(&commit_param, &sk, &TEST_ASSET, &10, &mut rng);
+	let payload = generate_mint_payload(&asset).unwrap();
+
+	assert_eq!(
+		MintData::try_deserialize(payload.as_ref()),
+		Ok(MintData::deserialize(payload.as_ref()))
+	);
+	assert_eq!(
+		MintData::try_deserialize(payload[..MINT_PAYLOAD_SIZE - 1].as_ref()),
+		Err(PayloadError::InvalidPayload)
+	);
+
+	let mut data = MintData::deserialize(payload.as_ref());
+	data.cm = [0xffu8; 32];
+	let mut payload = [0u8; MINT_PAYLOAD_SIZE];
+	data.serialize(payload.as_mut());
+	assert_eq!(
+		MintData::try_deserialize(payload.as_ref()),
+		Err(PayloadError::InvalidCurvePoint)
+	);
+}
+
+#[test]
+fn test_try_deserialize_private_transfer_data() {
+	let data = well_formed_transfer_data();
+	let mut payload = [0u8; PRIVATE_TRANSFER_PAYLOAD_SIZE];
+	data.serialize(payload.as_mut());
+	assert_eq!(
+		PrivateTransferData::try_deserialize(payload.as_ref()),
+		Ok(data.clone())
+	);
+	assert_eq!(
+		PrivateTransferData::try_deserialize(payload[..300].as_ref()),
+		Err(PayloadError::InvalidPayload)
+	);
+
+	let mut corrupted = data.clone();
+	corrupted.proof = [0xffu8; 192];
+	corrupted.serialize(payload.as_mut());
+	assert_eq!(
+		PrivateTransferData::try_deserialize(payload.as_ref()),
+		Err(PayloadError::InvalidProofEncoding)
+	);
+
+	let mut corrupted = data.clone();
+	corrupted.sender_2.root = [0xffu8; 32];
+	corrupted.serialize(payload.as_mut());
+	assert_eq!(
+		PrivateTransferData::try_deserialize(payload.as_ref()),
+		Err(PayloadError::InvalidCurvePoint)
+	);
+
+	let mut corrupted = data;
+	corrupted.receiver_1.cm = [0xffu8; 32];
+	corrupted.serialize(payload.as_mut());
+	assert_eq!(
+		PrivateTransferData::try_deserialize(payload.as_ref()),
+		Err(PayloadError::InvalidCurvePoint)
+	);
+}
+
+#[test]
+fn test_try_deserialize_reclaim_data() {
+	let transfer_data = well_formed_transfer_data();
+	let data = ReclaimData {
+		asset_id: TEST_ASSET,
+		reclaim_amount: 10,
+		recipient: encode_recipient(&1u64).unwrap(),
+		sender_1: transfer_data.sender_1,
+		sender_2: transfer_data.sender_2,
+		receiver: transfer_data.receiver_1,
+		chain_id: transfer_data.chain_id,
+		proof: transfer_data.proof,
+	};
+	let mut payload = [0u8; RECLAIM_PAYLOAD_SIZE];
+	data.serialize(payload.as_mut());
+	assert_eq!(
+		ReclaimData::try_deserialize(payload.as_ref()),
+		Ok(data.clone())
+	);
+	assert_eq!(
+		ReclaimData::try_deserialize(payload[..RECLAIM_PAYLOAD_SIZE - 1].as_ref()),
+		Err(PayloadError::InvalidPayload)
+	);
+
+	let mut corrupted = data.clone();
+	corrupted.proof = [0xffu8; 192];
+	corrupted.serialize(payload.as_mut());
+	assert_eq!(
+		ReclaimData::try_deserialize(payload.as_ref()),
+		Err(PayloadError::InvalidProofEncoding)
+	);
+
+	let mut corrupted = data;
+	corrupted.sender_1.k = [0xffu8; 32];
+	corrupted.serialize(payload.as_mut());
+	assert_eq!(
+		ReclaimData::try_deserialize(payload.as_ref()),
+		Err(PayloadError::InvalidCurvePoint)
+	);
+}
+
+#[test]
+fn test_note_encryption() {
+	let commit_param = CommitmentParam::deserialize(COMMIT_PARAM.data);
+	let mut rng = ChaCha20Rng::from_seed([3u8; 32]);
+	let mut sk = [0u8; 32];
+
+	rng.fill_bytes(&mut sk);
+	let receiver_full =
+		MantaAssetFullReceiver::sample(&commit_param, &sk, &TEST_ASSET, &(), &mut rng);
+	let receiver = receiver_full.prepared.process(&10, &mut rng);
+	rng.fill_bytes(&mut sk);
+	let other_receiver_full =
+		MantaAssetFullReceiver::sample(&commit_param, &sk, &TEST_ASSET, &(), &mut rng);
+
+	let note = Note {
+		asset_id: TEST_ASSET,
+		value: 10,
+		k: receiver.prepared_data.k,
+		s: receiver.prepared_data.s,
+	};
+	let cm = receiver.commitment;
+	let encrypted_note = encrypt_note(&note, &receiver.prepared_data.ecpk, &cm, &mut rng);
+	let ecsk = &receiver_full.spend.ecsk;
+	assert_eq!(
+		try_decrypt_note(ecsk, &cm, &encrypted_note),
+		Some(note.clone())
+	);
+
+	// the ephemeral key is fresh for every note
+	let other_encrypted_note = encrypt_note(&note, &receiver.prepared_data.ecpk, &cm, &mut rng);
+	assert_ne!(other_encrypted_note, encrypted_note);
+	assert_eq!(
+		try_decrypt_note(ecsk, &cm, &other_encrypted_note),
+		Some(note)
+	);
+
+	// the note is only readable by its receiver
+	assert_eq!(
+		try_decrypt_note(&other_receiver_full.spend.ecsk, &cm, &encrypted_note),
+		None
+	);
+
+	// the note is bound to its commitment
+	let mut other_cm = cm;
+	other_cm[0] ^= 1;
+	assert_eq!(try_decrypt_note(ecsk, &other_cm, &encrypted_note), None);
+
+	// the note is authenticated
+	for i in [0, 32, NOTE_CIPHERTEXT_SIZE - 1].iter() {
+		let mut tampered = encrypted_note.clone();
+		tampered.cipher[*i] ^= 1;
+		assert_eq!(try_decrypt_note(ecsk, &cm, &tampered), None);
+	}
+	let mut tampered = encrypted_note;
+	tampered.sender_pk[0] ^= 1;
+	assert_eq!(try_decrypt_note(ecsk, &cm, &tampered), None);
+}
+
+#[test]
+fn test_encode_recipient() {
+	let mut expected = [0u8; 32];
+	expected[..8].copy_from_slice(&3u64.to_le_bytes());
+	assert_eq!(encode_recipient(&3u64), Ok(expected));
+	assert_eq!(encode_recipient(&[7u8; 32]), Ok([7u8; 32]));
+
+	// the encoding of a vector is prefixed with its length
+	assert_eq!(
+		encode_recipient(&vec![7u8; 32]),
+		Err(PayloadGenError::RecipientTooLong)
+	);
+}
+
+#[test]
+fn test_generate_mint_payload_checks_the_asset() {
+	let commit_param = CommitmentParam::deserialize(COMMIT_PARAM.data);
+	let mut rng = ChaCha20Rng::from_seed([3u8; 32]);
+	let mut sk = [0u8; 32];
+
+	rng.fill_bytes(&mut sk);
+	let asset = MantaAsset::sample(&commit_param, &sk, &TEST_ASSET, &0, &mut rng);
+	assert_eq!(
+		generate_mint_payload(&asset).err(),
+		Some(PayloadGenError::ZeroValue)
+	);
+
+	rng.fill_bytes(&mut sk);
+	let mut asset = MantaAsset::sample(&commit_param, &sk, &TEST_ASSET, &10, &mut rng);
+	assert!(generate_mint_payload(&asset).is_ok());
+	asset.priv_info.value = 11;
+	assert_eq!(
+		generate_mint_payload(&asset).err(),
+		Some(PayloadGenError::InvalidCommitment)
+	);
+}
+
+#[test]
+fn test_generate_payload_checks_the_inputs() {
+	let hash_param = HashParam::deserialize(HASH_PARAM.data);
+	let commit_param = CommitmentParam::deserialize(COMMIT_PARAM.data);
+	let mut rng = ChaCha20Rng::from_seed([3u8; 32]);
+	let mut sk = [0u8; 32];
+
+	// the inputs are rejected before the proving key is used
+	let pk = default_transfer_zkp_key();
+
+	// two senders of 10 and 20
+	let mut coins = Vec::new();
+	for value in [10u64, 20].iter() {
+		rng.fill_bytes(&mut sk);
+		coins.push(MantaAsset::sample(
+			&commit_param,
+			&sk,
+			&TEST_ASSET,
+			value,
+			&mut rng,
+		));
+	}
+	let leaves: Vec<[u8; 32]> = coins.iter().map(|coin| coin.commitment).collect();
+	let senders: Vec<SenderMetaData> = coins
+		.iter()
+		.map(|coin| SenderMetaData::build(hash_param.clone(), coin.clone(), &leaves))
+		.collect();
+
+	let mut receiver = |asset_id: AssetId, value: u64, rng: &mut ChaCha20Rng| {
+		rng.fill_bytes(&mut sk);
+		MantaAssetFullReceiver::sample(&commit_param, &sk, &asset_id, &(), rng)
+			.prepared
+			.process(&value, rng)
+	};
+	let mut transfer = |senders: &[SenderMetaData], values: [u64; 2], asset_id, fee| {
+		generate_private_transfer_payload(
+			commit_param.clone(),
+			hash_param.clone(),
+			&pk,
+			senders[0].clone(),
+			senders[1].clone(),
+			receiver(TEST_ASSET, values[0], &mut rng),
+			receiver(asset_id, values[1], &mut rng),
+			fee,
+			[0u8; 32],
+			&mut rng,
+		)
+		.err()
+	};
+
+	// 10 + 20 != 10 + 15 + 4
+	assert_eq!(
+		transfer(&senders, [10, 15], TEST_ASSET, 4),
+		Some(PayloadGenError::ValueMismatch)
+	);
+	assert_eq!(
+		transfer(&senders, [10, 15], TEST_ASSET + 1, 5),
+		Some(PayloadGenError::AssetMismatch)
+	);
+
+	// the membership of the other sender
+	let mut forged = senders.clone();
+	forged[0].membership = senders[1].membership.clone();
+	assert_eq!(
+		transfer(&forged, [10, 15], TEST_ASSET, 5),
+		Some(PayloadGenError::InvalidMembership)
+	);
+	// a membership that does not open to the root
+	let mut forged = senders.clone();
+	forged[1].root = HashOutput::default();
+	assert_eq!(
+		transfer(&forged, [10, 15], TEST_ASSET, 5),
+		Some(PayloadGenError::InvalidMembership)
+	);
+}
@@ -0,0 +1,227 @@
+// Copyright 2019-2021 Manta Network.
+// This file is part of pallet-manta-pay.
+//
+// pallet-manta-pay is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// pallet-manta-pay is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with pallet-manta-pay.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::{planner::*, *};
+use ark_std::rand::{RngCore, SeedableRng};
+use manta_asset::*;
+use manta_crypto::*;
+use rand_chacha::ChaCha20Rng;
+
+// sample a coin of `value` for each of `values`
+fn sample_coins(asset_id: AssetId, values: &[u64], rng: &mut ChaCha20Rng) -> Vec<MantaAsset> {
+	let commit_param = CommitmentParam::deserialize(COMMIT_PARAM.data);
+	let mut sk = [0u8; 32];
+	values
+		.iter()
+		.map(|value| {
+			rng.fill_bytes(&mut sk);
+			MantaAsset::sample(&commit_param, &sk, &asset_id, value, rng)
+		})
+		.collect()
+}
+
+// a fresh address of `asset_id`
+fn sample_address(asset_id: AssetId, rng: &mut ChaCha20Rng) -> MantaAssetShieldedAddress {
+	let commit_param = CommitmentParam::deserialize(COMMIT_PARAM.data);
+	let mut sk = [0u8; 32];
+	rng.fill_bytes(&mut sk);
+	MantaAssetFullReceiver::sample(&commit_param, &sk, &asset_id, &(), rng).prepared
+}
+
+#[test]
+fn test_plan_transfer_of_two_coins() {
+	let mut rng = ChaCha20Rng::from_seed([3u8; 32]);
+	let mut coins = sample_coins(TEST_ASSET, &[10, 50, 40], &mut rng);
+	coins.extend(sample_coins(TEST_ASSET + 1, &[100, 100], &mut rng));
+	let recipient = sample_address(TEST_ASSET, &mut rng);
+
+	// the two largest coins pay for the transfer and its fee
+	let plan = plan_transfer(&coins, recipient.clone(), 80, 1).unwrap();
+	assert_eq!(plan.asset_id, TEST_ASSET);
+	assert_eq!(plan.rounds(), 1);
+	assert_eq!(plan.steps.len(), 1);
+	let step = &plan.steps[0];
+	assert_eq!(
+		step.inputs,
+		[
+			StepInput::Coin(coins[1].commitment),
+			StepInput::Coin(coins[2].commitment)
+		]
+	);
+	assert_eq!(step.change, vec![9]);
+	assert_eq!(step.circuit(), CircuitKind::Transfer);
+	match step.kind {
+		StepKind::Transfer { value, .. } => assert_eq!(value, 80),
+		_ => panic!("the last step should be the transfer"),
+	}
+
+	// a transfer without change still creates a change coin, of zero value
+	let plan = plan_transfer(&coins, recipient.clone(), 89, 1).unwrap();
+	assert_eq!(plan.steps[0].change, vec![0]);
+	assert_eq!(plan.steps[0].circuit(), CircuitKind::Transfer);
+
+	// the largest coin pays on its own, padded with the smallest coin
+	let plan = plan_transfer(&coins, recipient, 30, 1).unwrap();
+	assert_eq!(plan.steps.len(), 1);
+	assert_eq!(
+		plan.steps[0].inputs,
+		[
+			StepInput::Coin(coins[1].commitment),
+			StepInput::Coin(coins[0].commitment)
+		]
+	);
+	assert_eq!(plan.steps[0].change, vec![29]);
+}
+
+#[test]
+fn test_plan_merges_coins() {
+	let mut rng = ChaCha20Rng::from_seed([3u8; 32]);
+	let coins = sample_coins(TEST_ASSET, &[10, 20, 30, 40, 50, 5], &mut rng);
+
+	// 50 + 40 + 30 + 20 + 10 = 150 = 140 + 3 merges * 2 + 4
+	let plan = plan_reclaim(&coins, TEST_ASSET, [1u8; 32], 140, 2).unwrap();
+	let reclaim = plan.steps.last().unwrap();
+	assert_eq!(reclaim.change, vec![4]);
+
+	// 5 coins, merged into 3, then 2
+	assert_eq!(plan.rounds(), 3);
+	assert_eq!(plan.round(0).len(), 2);
+	assert_eq!(plan.round(1).len(), 1);
+	assert_eq!(plan.round(2), vec![plan.steps.len() - 1]);
+	assert_eq!(reclaim.circuit(), CircuitKind::Reclaim);
+	for step in plan.steps.iter() {
+		for input in step.inputs.iter() {
+			match input {
+				StepInput::Coin(cm) => assert_ne!(*cm, coins[5].commitment),
+				StepInput::Change(index) => {
+					assert!(plan.steps[*index].round < step.round);
+					assert!(matches!(plan.steps[*index].kind, StepKind::Merge));
+				}
+			}
+		}
+	}
+
+	// the largest coins are merged with the smallest ones,
+	// into the merged coin and a zero-value coin
+	assert_eq!(
+		plan.steps[0].inputs,
+		[
+			StepInput::Coin(coins[4].commitment),
+			StepInput::Coin(coins[0].commitment)
+		]
+	);
+	assert_eq!(
+		plan.steps[1].inputs,
+		[
+			StepInput::Coin(coins[3].commitment),
+			StepInput::Coin(coins[1].commitment)
+		]
+	);
+	for step in plan
+		.steps
+		.iter()
+		.filter(|step| matches!(step.kind, StepKind::Merge))
+	{
+		assert_eq!(step.circuit(), CircuitKind::Transfer);
+		assert_eq!(step.change.len(), 2);
+		assert_eq!(step.change[1], 0);
+	}
+
+	// every merge conserves the value, minus the fee
+	let value_of = |input: &StepInput| match input {
+		StepInput::Coin(cm) => {
+			coins
+				.iter()
+				.find(|coin| coin.commitment == *cm)
+				.unwrap()
+				.priv_info
+				.value
+		}
+		StepInput::Change(index) => plan.steps[*index].change[0],
+	};
+	for step in plan.steps.iter() {
+		let input: u64 = step.inputs.iter().map(value_of).sum();
+		let output: u64 = step.change.iter().sum();
+		match step.kind {
+			StepKind::Merge => assert_eq!(input, output + 2),
+			StepKind::Reclaim { value, .. } => assert_eq!(input, output + value),
+			_ => panic!("a reclaim plan has no transfer"),
+		}
+	}
+
+	// the execution follows the rounds
+	let execution = PlanExecution::new(plan);
+	assert!(execution.inputs(0).is_ok());
+	assert_eq!(
+		execution.inputs(execution.plan().steps.len() - 1).err(),
+		Some(PlanError::StepNotReady)
+	);
+	assert_eq!(execution.inputs(100).err(), Some(PlanError::UnknownStep));
+}
+
+#[test]
+fn test_plan_errors() {
+	let mut rng = ChaCha20Rng::from_seed([3u8; 32]);
+	let coins = sample_coins(TEST_ASSET, &[10, 20, 30], &mut rng);
+	let recipient = sample_address(TEST_ASSET, &mut rng);
+
+	assert_eq!(
+		plan_transfer(&coins[..1], recipient.clone(), 5, 0).err(),
+		Some(PlanError::NotEnoughCoins)
+	);
+	assert_eq!(
+		plan_reclaim(&coins, TEST_ASSET + 1, [1u8; 32], 5, 0).err(),
+		Some(PlanError::NotEnoughCoins)
+	);
+	// 60 - 1 merge fee - 1 transfer fee < 59
+	assert_eq!(
+		plan_transfer(&coins, recipient.clone(), 59, 1).err(),
+		Some(PlanError::InsufficientFunds)
+	);
+	assert!(plan_transfer(&coins, recipient, 58, 1).is_ok());
+}
+
+#[test]
+fn test_plan_single_coin() {
+	let mut rng = ChaCha20Rng::from_seed([3u8; 32]);
+	let coins = sample_coins(TEST_ASSET, &[0, 50], &mut rng);
+	let recipient = sample_address(TEST_ASSET, &mut rng);
+
+	// the coin is spent along with the zero-value coin
+	let inputs = [
+		StepInput::Coin(coins[1].commitment),
+		StepInput::Coin(coins[0].commitment),
+	];
+	let plan = plan_transfer(&coins, recipient.clone(), 30, 1).unwrap();
+	assert_eq!(plan.steps.len(), 1);
+	assert_eq!(plan.steps[0].inputs, inputs);
+	assert_eq!(plan.steps[0].change, vec![19]);
+	assert_eq!(plan.steps[0].circuit(), CircuitKind::Transfer);
+	assert_eq!(
+		plan_transfer(&coins, recipient, 50, 1).err(),
+		Some(PlanError::InsufficientFunds)
+	);
+
+	let plan = plan_reclaim(&coins, TEST_ASSET, [1u8; 32], 40, 1).unwrap();
+	assert_eq!(plan.steps.len(), 1);
+	assert_eq!(plan.steps[0].inputs, inputs);
+	assert_eq!(plan.steps[0].change, vec![10]);
+	assert_eq!(plan.steps[0].circuit(), CircuitKind::Reclaim);
+	assert_eq!(
+		plan_reclaim(&coins, TEST_ASSET, [1u8; 32], 51, 1).err(),
+		Some(PlanError::InsufficientFunds)
+	);
+}
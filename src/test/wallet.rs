@@ -0,0 +1,221 @@
+// Copyright 2019-2021 Manta Network.
+// This file is part of pallet-manta-pay.
+//
+// pallet-manta-pay is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// pallet-manta-pay is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with pallet-manta-pay.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::{wallet::*, *};
+use ark_std::rand::{CryptoRng, RngCore, SeedableRng};
+use manta_asset::*;
+use manta_crypto::*;
+use rand_chacha::ChaCha20Rng;
+
+// the note record of a processed receiver at `(shard_index, leaf_index)`
+fn note_record<R: RngCore + CryptoRng>(
+	receiver: &MantaAssetProcessedReceiver,
+	shard_index: u32,
+	leaf_index: u64,
+	rng: &mut R,
+) -> NoteRecord {
+	let note = Note {
+		asset_id: receiver.prepared_data.asset_id,
+		value: receiver.value,
+		k: receiver.prepared_data.k,
+		s: receiver.prepared_data.s,
+	};
+	NoteRecord {
+		cm: receiver.commitment,
+		shard_index,
+		leaf_index,
+		encrypted_note: encrypt_note(
+			&note,
+			&receiver.prepared_data.ecpk,
+			&receiver.commitment,
+			rng,
+		),
+	}
+}
+
+#[test]
+fn test_wallet_scans_owned_coins() {
+	let hash_param = HashParam::deserialize(HASH_PARAM.data);
+	let commit_param = CommitmentParam::deserialize(COMMIT_PARAM.data);
+	let mut rng = ChaCha20Rng::from_seed([3u8; 32]);
+	let mut sk = [0u8; 32];
+
+	// two addresses of the wallet, and the address of someone else
+	let mut receivers_full = Vec::new();
+	for _ in 0..3 {
+		rng.fill_bytes(&mut sk);
+		receivers_full.push(MantaAssetFullReceiver::sample(
+			&commit_param,
+			&sk,
+			&TEST_ASSET,
+			&(),
+			&mut rng,
+		));
+	}
+	let mut wallet = Wallet::new(hash_param.clone(), commit_param.clone());
+	wallet.add_address(AddressSecret::from(&receivers_full[0]));
+	wallet.add_address(AddressSecret::from(&receivers_full[1]));
+
+	// a coin minted by the user
+	rng.fill_bytes(&mut sk);
+	let minted = MantaAsset::sample(&commit_param, &sk, &TEST_ASSET, &40, &mut rng);
+	wallet.add_coin(minted.clone(), 0, 0);
+
+	// the ledger: shard 0 holds the minted coin and the coin of the first
+	// address, shard 1 the coin of someone else and the coin of the second one
+	let receiver_1 = receivers_full[0].prepared.process(&10, &mut rng);
+	let receiver_2 = receivers_full[1].prepared.process(&30, &mut rng);
+	let other_receiver = receivers_full[2].prepared.process(&20, &mut rng);
+	let shard_0 = vec![minted.commitment, receiver_1.commitment];
+	let shard_1 = vec![other_receiver.commitment, receiver_2.commitment];
+	assert_eq!(
+		wallet.sync_leaf(0, 1, shard_0[1]),
+		Err(WalletError::LeafOutOfOrder)
+	);
+	for (shard_index, leaves) in [&shard_0, &shard_1].iter().enumerate() {
+		for (leaf_index, cm) in leaves.iter().enumerate() {
+			assert_eq!(
+				wallet.sync_leaf(shard_index as u32, leaf_index as u64, *cm),
+				Ok(())
+			);
+		}
+	}
+
+	// trial-decrypt the notes
+	let notes = vec![
+		note_record(&receiver_1, 0, 1, &mut rng),
+		note_record(&other_receiver, 1, 0, &mut rng),
+		note_record(&receiver_2, 1, 1, &mut rng),
+	];
+	let coin = wallet.scan_note(&notes[0]).unwrap().clone();
+	assert_eq!(coin.asset.commitment, receiver_1.commitment);
+	assert_eq!(coin.asset.priv_info.value, 10);
+	assert_eq!((coin.shard_index, coin.leaf_index), (0, 1));
+	assert!(!coin.spent);
+	assert!(wallet.scan_note(&notes[1]).is_none());
+	assert_eq!(
+		wallet.scan_note(&notes[2]).unwrap().asset.priv_info.value,
+		30
+	);
+	// a note is only scanned once
+	assert!(wallet.scan_note(&notes[0]).is_none());
+
+	// the rebuilt coin opens `k = com(pk || rho, r)`
+	let pub_info = &coin.asset.pub_info;
+	assert!(<MantaCrypto as Commitment>::check_commitment(
+		&commit_param,
+		&[pub_info.pk.as_ref(), pub_info.rho.as_ref()].concat(),
+		&pub_info.r,
+		&pub_info.k
+	));
+	assert_eq!(pub_info.k, receiver_1.prepared_data.k);
+	assert_eq!(pub_info.s, receiver_1.prepared_data.s);
+
+	assert_eq!(wallet.unspent_coins().len(), 3);
+	assert_eq!(wallet.balance(TEST_ASSET), 80);
+	assert_eq!(wallet.balance(TEST_ASSET + 1), 0);
+
+	// the sender meta data is built against the synced leaves
+	let sender = wallet.sender_meta_data(&minted.commitment).unwrap();
+	let expected = SenderMetaData::build(hash_param.clone(), minted.clone(), &shard_0);
+	assert_eq!(sender.root, expected.root);
+	assert_eq!(sender.membership, expected.membership);
+	assert_eq!(sender.asset.commitment, minted.commitment);
+	assert_eq!(
+		wallet.sender_meta_data(&other_receiver.commitment).err(),
+		Some(WalletError::UnknownCoin)
+	);
+
+	// spend the minted coin
+	wallet.sync_void_number(&minted.void_number);
+	assert!(wallet.coin(&minted.commitment).unwrap().spent);
+	assert_eq!(wallet.balance(TEST_ASSET), 40);
+	assert_eq!(
+		wallet.sender_meta_data(&minted.commitment).err(),
+		Some(WalletError::CoinSpent)
+	);
+	wallet.sync_void_number(&coin.asset.void_number);
+	assert_eq!(wallet.balance(TEST_ASSET), 30);
+}
+
+#[test]
+fn test_wallet_drops_notes_that_do_not_open_the_commitment() {
+	let hash_param = HashParam::deserialize(HASH_PARAM.data);
+	let commit_param = CommitmentParam::deserialize(COMMIT_PARAM.data);
+	let mut rng = ChaCha20Rng::from_seed([3u8; 32]);
+	let mut sk = [0u8; 32];
+
+	rng.fill_bytes(&mut sk);
+	let receiver_full =
+		MantaAssetFullReceiver::sample(&commit_param, &sk, &TEST_ASSET, &(), &mut rng);
+	let mut wallet = Wallet::new(hash_param, commit_param);
+	wallet.add_address(AddressSecret::from(&receiver_full));
+	let receiver = receiver_full.prepared.process(&10, &mut rng);
+
+	// the note of the coin claims another value
+	let mut forged = receiver.clone();
+	forged.value = 1000;
+	assert!(wallet
+		.scan_note(&note_record(&forged, 0, 0, &mut rng))
+		.is_none());
+
+	// the note of the coin claims another asset
+	let mut forged = receiver.clone();
+	forged.prepared_data.asset_id = TEST_ASSET + 1;
+	assert!(wallet
+		.scan_note(&note_record(&forged, 0, 0, &mut rng))
+		.is_none());
+	assert!(wallet.unspent_coins().is_empty());
+
+	let coin = wallet
+		.scan_note(&note_record(&receiver, 0, 0, &mut rng))
+		.unwrap();
+	assert_eq!(coin.asset.priv_info.value, 10);
+}
+
+#[test]
+fn test_wallet_tracks_spent_coins_in_any_order() {
+	let hash_param = HashParam::deserialize(HASH_PARAM.data);
+	let commit_param = CommitmentParam::deserialize(COMMIT_PARAM.data);
+	let mut rng = ChaCha20Rng::from_seed([3u8; 32]);
+	let mut sk = [0u8; 32];
+
+	rng.fill_bytes(&mut sk);
+	let spent = MantaAsset::sample(&commit_param, &sk, &TEST_ASSET, &10, &mut rng);
+	rng.fill_bytes(&mut sk);
+	let unspent = MantaAsset::sample(&commit_param, &sk, &TEST_ASSET, &20, &mut rng);
+
+	// the void number is synced before the coin is known
+	let mut wallet = Wallet::new(hash_param, commit_param);
+	wallet.sync_void_number(&spent.void_number);
+	wallet.add_coin(spent.clone(), 0, 0);
+	wallet.add_coin(unspent.clone(), 0, 1);
+	assert!(wallet.coin(&spent.commitment).unwrap().spent);
+	assert_eq!(wallet.balance(TEST_ASSET), 20);
+
+	// the coin is not in the synced leaves yet
+	assert_eq!(
+		wallet.sender_meta_data(&unspent.commitment).err(),
+		Some(WalletError::CoinNotInLedger)
+	);
+	assert_eq!(wallet.sync_leaf(0, 0, spent.commitment), Ok(()));
+	assert_eq!(
+		wallet.sender_meta_data(&unspent.commitment).err(),
+		Some(WalletError::CoinNotInLedger)
+	);
+	assert_eq!(wallet.sync_leaf(0, 1, unspent.commitment), Ok(()));
+	assert!(wallet.sender_meta_data(&unspent.commitment).is_ok());
+}
@@ -0,0 +1,294 @@
+// Copyright 2019-2021 Manta Network.
+// This file is part of pallet-manta-pay.
+//
+// pallet-manta-pay is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// pallet-manta-pay is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with pallet-manta-pay.  If not, see <http://www.gnu.org/licenses/>.
+
+//! A client side wallet, which finds the coins of a user in the ledger.
+//!
+//! The wallet is fed with the content of the ledger, in order:
+//! * every commitment of every shard, with `sync_leaf`,
+//!   i.e., the content of `ShardLeaves`;
+//! * every note, with `scan_note`, i.e., the content of `Notes`,
+//!   for instance as returned by the `MantaPayApi` runtime API;
+//! * every void number, with `sync_void_number`, i.e., the content of
+//!   `VoidNumbers`.
+//!
+//! The wallet trial-decrypts the notes with the secrets of its addresses,
+//! rebuilds the `MantaAsset` of the coins it receives, tracks whether they
+//! have been spent, and assembles the `SenderMetaData` to spend them.
+//! A note is only accepted if it opens the commitment it is attached to,
+//! since the sender of a coin may encrypt anything in its note.
+//!
+//! The wallet keeps the merkle witnesses of its unspent coins up to date as
+//! the leaves are synced, so that spending a coin does not rebuild the merkle
+//! tree of its shard. A coin that is found after its leaf has been synced,
+//! e.g., a note scanned after the leaves, costs a replay of its shard.
+
+use crate::*;
+use ark_crypto_primitives::prf::{Blake2s, PRF};
+use manta_asset::*;
+use manta_crypto::*;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The errors of the `Wallet`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WalletError {
+	/// A leaf is not the next leaf of its shard.
+	LeafOutOfOrder,
+	/// The coin is not owned by the wallet.
+	UnknownCoin,
+	/// The commitment of the coin is not in the synced leaves of its shard.
+	CoinNotInLedger,
+	/// The coin has already been spent.
+	CoinSpent,
+}
+
+/// The secrets of a receiving address of the wallet:
+/// the opening `(pk, rho, r)` of its `k = com(pk || rho, r)`,
+/// where `pk = PRF(sk, [0u8; 32])`,
+/// and the ECIES secret key its notes are encrypted to.
+#[derive(Clone)]
+pub struct AddressSecret {
+	pub ecsk: MantaEciesSecretKey,
+	pub sk: [u8; 32],
+	pub rho: [u8; 32],
+	pub r: [u8; 32],
+	pub k: [u8; 32],
+}
+
+impl From<&MantaAssetFullReceiver> for AddressSecret {
+	fn from(receiver: &MantaAssetFullReceiver) -> Self {
+		Self {
+			ecsk: receiver.spend.ecsk.clone(),
+			sk: receiver.spend.sk,
+			rho: receiver.spend.rho,
+			r: receiver.spend.r,
+			k: receiver.prepared.k,
+		}
+	}
+}
+
+/// A coin owned by the wallet, and its position in the ledger.
+#[derive(Debug, Clone)]
+pub struct OwnedCoin {
+	pub asset: MantaAsset,
+	pub shard_index: u32,
+	pub leaf_index: u64,
+	pub spent: bool,
+}
+
+/// A client side wallet, see the module documentation.
+pub struct Wallet {
+	hash_param: HashParam,
+	commit_param: CommitmentParam,
+	addresses: Vec<AddressSecret>,
+	// the owned coins, indexed by their commitment
+	coins: BTreeMap<[u8; 32], OwnedCoin>,
+	// the commitments of the owned coins, indexed by their void number
+	void_numbers: BTreeMap<[u8; 32], [u8; 32]>,
+	// the void numbers synced before the coin they spend is found
+	spent_void_numbers: BTreeSet<[u8; 32]>,
+	// the leaves of every shard, in order
+	shards: BTreeMap<u32, Vec<[u8; 32]>>,
+	// the witnesses of the unspent coins of every shard
+	trackers: BTreeMap<u32, WitnessTracker>,
+}
+
+impl Wallet {
+	/// An empty wallet.
+	pub fn new(hash_param: HashParam, commit_param: CommitmentParam) -> Self {
+		Self {
+			hash_param,
+			commit_param,
+			addresses: Vec::new(),
+			coins: BTreeMap::new(),
+			void_numbers: BTreeMap::new(),
+			spent_void_numbers: BTreeSet::new(),
+			shards: BTreeMap::new(),
+			trackers: BTreeMap::new(),
+		}
+	}
+
+	/// Add a receiving address to the wallet;
+	/// only the notes scanned from now on are decrypted with it.
+	pub fn add_address(&mut self, address: AddressSecret) {
+		self.addresses.push(address);
+	}
+
+	/// Add a coin which is known to the wallet without a note,
+	/// e.g., a coin minted by the user, at the position
+	/// `(shard_index, leaf_index)` of the ledger.
+	pub fn add_coin(&mut self, asset: MantaAsset, shard_index: u32, leaf_index: u64) {
+		let spent = self.spent_void_numbers.contains(&asset.void_number);
+		self.void_numbers
+			.insert(asset.void_number, asset.commitment);
+		self.coins.insert(
+			asset.commitment,
+			OwnedCoin {
+				asset,
+				shard_index,
+				leaf_index,
+				spent,
+			},
+		);
+
+		// the leaf has already been synced without its witness
+		let synced = self
+			.shards
+			.get(&shard_index)
+			.map_or(0, |leaves| leaves.len() as u64);
+		if !spent && leaf_index < synced {
+			self.replay_shard(shard_index);
+		}
+	}
+
+	/// Append the commitment `cm` to the shard `shard_index`;
+	/// the leaves of a shard must be synced in order.
+	pub fn sync_leaf(
+		&mut self,
+		shard_index: u32,
+		leaf_index: u64,
+		cm: [u8; 32],
+	) -> Result<(), WalletError> {
+		let leaves = self.shards.entry(shard_index).or_default();
+		if leaf_index != leaves.len() as u64 {
+			return Err(WalletError::LeafOutOfOrder);
+		}
+		leaves.push(cm);
+
+		let track = self.is_unspent_coin_at(&cm, shard_index, leaf_index);
+		self.trackers
+			.entry(shard_index)
+			.or_default()
+			.append(&self.hash_param, &cm, track);
+		Ok(())
+	}
+
+	/// Trial-decrypt the note `record` with the secrets of the addresses of
+	/// the wallet. Returns the coin if the note is for one of them,
+	/// and if it opens the commitment of the record.
+	pub fn scan_note(&mut self, record: &NoteRecord) -> Option<&OwnedCoin> {
+		if self.coins.contains_key(&record.cm) {
+			return None;
+		}
+		let commit_param = &self.commit_param;
+		let asset = self.addresses.iter().find_map(|address| {
+			let note = try_decrypt_note(&address.ecsk, &record.cm, &record.encrypted_note)?;
+			// the sender picked the address of the note,
+			// and a coin that the note does not open cannot be spent
+			if note.k != address.k || !opens_commitment(commit_param, &note, &record.cm) {
+				return None;
+			}
+			Some(asset_of(address, &note, &record.cm))
+		})?;
+
+		self.add_coin(asset, record.shard_index, record.leaf_index);
+		self.coins.get(&record.cm)
+	}
+
+	/// Mark the coin with the void number `void_number`, if any, as spent.
+	pub fn sync_void_number(&mut self, void_number: &[u8; 32]) {
+		self.spent_void_numbers.insert(*void_number);
+		if let Some(cm) = self.void_numbers.get(void_number) {
+			if let Some(coin) = self.coins.get_mut(cm) {
+				coin.spent = true;
+				if let Some(tracker) = self.trackers.get_mut(&coin.shard_index) {
+					tracker.untrack(cm);
+				}
+			}
+		}
+	}
+
+	/// Get the coin of commitment `cm`.
+	pub fn coin(&self, cm: &[u8; 32]) -> Option<&OwnedCoin> {
+		self.coins.get(cm)
+	}
+
+	/// The coins that have not been spent yet.
+	pub fn unspent_coins(&self) -> Vec<&OwnedCoin> {
+		self.coins.values().filter(|coin| !coin.spent).collect()
+	}
+
+	/// The total value of the unspent coins of `asset_id`.
+	pub fn balance(&self, asset_id: AssetId) -> u64 {
+		self.unspent_coins()
+			.iter()
+			.filter(|coin| coin.asset.asset_id == asset_id)
+			.map(|coin| coin.asset.priv_info.value)
+			.sum()
+	}
+
+	/// Assemble the `SenderMetaData` to spend the coin of commitment `cm`,
+	/// against the latest synced root of its shard.
+	pub fn sender_meta_data(&self, cm: &[u8; 32]) -> Result<SenderMetaData, WalletError> {
+		let coin = self.coins.get(cm).ok_or(WalletError::UnknownCoin)?;
+		if coin.spent {
+			return Err(WalletError::CoinSpent);
+		}
+		let witness = self
+			.trackers
+			.get(&coin.shard_index)
+			.and_then(|tracker| tracker.witness(cm).ok())
+			.ok_or(WalletError::CoinNotInLedger)?;
+		SenderMetaData::from_witness(&self.hash_param, coin.asset.clone(), witness.clone())
+			.map_err(|_| WalletError::CoinNotInLedger)
+	}
+
+	// whether `cm` is an unspent coin of the wallet at the given position
+	fn is_unspent_coin_at(&self, cm: &[u8; 32], shard_index: u32, leaf_index: u64) -> bool {
+		self.coins.get(cm).map_or(false, |coin| {
+			!coin.spent && coin.shard_index == shard_index && coin.leaf_index == leaf_index
+		})
+	}
+
+	// rebuild the witnesses of the shard `shard_index` from its leaves
+	fn replay_shard(&mut self, shard_index: u32) {
+		let leaves = self.shards.get(&shard_index).cloned().unwrap_or_default();
+		let tracker = WitnessTracker::from_leaves(&self.hash_param, &leaves, |leaf_index, cm| {
+			self.is_unspent_coin_at(cm, shard_index, leaf_index)
+		});
+		self.trackers.insert(shard_index, tracker);
+	}
+}
+
+// whether `cm = com(asset_id || v || k, s)` for the content of the `note`
+fn opens_commitment(param: &CommitmentParam, note: &Note, cm: &[u8; 32]) -> bool {
+	let payload = [
+		(note.asset_id as u64).to_le_bytes().as_ref(),
+		note.value.to_le_bytes().as_ref(),
+		note.k.as_ref(),
+	]
+	.concat();
+	<MantaCrypto as Commitment>::check_commitment(param, &payload, &note.s, cm)
+}
+
+// rebuild the `MantaAsset` of commitment `cm` from its note and its address
+fn asset_of(address: &AddressSecret, note: &Note, cm: &[u8; 32]) -> MantaAsset {
+	// the PRF only fails on seeds and inputs that are not 32 bytes long
+	let pk = Blake2s::evaluate(&address.sk, &[0u8; 32]).unwrap();
+	let void_number = Blake2s::evaluate(&address.sk, &address.rho).unwrap();
+
+	let mut asset = MantaAsset::default();
+	asset.asset_id = note.asset_id;
+	asset.commitment = *cm;
+	asset.void_number = void_number;
+	asset.pub_info.pk = pk;
+	asset.pub_info.rho = address.rho;
+	asset.pub_info.r = address.r;
+	asset.pub_info.k = note.k;
+	asset.pub_info.s = note.s;
+	asset.priv_info.sk = address.sk;
+	asset.priv_info.value = note.value;
+	asset
+}
@@ -33,6 +33,15 @@
 // --heap-pages=4096
 // --output=./weights.rs
 // --template=./frame-weight-template.hbs
+//
+// NOTE: `initialize_ledger`, `set_verification_key`, `private_transfer_batch` and
+// `private_transfer_n` are not benchmarked yet, and the storage accesses are counted
+// by hand. Preparing a verification key costs one pairing, less than verifying a proof,
+// so it is bounded by `private_transfer`, on top of the former ledger initialization
+// of `init_asset`. A batch is bounded by a `private_transfer` per transfer. An n-ary
+// transfer verifies a single proof, i.e., `private_transfer` less the two appends of
+// `mint_private_asset`, and appends a coin per receiver. Rerun the command above to
+// replace these bounds with measured weights.
 #![allow(clippy::unnecessary_cast)]
 #![allow(unused_parens)]
 #![allow(unused_imports)]
@@ -45,20 +54,34 @@ use sp_std::marker::PhantomData;
 
 /// Weight functions needed for pallet_manta_pay.
 pub trait WeightInfo {
+	fn initialize_ledger() -> Weight;
+	fn set_verification_key() -> Weight;
 	fn init_asset() -> Weight;
 	fn transfer_asset() -> Weight;
 	fn mint_private_asset() -> Weight;
 	fn private_transfer() -> Weight;
+	fn private_transfer_batch(n: u32) -> Weight;
+	fn private_transfer_n(s: u32, r: u32) -> Weight;
 	fn reclaim() -> Weight;
 }
 
 /// Weights for pallet_manta_pay using the Substrate node and recommended hardware.
 pub struct SubstrateWeight<T>(PhantomData<T>);
 impl<T: frame_system::Config> WeightInfo for SubstrateWeight<T> {
+	fn initialize_ledger() -> Weight {
+		(335_327_817_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(1 as Weight))
+			.saturating_add(T::DbWeight::get().writes(10 as Weight))
+	}
+	fn set_verification_key() -> Weight {
+		(165_009_033_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(1 as Weight))
+			.saturating_add(T::DbWeight::get().writes(3 as Weight))
+	}
 	fn init_asset() -> Weight {
 		(5_309_751_000 as Weight)
 			.saturating_add(T::DbWeight::get().reads(1 as Weight))
-			.saturating_add(T::DbWeight::get().writes(11 as Weight))
+			.saturating_add(T::DbWeight::get().writes(3 as Weight))
 	}
 	fn transfer_asset() -> Weight {
 		(51_229_000 as Weight)
@@ -67,27 +90,54 @@ impl<T: frame_system::Config> WeightInfo for SubstrateWeight<T> {
 	}
 	fn mint_private_asset() -> Weight {
 		(42_731_469_000 as Weight)
-			.saturating_add(T::DbWeight::get().reads(6 as Weight))
-			.saturating_add(T::DbWeight::get().writes(3 as Weight))
+			.saturating_add(T::DbWeight::get().reads(10 as Weight))
+			.saturating_add(T::DbWeight::get().writes(11 as Weight))
 	}
 	fn private_transfer() -> Weight {
 		(165_009_033_000 as Weight)
-			.saturating_add(T::DbWeight::get().reads(6 as Weight))
-			.saturating_add(T::DbWeight::get().writes(3 as Weight))
+			.saturating_add(T::DbWeight::get().reads(20 as Weight))
+			.saturating_add(T::DbWeight::get().writes(22 as Weight))
+	}
+	fn private_transfer_batch(n: u32) -> Weight {
+		(0 as Weight)
+			.saturating_add((165_009_033_000 as Weight).saturating_mul(n as Weight))
+			.saturating_add(T::DbWeight::get().reads(4 as Weight))
+			.saturating_add(T::DbWeight::get().reads((16 as Weight).saturating_mul(n as Weight)))
+			.saturating_add(T::DbWeight::get().writes((22 as Weight).saturating_mul(n as Weight)))
+	}
+	fn private_transfer_n(s: u32, r: u32) -> Weight {
+		(79_546_095_000 as Weight)
+			.saturating_add((42_731_469_000 as Weight).saturating_mul(s.max(r) as Weight))
+			.saturating_add(T::DbWeight::get().reads(8 as Weight))
+			.saturating_add(T::DbWeight::get().reads((2 as Weight).saturating_mul(s as Weight)))
+			.saturating_add(T::DbWeight::get().reads((4 as Weight).saturating_mul(r as Weight)))
+			.saturating_add(T::DbWeight::get().writes(2 as Weight))
+			.saturating_add(T::DbWeight::get().writes((1 as Weight).saturating_mul(s as Weight)))
+			.saturating_add(T::DbWeight::get().writes((9 as Weight).saturating_mul(r as Weight)))
 	}
 	fn reclaim() -> Weight {
 		(123_932_053_000 as Weight)
-			.saturating_add(T::DbWeight::get().reads(8 as Weight))
-			.saturating_add(T::DbWeight::get().writes(5 as Weight))
+			.saturating_add(T::DbWeight::get().reads(16 as Weight))
+			.saturating_add(T::DbWeight::get().writes(13 as Weight))
 	}
 }
 
 // For backwards compatibility and tests
 impl WeightInfo for () {
+	fn initialize_ledger() -> Weight {
+		(335_327_817_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(1 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(10 as Weight))
+	}
+	fn set_verification_key() -> Weight {
+		(165_009_033_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(1 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(3 as Weight))
+	}
 	fn init_asset() -> Weight {
 		(5_309_751_000 as Weight)
 			.saturating_add(RocksDbWeight::get().reads(1 as Weight))
-			.saturating_add(RocksDbWeight::get().writes(11 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(3 as Weight))
 	}
 	fn transfer_asset() -> Weight {
 		(51_229_000 as Weight)
@@ -96,17 +146,34 @@ impl WeightInfo for () {
 	}
 	fn mint_private_asset() -> Weight {
 		(42_731_469_000 as Weight)
-			.saturating_add(RocksDbWeight::get().reads(6 as Weight))
-			.saturating_add(RocksDbWeight::get().writes(3 as Weight))
+			.saturating_add(RocksDbWeight::get().reads(10 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(11 as Weight))
 	}
 	fn private_transfer() -> Weight {
 		(165_009_033_000 as Weight)
-			.saturating_add(RocksDbWeight::get().reads(6 as Weight))
-			.saturating_add(RocksDbWeight::get().writes(3 as Weight))
+			.saturating_add(RocksDbWeight::get().reads(20 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(22 as Weight))
+	}
+	fn private_transfer_batch(n: u32) -> Weight {
+		(0 as Weight)
+			.saturating_add((165_009_033_000 as Weight).saturating_mul(n as Weight))
+			.saturating_add(RocksDbWeight::get().reads(4 as Weight))
+			.saturating_add(RocksDbWeight::get().reads((16 as Weight).saturating_mul(n as Weight)))
+			.saturating_add(RocksDbWeight::get().writes((22 as Weight).saturating_mul(n as Weight)))
+	}
+	fn private_transfer_n(s: u32, r: u32) -> Weight {
+		(79_546_095_000 as Weight)
+			.saturating_add((42_731_469_000 as Weight).saturating_mul(s.max(r) as Weight))
+			.saturating_add(RocksDbWeight::get().reads(8 as Weight))
+			.saturating_add(RocksDbWeight::get().reads((2 as Weight).saturating_mul(s as Weight)))
+			.saturating_add(RocksDbWeight::get().reads((4 as Weight).saturating_mul(r as Weight)))
+			.saturating_add(RocksDbWeight::get().writes(2 as Weight))
+			.saturating_add(RocksDbWeight::get().writes((1 as Weight).saturating_mul(s as Weight)))
+			.saturating_add(RocksDbWeight::get().writes((9 as Weight).saturating_mul(r as Weight)))
 	}
 	fn reclaim() -> Weight {
 		(123_932_053_000 as Weight)
-			.saturating_add(RocksDbWeight::get().reads(8 as Weight))
-			.saturating_add(RocksDbWeight::get().writes(5 as Weight))
+			.saturating_add(RocksDbWeight::get().reads(16 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(13 as Weight))
 	}
 }
@@ -18,6 +18,7 @@ use super::*;
 use ark_ed_on_bls12_381::{constraints::FqVar, Fq};
 use ark_r1cs_std::{alloc::AllocVar, prelude::*};
 use ark_relations::r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError};
+use ark_std::vec;
 use manta_asset::*;
 use manta_crypto::*;
 
@@ -26,17 +27,23 @@ use manta_crypto::*;
 /// # <weight>
 /// 1. both sender's coins are well-formed:
 ///  * `k = com(pk||rho, r)`
-///  * `cm = com(v||k, s)`
+///  * `cm = com(asset_id||v||k, s)`
 /// where k is public.
 /// 2. both receiver's coins are well-formed
-///  * `cm = com(v||k, s)`
+///  * `cm = com(asset_id||v||k, s)`
 /// where k and cm are both public.
 /// 3. address and the secret key derives public key:
 ///  `sender.pk = PRF(sender_sk, [0u8;32])`
 /// 4. sender's commitment is in CMList.
-///  NOTE: we de not need to prove that sender's sn is not in VNList
+///  NOTE: we de not need to prove that sender's sn is not in VoidNumbers
 ///        this can be done in the public.
-/// 5. sender's and receiver's combined values are the same.
+/// 5. sender's total value == receiver's total value + fee.
+/// 6. all the values, including the fee, are 64 bits integers,
+///  so that the sums cannot overflow the field.
+/// 7. all the coins commit to the same asset id, which is the public one
+///  when a fee is paid in it; a transfer without a fee does not reveal its asset.
+/// 8. the binding hash of the payload is a public input, so that the proof
+///  is bound to the rest of the payload and to the chain.
 /// # </weight>
 // =============================
 #[derive(Clone)]
@@ -45,6 +52,13 @@ pub struct TransferCircuit {
 	pub commit_param: CommitmentParam,
 	pub hash_param: HashParam,
 
+	// public fee
+	pub asset_id: AssetId,
+	pub fee: u64,
+
+	// binding hash of the payload
+	pub binding: [u8; 32],
+
 	// sender
 	pub sender_1: SenderMetaData,
 	pub sender_2: SenderMetaData,
@@ -54,6 +68,51 @@ pub struct TransferCircuit {
 	pub receiver_2: MantaAssetProcessedReceiver,
 }
 
+// =============================
+/// ZK circuit for the __transfer__ statements, with any number of
+/// senders and receivers.
+/// The statements are the ones of `TransferCircuit`, for every sender and
+/// every receiver; `TransferCircuit` is the 2 to 2 instance of this circuit,
+/// so both circuits share their keys for this shape.
+///
+/// The public inputs are, in order: the `k` of every sender, the commitment
+/// of every receiver, the void number of every sender, the merkle root
+/// of every sender, the fee, the asset id and the binding hash.
+// =============================
+#[derive(Clone)]
+pub struct MultiTransferCircuit {
+	// param
+	pub commit_param: CommitmentParam,
+	pub hash_param: HashParam,
+
+	// public fee
+	pub asset_id: AssetId,
+	pub fee: u64,
+
+	// binding hash of the payload
+	pub binding: [u8; 32],
+
+	// senders, there must be at least one
+	pub senders: Vec<SenderMetaData>,
+
+	// receivers, there must be at least one
+	pub receivers: Vec<MantaAssetProcessedReceiver>,
+}
+
+impl From<TransferCircuit> for MultiTransferCircuit {
+	fn from(circuit: TransferCircuit) -> Self {
+		Self {
+			commit_param: circuit.commit_param,
+			hash_param: circuit.hash_param,
+			asset_id: circuit.asset_id,
+			fee: circuit.fee,
+			binding: circuit.binding,
+			senders: vec![circuit.sender_1, circuit.sender_2],
+			receivers: vec![circuit.receiver_1, circuit.receiver_2],
+		}
+	}
+}
+
 // =============================
 /// ZK circuit for the __reclaim__ statements.
 /// # <weight>
@@ -62,14 +121,20 @@ pub struct TransferCircuit {
 ///   * `cm = com(asset_id||v||k, s)`
 /// where only k is public.
 /// 2. receiver's coin is well-formed:
-///   * `cm = com(v||k, s)`
+///   * `cm = com(asset_id||v||k, s)`
 /// where both `k` and `cm` are public.
 /// 3. address and the secret key derives public key:
 ///  `sender.pk = PRF(sender_sk, [0u8;32])`
 /// 4. sender's commitment is in CMList.
-///  NOTE: we do not need to prove that sender's vn is not in VNList.
+///  NOTE: we do not need to prove that sender's vn is not in VoidNumbers.
 ///        this can be done in the public.
 /// 5. sender's total value == receiver value + reclaim value.
+/// 6. all the values, including the reclaim value, are 64 bits integers,
+///  so that the sums cannot overflow the field.
+/// 7. the recipient of the reclaimed value is a public input, so that
+///  the proof cannot be replayed to the benefit of another account.
+/// 8. the binding hash of the payload is a public input, so that the proof
+///  is bound to the rest of the payload and to the chain.
 /// # </weight>
 // =============================
 #[derive(Clone)]
@@ -88,152 +153,66 @@ pub struct ReclaimCircuit {
 	// reclaimed amount
 	pub asset_id: AssetId,
 	pub reclaim_value: u64,
+
+	// the account credited with the reclaimed amount
+	pub recipient: [u8; 32],
+
+	// binding hash of the payload
+	pub binding: [u8; 32],
 }
 
 impl ConstraintSynthesizer<Fq> for TransferCircuit {
 	/// Input a circuit, build the corresponding constraint system, and
 	/// add it to `cs`.
 	fn generate_constraints(self, cs: ConstraintSystemRef<Fq>) -> Result<(), SynthesisError> {
-		// 1. both sender's and receiver's coins are well-formed
-		//  k = com( pk || rho, r)
-		//  cm = com( asset_id || v || k, s)
-
-		// parameters
-		let parameters_var =
-			CommitmentParamVar::new_input(ark_relations::ns!(cs, "gadget_parameters"), || {
-				Ok(&self.commit_param)
-			})
-			.unwrap();
-
-		sender_token_well_formed_circuit_helper(&parameters_var, &self.sender_1.asset, cs.clone());
-		sender_token_well_formed_circuit_helper(&parameters_var, &self.sender_2.asset, cs.clone());
-		receiver_token_well_formed_circuit_helper(&parameters_var, &self.receiver_1, cs.clone());
-		receiver_token_well_formed_circuit_helper(&parameters_var, &self.receiver_2, cs.clone());
-
-		// 2. address and the secret key derives public key
-		//  sender.pk = PRF(sender_sk, [0u8;32])
-		//  sender.sn = PRF(sender_sk, rho)
-		prf_circuit_helper(
-			true,
-			&self.sender_1.asset.priv_info.sk,
-			&[0u8; 32],
-			&self.sender_1.asset.pub_info.pk,
-			cs.clone(),
-		);
-		prf_circuit_helper(
-			false,
-			&self.sender_1.asset.priv_info.sk,
-			&self.sender_1.asset.pub_info.rho,
-			&self.sender_1.asset.void_number,
-			cs.clone(),
-		);
-		prf_circuit_helper(
-			true,
-			&self.sender_2.asset.priv_info.sk,
-			&[0u8; 32],
-			&self.sender_2.asset.pub_info.pk,
-			cs.clone(),
-		);
-		prf_circuit_helper(
-			false,
-			&self.sender_2.asset.priv_info.sk,
-			&self.sender_2.asset.pub_info.rho,
-			&self.sender_2.asset.void_number,
-			cs.clone(),
-		);
-
-		// 3. sender's commitment is on the list
-		// Allocate Parameters for CRH
-		let param_var = HashParamVar::new_constant(
-			ark_relations::ns!(cs, "new_parameter"),
-			self.hash_param.clone(),
-		)
-		.unwrap();
+		MultiTransferCircuit::from(self).generate_constraints(cs)
+	}
+}
 
-		merkle_membership_circuit_proof(
-			&self.sender_1.asset.commitment,
-			&self.sender_1.membership,
-			param_var.clone(),
-			self.sender_1.root,
+impl ConstraintSynthesizer<Fq> for MultiTransferCircuit {
+	/// Input a circuit, build the corresponding constraint system, and
+	/// add it to `cs`.
+	fn generate_constraints(self, cs: ConstraintSystemRef<Fq>) -> Result<(), SynthesisError> {
+		if self.senders.is_empty() || self.receivers.is_empty() {
+			return Err(SynthesisError::Unsatisfiable);
+		}
+
+		// 1. senders and receivers coins are well-formed,
+		// 2. the senders know the secret keys of their coins,
+		// 3. the senders' coins are on the list
+		let (sender_coins, receiver_coins) = coins_circuit_helper(
+			&self.commit_param,
+			&self.hash_param,
+			&self.senders,
+			&self.receivers,
 			cs.clone(),
-		);
+		)?;
+
+		// 4. sender's total value is the receiver's total value plus the fee;
+		// all the values are 64 bits, so the sums do not wrap around
+		let sender_value_sum = value_sum_circuit_helper(&sender_coins);
+		let mut receiver_value_sum = value_sum_circuit_helper(&receiver_coins);
+		let fee_fq = Fq::from(self.fee);
+		let fee_var = FqVar::new_input(ark_relations::ns!(cs, "fee"), || Ok(&fee_fq))?;
+		range_check_u64_circuit_helper(&fee_var)?;
+		receiver_value_sum += &fee_var;
+
+		sender_value_sum.enforce_equal(&receiver_value_sum)?;
+
+		// 5. check that the coins commit to the same asset id, and that
+		// it is the public one when the fee is not zero:
+		// fee * (public asset id - asset id) = 0
+		let coin_asset_id = &sender_coins[0].asset_id;
+		for coin in sender_coins.iter().chain(receiver_coins.iter()) {
+			coin_asset_id.enforce_equal(&coin.asset_id)?;
+		}
+		let asset_id_fq = Fq::from(self.asset_id as u64);
+		let asset_id_fq_var =
+			FqVar::new_input(ark_relations::ns!(cs, "asset id"), || Ok(&asset_id_fq))?;
+		(&asset_id_fq_var - coin_asset_id).mul_equals(&fee_var, &FqVar::zero())?;
 
-		merkle_membership_circuit_proof(
-			&self.sender_2.asset.commitment,
-			&self.sender_2.membership,
-			param_var,
-			self.sender_2.root,
-			cs.clone(),
-		);
-
-		// 4. sender's and receiver's total value are the same
-		// TODO: do we need to check that the values are all positive?
-		// seems that Rust's type system has already eliminated negative values
-		let sender_value_1_fq = Fq::from(self.sender_1.asset.priv_info.value);
-		let mut sender_value_sum =
-			FqVar::new_witness(ark_relations::ns!(cs, "sender value"), || {
-				Ok(&sender_value_1_fq)
-			})
-			.unwrap();
-		let sender_value_2_fq = Fq::from(self.sender_2.asset.priv_info.value);
-		let sender_value_2_var = FqVar::new_witness(ark_relations::ns!(cs, "sender value"), || {
-			Ok(&sender_value_2_fq)
-		})
-		.unwrap();
-		sender_value_sum += sender_value_2_var;
-
-		let receiver_value_1_fq = Fq::from(self.receiver_1.value);
-		let mut receiver_value_sum =
-			FqVar::new_witness(ark_relations::ns!(cs, "receiver value"), || {
-				Ok(&receiver_value_1_fq)
-			})
-			.unwrap();
-		let receiver_value_2_fq = Fq::from(self.receiver_2.value);
-		let receiver_value_2_var =
-			FqVar::new_witness(ark_relations::ns!(cs, "receiver value"), || {
-				Ok(&receiver_value_2_fq)
-			})
-			.unwrap();
-		receiver_value_sum += receiver_value_2_var;
-
-		sender_value_sum.enforce_equal(&receiver_value_sum).unwrap();
-
-		// 5. check that the asset ids match
-		let sender_1_asset_id_fq = Fq::from(self.sender_1.asset.asset_id as u64);
-		let sender_2_asset_id_fq = Fq::from(self.sender_2.asset.asset_id as u64);
-		let receiver_1_asset_id_fq = Fq::from(self.receiver_1.prepared_data.asset_id as u64);
-		let receiver_2_asset_id_fq = Fq::from(self.receiver_2.prepared_data.asset_id as u64);
-
-		let sender_1_asset_id_fq_var =
-			FqVar::new_witness(ark_relations::ns!(cs, "sender asset id"), || {
-				Ok(&sender_1_asset_id_fq)
-			})
-			.unwrap();
-		let sender_2_asset_id_fq_var =
-			FqVar::new_witness(ark_relations::ns!(cs, "sender asset id"), || {
-				Ok(&sender_2_asset_id_fq)
-			})
-			.unwrap();
-		let receiver_1_asset_id_fq_var =
-			FqVar::new_witness(ark_relations::ns!(cs, "sender asset id"), || {
-				Ok(&receiver_1_asset_id_fq)
-			})
-			.unwrap();
-		let receiver_2_asset_id_fq_var =
-			FqVar::new_witness(ark_relations::ns!(cs, "sender asset id"), || {
-				Ok(&receiver_2_asset_id_fq)
-			})
-			.unwrap();
-
-		sender_1_asset_id_fq_var
-			.enforce_equal(&sender_2_asset_id_fq_var)
-			.unwrap();
-		sender_1_asset_id_fq_var
-			.enforce_equal(&receiver_1_asset_id_fq_var)
-			.unwrap();
-		sender_1_asset_id_fq_var
-			.enforce_equal(&receiver_2_asset_id_fq_var)
-			.unwrap();
+		// 6. bind the rest of the payload
+		binding_circuit_helper(&self.binding, cs)?;
 
 		Ok(())
 	}
@@ -243,145 +222,148 @@ impl ConstraintSynthesizer<Fq> for ReclaimCircuit {
 	/// Input a circuit, build the corresponding constraint system, and
 	/// add it to `cs`.
 	fn generate_constraints(self, cs: ConstraintSystemRef<Fq>) -> Result<(), SynthesisError> {
-		// 1. both sender's and receiver's coins are well-formed
-		//  k = com(pk||rho, r)
-		//  cm = com(v||k, s)
-
-		// parameters
-		let parameters_var =
-			CommitmentParamVar::new_input(ark_relations::ns!(cs, "gadget_parameters"), || {
-				Ok(&self.commit_param)
-			})
-			.unwrap();
-
-		sender_token_well_formed_circuit_helper(&parameters_var, &self.sender_1.asset, cs.clone());
-		sender_token_well_formed_circuit_helper(&parameters_var, &self.sender_2.asset, cs.clone());
-		receiver_token_well_formed_circuit_helper(&parameters_var, &self.receiver, cs.clone());
-
-		// 2. address and the secret key derives public key
-		//  sender.pk = PRF(sender_sk, [0u8;32])
-		//  sender.sn = PRF(sender_sk, rho)
-		prf_circuit_helper(
-			true,
-			&self.sender_1.asset.priv_info.sk,
-			&[0u8; 32],
-			&self.sender_1.asset.pub_info.pk,
-			cs.clone(),
-		);
-		prf_circuit_helper(
-			false,
-			&self.sender_1.asset.priv_info.sk,
-			&self.sender_1.asset.pub_info.rho,
-			&self.sender_1.asset.void_number,
+		// 1. senders and receivers coins are well-formed,
+		// 2. the senders know the secret keys of their coins,
+		// 3. the senders' coins are on the list
+		let (sender_coins, receiver_coins) = coins_circuit_helper(
+			&self.commit_param,
+			&self.hash_param,
+			&[self.sender_1.clone(), self.sender_2.clone()],
+			&[self.receiver.clone()],
 			cs.clone(),
-		);
+		)?;
+
+		// 4. sender's and receiver's total value are the same;
+		// all the values are 64 bits, so the sums do not wrap around
+		let sender_value_sum = value_sum_circuit_helper(&sender_coins);
+		let mut receiver_value_sum = value_sum_circuit_helper(&receiver_coins);
+		let reclaim_value_fq = Fq::from(self.reclaim_value);
+		let reclaim_value_var =
+			FqVar::new_input(ark_relations::ns!(cs, "reclaimed value"), || {
+				Ok(&reclaim_value_fq)
+			})?;
+		range_check_u64_circuit_helper(&reclaim_value_var)?;
+		receiver_value_sum += reclaim_value_var;
+
+		sender_value_sum.enforce_equal(&receiver_value_sum)?;
+
+		// 5. check that the committed asset ids match the public one
+		let asset_id_fq = Fq::from(self.asset_id as u64);
+		let asset_id_fq_var = FqVar::new_input(ark_relations::ns!(cs, "sender asset id"), || {
+			Ok(&asset_id_fq)
+		})?;
+		for coin in sender_coins.iter().chain(receiver_coins.iter()) {
+			asset_id_fq_var.enforce_equal(&coin.asset_id)?;
+		}
+
+		// 6. bind the recipient; as every public input,
+		// it is bound by the proof even though it is not constrained
+		let recipient_fq: Vec<Fq> =
+			ToConstraintField::<Fq>::to_field_elements(self.recipient.as_ref())
+				.ok_or(SynthesisError::AssignmentMissing)?;
+		for recipient in recipient_fq.iter() {
+			FqVar::new_input(ark_relations::ns!(cs, "recipient"), || Ok(recipient))?;
+		}
+
+		// 7. bind the rest of the payload
+		binding_circuit_helper(&self.binding, cs)?;
+
+		Ok(())
+	}
+}
+
+// =============================
+// circuit for the following statements, for all the senders and receivers
+// 1. the sender's and receiver's coins are well-formed
+//  k = com( pk || rho, r)
+//  cm = com( asset_id || v || k, s)
+// 2. address and the secret key derives public key
+//  sender.pk = PRF(sender_sk, [0u8;32])
+//  sender.sn = PRF(sender_sk, rho)
+// 3. sender's commitment is on the list
+// the asset ids and values committed by the senders and by the receivers
+// are returned
+// =============================
+fn coins_circuit_helper(
+	commit_param: &CommitmentParam,
+	hash_param: &HashParam,
+	senders: &[SenderMetaData],
+	receivers: &[MantaAssetProcessedReceiver],
+	cs: ConstraintSystemRef<Fq>,
+) -> Result<(Vec<CoinVar>, Vec<CoinVar>), SynthesisError> {
+	// parameters
+	let parameters_var =
+		CommitmentParamVar::new_input(ark_relations::ns!(cs, "gadget_parameters"), || {
+			Ok(commit_param)
+		})?;
+
+	let sender_coins = senders
+		.iter()
+		.map(|sender| {
+			sender_token_well_formed_circuit_helper(&parameters_var, &sender.asset, cs.clone())
+		})
+		.collect::<Result<_, _>>()?;
+	let receiver_coins = receivers
+		.iter()
+		.map(|receiver| {
+			receiver_token_well_formed_circuit_helper(&parameters_var, receiver, cs.clone())
+		})
+		.collect::<Result<_, _>>()?;
+
+	for sender in senders.iter() {
 		prf_circuit_helper(
 			true,
-			&self.sender_2.asset.priv_info.sk,
+			&sender.asset.priv_info.sk,
 			&[0u8; 32],
-			&self.sender_2.asset.pub_info.pk,
+			&sender.asset.pub_info.pk,
 			cs.clone(),
-		);
+		)?;
 		prf_circuit_helper(
 			false,
-			&self.sender_2.asset.priv_info.sk,
-			&self.sender_2.asset.pub_info.rho,
-			&self.sender_2.asset.void_number,
+			&sender.asset.priv_info.sk,
+			&sender.asset.pub_info.rho,
+			&sender.asset.void_number,
 			cs.clone(),
-		);
+		)?;
+	}
 
-		// 3. sender's commitment is in List_all
-		// Allocate Parameters for CRH
-		let param_var = HashParamVar::new_constant(
-			ark_relations::ns!(cs, "new_parameter"),
-			self.hash_param.clone(),
-		)
-		.unwrap();
+	// Allocate Parameters for CRH
+	let param_var =
+		HashParamVar::new_constant(ark_relations::ns!(cs, "new_parameter"), hash_param.clone())?;
 
+	for sender in senders.iter() {
 		merkle_membership_circuit_proof(
-			&self.sender_1.asset.commitment,
-			&self.sender_1.membership,
+			&sender.asset.commitment,
+			&sender.membership,
 			param_var.clone(),
-			self.sender_1.root,
-			cs.clone(),
-		);
-
-		merkle_membership_circuit_proof(
-			&self.sender_2.asset.commitment,
-			&self.sender_2.membership,
-			param_var,
-			self.sender_2.root,
+			sender.root,
 			cs.clone(),
-		);
-
-		// 4. sender's and receiver's total value are the same
-		// TODO: do we need to check that the values are all positive?
-		// seems that Rust's type system has already eliminated negative values
-		let sender_value_1_fq = Fq::from(self.sender_1.asset.priv_info.value);
-		let mut sender_value_sum =
-			FqVar::new_witness(ark_relations::ns!(cs, "sender value"), || {
-				Ok(&sender_value_1_fq)
-			})
-			.unwrap();
-		let sender_value_2_fq = Fq::from(self.sender_2.asset.priv_info.value);
-		let sender_value_2_var = FqVar::new_witness(ark_relations::ns!(cs, "sender value"), || {
-			Ok(&sender_value_2_fq)
-		})
-		.unwrap();
-		sender_value_sum += sender_value_2_var;
-
-		let receiver_value_fq = Fq::from(self.receiver.value);
-		let mut receiver_value_sum =
-			FqVar::new_witness(ark_relations::ns!(cs, "receiver value"), || {
-				Ok(&receiver_value_fq)
-			})
-			.unwrap();
-		let reclaim_value_fq = Fq::from(self.reclaim_value);
-		let reclaim_value_var = FqVar::new_input(ark_relations::ns!(cs, "reclaimed value"), || {
-			Ok(&reclaim_value_fq)
-		})
-		.unwrap();
-		receiver_value_sum += reclaim_value_var;
-
-		sender_value_sum.enforce_equal(&receiver_value_sum).unwrap();
-
-		// 5. check that the asset ids match
-
-		let asset_id_fq = Fq::from(self.asset_id as u64);
-		let sender_1_asset_id_fq = Fq::from(self.sender_1.asset.asset_id as u64);
-		let sender_2_asset_id_fq = Fq::from(self.sender_2.asset.asset_id as u64);
-		let receiver_asset_id_fq = Fq::from(self.receiver.prepared_data.asset_id as u64);
+		)?;
+	}
 
-		let asset_id_fq_var = FqVar::new_input(ark_relations::ns!(cs, "sender asset id"), || {
-			Ok(&asset_id_fq)
-		})
-		.unwrap();
-		let sender_1_asset_id_fq_var =
-			FqVar::new_witness(ark_relations::ns!(cs, "sender asset id"), || {
-				Ok(&sender_1_asset_id_fq)
-			})
-			.unwrap();
-		let sender_2_asset_id_fq_var =
-			FqVar::new_witness(ark_relations::ns!(cs, "sender asset id"), || {
-				Ok(&sender_2_asset_id_fq)
-			})
-			.unwrap();
-		let receiver_asset_id_fq_var =
-			FqVar::new_witness(ark_relations::ns!(cs, "sender asset id"), || {
-				Ok(&receiver_asset_id_fq)
-			})
-			.unwrap();
-
-		asset_id_fq_var
-			.enforce_equal(&sender_1_asset_id_fq_var)
-			.unwrap();
-		asset_id_fq_var
-			.enforce_equal(&sender_2_asset_id_fq_var)
-			.unwrap();
-		asset_id_fq_var
-			.enforce_equal(&receiver_asset_id_fq_var)
-			.unwrap();
+	Ok((sender_coins, receiver_coins))
+}
 
-		Ok(())
+// sum up the values committed to by the `coins`; they are 64 bits
+// by construction, and there are at most a few hundreds of them,
+// so their sum cannot wrap around the modulus
+fn value_sum_circuit_helper(coins: &[CoinVar]) -> FqVar {
+	// callers always pass at least one coin
+	let mut sum = coins[0].value.clone();
+	for coin in coins[1..].iter() {
+		sum += &coin.value;
 	}
+	sum
+}
+
+// allocate the binding hash of the payload as a public input;
+// as every public input, it is bound by the proof even though
+// it is not constrained
+fn binding_circuit_helper(
+	binding: &[u8; 32],
+	cs: ConstraintSystemRef<Fq>,
+) -> Result<(), SynthesisError> {
+	let binding_fq = binding_to_field_element(binding);
+	FqVar::new_input(ark_relations::ns!(cs, "binding"), || Ok(&binding_fq))?;
+	Ok(())
 }
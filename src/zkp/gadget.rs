@@ -17,69 +17,80 @@
 //! This module defines useful gadgets for building constraint systems.
 //! - `sender_token_well_formed_circuit_helper` generates the circuit for
 //!     1. k = com(pk||rho, r)
-//!     2. cm = com(v||k, s)
+//!     2. cm = com(asset_id||v||k, s)
+//!   and returns the committed asset id and value
 //! - `receiver_token_well_formed_circuit_helper` generates the circuit for
-//!     1. cm = com(v||k, s)
+//!     1. cm = com(asset_id||v||k, s)
+//!   and returns the committed asset id and value
+//! - `range_check_u64_circuit_helper` generates the circuit for
+//!     1. value < 2^64
 //! - `prf_circuit_helper` generates the circuit for
 //!     1. pk = PRF(sk, [0u8;32])
 //!     2. void_number = PRF(sk, rho)
 //! - `merkle_membership_circuit_proof` generates the circuit for
-//!     1. merkle_path(cm, path, root) == true
+//!     1. merkle_path(cm, witness, root) == true
 
+use crate::{ledger::MERKLE_PATH_LENGTH, MerkleWitness};
 use ark_crypto_primitives::{
 	commitment::pedersen::Randomness,
 	prf::{blake2s::constraints::Blake2sGadget, PRFGadget},
-	CommitmentGadget, PathVar,
+	CommitmentGadget, FixedLengthCRHGadget,
 };
-use ark_ed_on_bls12_381::{EdwardsProjective, Fq, Fr};
+use ark_ed_on_bls12_381::{constraints::FqVar, EdwardsProjective, Fq, Fr};
 use ark_r1cs_std::{alloc::AllocVar, prelude::*};
-use ark_relations::r1cs::ConstraintSystemRef;
+use ark_relations::r1cs::{ConstraintSystemRef, SynthesisError};
 use ark_serialize::CanonicalDeserialize;
 use ark_std::vec::Vec;
 use manta_asset::*;
 use manta_crypto::*;
 
+// =============================
+// the asset id and the value a coin commits to
+// =============================
+pub(crate) struct CoinVar {
+	pub(crate) asset_id: FqVar,
+	pub(crate) value: FqVar,
+}
+
 // =============================
 // circuit for the following statements
 // 1. k = com(pk||rho, r)
-// 2. cm = com(v||k, s)
-// for the sender, the cm is hidden and k is public
+// 2. cm = com(asset_id||v||k, s)
+// for the sender, the cm is hidden and k is public;
+// the committed asset id and value are returned
 // =============================
 pub(crate) fn sender_token_well_formed_circuit_helper(
 	parameters_var: &CommitmentParamVar,
 	asset: &MantaAsset,
 	cs: ConstraintSystemRef<Fq>,
-) {
+) -> Result<CoinVar, SynthesisError> {
 	// =============================
 	// statement 1: k = com(pk||rho, r)
 	// =============================
 	let input: Vec<u8> = [asset.pub_info.pk.as_ref(), asset.pub_info.rho.as_ref()].concat();
 	let mut input_var = Vec::new();
 	for byte in &input {
-		input_var.push(UInt8::new_witness(cs.clone(), || Ok(*byte)).unwrap());
+		input_var.push(UInt8::new_witness(cs.clone(), || Ok(*byte))?);
 	}
 
 	// opening
-	let r = Fr::deserialize(asset.pub_info.r.as_ref()).unwrap();
+	let r: Fr = deserialize_witness(asset.pub_info.r.as_ref())?;
 	let r = Randomness::<EdwardsProjective>(r);
 	let randomness_var = MantaCoinCommitmentOpenVar::new_witness(
 		ark_relations::ns!(cs, "gadget_randomness"),
 		|| Ok(&r),
-	)
-	.unwrap();
+	)?;
 
 	// commitment
-	let result_var =
-		CommitmentSchemeVar::commit(&parameters_var, &input_var, &randomness_var).unwrap();
+	let result_var = CommitmentSchemeVar::commit(&parameters_var, &input_var, &randomness_var)?;
 
 	// circuit to compare the committed value with supplied value
-	let k = CommitmentOutput::deserialize(asset.pub_info.k.as_ref()).unwrap();
+	let k: CommitmentOutput = deserialize_witness(asset.pub_info.k.as_ref())?;
 	let commitment_var2 = MantaCoinCommitmentOutputVar::new_input(
 		ark_relations::ns!(cs, "gadget_commitment"),
 		|| Ok(k),
-	)
-	.unwrap();
-	result_var.enforce_equal(&commitment_var2).unwrap();
+	)?;
+	result_var.enforce_equal(&commitment_var2)?;
 
 	// =============================
 	// statement 2: cm = com( asset_id || v || k, s)
@@ -92,82 +103,121 @@ pub(crate) fn sender_token_well_formed_circuit_helper(
 	.concat();
 	let mut input_var = Vec::new();
 	for byte in &input {
-		input_var.push(UInt8::new_witness(cs.clone(), || Ok(*byte)).unwrap());
+		input_var.push(UInt8::new_witness(cs.clone(), || Ok(*byte))?);
 	}
 
 	// opening
-	let s = Randomness::<EdwardsProjective>(Fr::deserialize(asset.pub_info.s.as_ref()).unwrap());
+	let s = Randomness::<EdwardsProjective>(deserialize_witness(asset.pub_info.s.as_ref())?);
 	let randomness_var = MantaCoinCommitmentOpenVar::new_witness(
 		ark_relations::ns!(cs, "gadget_randomness"),
 		|| Ok(&s),
-	)
-	.unwrap();
+	)?;
 
 	// commitment
 	let result_var: MantaCoinCommitmentOutputVar =
-		CommitmentSchemeVar::commit(&parameters_var, &input_var, &randomness_var).unwrap();
+		CommitmentSchemeVar::commit(&parameters_var, &input_var, &randomness_var)?;
 
 	// the other commitment
-	let cm = CommitmentOutput::deserialize(asset.commitment.as_ref()).unwrap();
+	let cm: CommitmentOutput = deserialize_witness(asset.commitment.as_ref())?;
 	// the commitment is from the sender, so it is hidden
 	let commitment_var2 = MantaCoinCommitmentOutputVar::new_witness(
 		ark_relations::ns!(cs, "gadget_commitment"),
 		|| Ok(cm),
-	)
-	.unwrap();
+	)?;
 
 	// circuit to compare the committed value with supplied value
-	result_var.enforce_equal(&commitment_var2).unwrap();
+	result_var.enforce_equal(&commitment_var2)?;
+
+	Ok(CoinVar {
+		asset_id: le_bytes_to_fq_var(&input_var[0..8])?,
+		value: le_bytes_to_fq_var(&input_var[8..16])?,
+	})
 }
 
 // =============================
 // circuit for the following statements
-// 1. cm = com(v||k, s)
-// for the receiver, the cm is public
+// 1. cm = com(asset_id||v||k, s)
+// for the receiver, the cm is public;
+// the committed asset id and value are returned
 // =============================
 pub(crate) fn receiver_token_well_formed_circuit_helper(
 	parameters_var: &CommitmentParamVar,
 	receiver: &MantaAssetProcessedReceiver,
 	cs: ConstraintSystemRef<Fq>,
-) {
+) -> Result<CoinVar, SynthesisError> {
 	// =============================
-	// statement 1: cm = com(v||k, s)
+	// statement 1: cm = com(asset_id||v||k, s)
+	// the same opening as the sender's one,
+	// so that the receiver can spend the coin
 	// =============================
 	let input: Vec<u8> = [
+		(receiver.prepared_data.asset_id as u64)
+			.to_le_bytes()
+			.as_ref(),
 		receiver.value.to_le_bytes().as_ref(),
 		receiver.prepared_data.k.as_ref(),
 	]
 	.concat();
 	let mut input_var = Vec::new();
 	for byte in &input {
-		input_var.push(UInt8::new_witness(cs.clone(), || Ok(*byte)).unwrap());
+		input_var.push(UInt8::new_witness(cs.clone(), || Ok(*byte))?);
 	}
 
 	// opening
-	let s = Randomness::<EdwardsProjective>(
-		Fr::deserialize(receiver.prepared_data.s.as_ref()).unwrap(),
-	);
+	let s =
+		Randomness::<EdwardsProjective>(deserialize_witness(receiver.prepared_data.s.as_ref())?);
 	let randomness_var = MantaCoinCommitmentOpenVar::new_witness(
 		ark_relations::ns!(cs, "gadget_randomness"),
 		|| Ok(&s),
-	)
-	.unwrap();
+	)?;
 
 	// commitment
 	let result_var: MantaCoinCommitmentOutputVar =
-		CommitmentSchemeVar::commit(&parameters_var, &input_var, &randomness_var).unwrap();
+		CommitmentSchemeVar::commit(&parameters_var, &input_var, &randomness_var)?;
 
 	// the other commitment
-	let cm = CommitmentOutput::deserialize(receiver.commitment.as_ref()).unwrap();
+	let cm: CommitmentOutput = deserialize_witness(receiver.commitment.as_ref())?;
 	// the commitment is from the receiver, it is public
 	let commitment_var2 = MantaCoinCommitmentOutputVar::new_input(
 		ark_relations::ns!(cs, "gadget_commitment"),
 		|| Ok(cm),
-	)
-	.unwrap();
+	)?;
 
 	// circuit to compare the committed value with supplied value
-	result_var.enforce_equal(&commitment_var2).unwrap();
+	result_var.enforce_equal(&commitment_var2)?;
+
+	Ok(CoinVar {
+		asset_id: le_bytes_to_fq_var(&input_var[0..8])?,
+		value: le_bytes_to_fq_var(&input_var[8..16])?,
+	})
+}
+
+// =============================
+// the field element of a little endian integer of at most 64 bits,
+// built from the very byte witnesses that encode it, e.g., the ones
+// that are committed to; it is below 2^64 by construction,
+// so it cannot wrap around the modulus
+// =============================
+fn le_bytes_to_fq_var(bytes: &[UInt8<Fq>]) -> Result<FqVar, SynthesisError> {
+	let mut bits = Vec::new();
+	for byte in bytes {
+		bits.extend(byte.to_bits_le()?);
+	}
+	Boolean::le_bits_to_fp_var(&bits)
+}
+
+// =============================
+// circuit for the following statement
+// 1. value < 2^64
+// the field element is decomposed into its canonical bits,
+// and all the bits above the 64th one must be zero
+// =============================
+pub(crate) fn range_check_u64_circuit_helper(value_var: &FqVar) -> Result<(), SynthesisError> {
+	let bits = value_var.to_bits_le()?;
+	for bit in bits[64..].iter() {
+		bit.enforce_equal(&Boolean::FALSE)?;
+	}
+	Ok(())
 }
 
 // =============================
@@ -182,62 +232,75 @@ pub(crate) fn prf_circuit_helper(
 	input: &[u8; 32],
 	output: &[u8; 32],
 	cs: ConstraintSystemRef<Fq>,
-) {
+) -> Result<(), SynthesisError> {
 	// step 1. Allocate seed
 	let seed_var = Blake2sGadget::new_seed(cs.clone(), &seed);
 
 	// step 2. Allocate inputs
-	let input_var = UInt8::new_witness_vec(ark_relations::ns!(cs, "declare_input"), input).unwrap();
+	let input_var = UInt8::new_witness_vec(ark_relations::ns!(cs, "declare_input"), input)?;
 
 	// step 3. Allocate evaluated output
-	let output_var = Blake2sGadget::evaluate(&seed_var, &input_var).unwrap();
+	let output_var = Blake2sGadget::evaluate(&seed_var, &input_var)?;
 
 	// step 4. Actual output
 	let actual_out_var = if is_output_hidden {
 		<Blake2sGadget as PRFGadget<_, Fq>>::OutputVar::new_witness(
 			ark_relations::ns!(cs, "declare_output"),
 			|| Ok(output),
-		)
-		.unwrap()
+		)?
 	} else {
 		<Blake2sGadget as PRFGadget<_, Fq>>::OutputVar::new_input(
 			ark_relations::ns!(cs, "declare_output"),
 			|| Ok(output),
-		)
-		.unwrap()
+		)?
 	};
 
 	// step 5. compare the outputs
-	output_var.enforce_equal(&actual_out_var).unwrap();
+	output_var.enforce_equal(&actual_out_var)
 }
 
 // =============================
 // circuit for the following statements
-// 1. merkle_path(cm, path, root) == true
+// 1. merkle_path(cm, witness, root) == true
 // =============================
 pub(crate) fn merkle_membership_circuit_proof(
 	cm: &[u8; 32],
-	path: &AccountMembership,
+	witness: &MerkleWitness,
 	param_var: HashParamVar,
 	root: HashOutput,
 	cs: ConstraintSystemRef<Fq>,
-) {
-	let root_var =
-		HashOutputVar::new_input(ark_relations::ns!(cs, "new_digest"), || Ok(root)).unwrap();
-
-	// Allocate Merkle Tree Path
-	let membership_var =
-		PathVar::<_, HashVar, _>::new_witness(ark_relations::ns!(cs, "new_witness"), || Ok(path))
-			.unwrap();
+) -> Result<(), SynthesisError> {
+	let root_var = HashOutputVar::new_input(ark_relations::ns!(cs, "new_digest"), || Ok(root))?;
 
 	// Allocate Leaf
-	let leaf_var = UInt8::new_witness_vec(ark_relations::ns!(cs, "commitment"), cm).unwrap();
-	let leaf_var: &[_] = leaf_var.as_slice();
+	let leaf_var = UInt8::new_witness_vec(ark_relations::ns!(cs, "commitment"), cm)?;
+	let mut node_var = HashVar::evaluate(&param_var, &leaf_var)?;
+
+	// hash the leaf up to the root; the path always has the same length,
+	// so that the circuit does not depend on the witness
+	for level in 0..MERKLE_PATH_LENGTH {
+		let sibling_var = HashOutputVar::new_witness(ark_relations::ns!(cs, "sibling"), || {
+			Ok(witness.sibling(level))
+		})?;
+		let is_right_var = Boolean::new_witness(ark_relations::ns!(cs, "is_right"), || {
+			Ok((witness.leaf_index >> level) & 1 == 1)
+		})?;
+
+		let left_var = is_right_var.select(&sibling_var, &node_var)?;
+		let right_var = is_right_var.select(&node_var, &sibling_var)?;
+		let mut bytes_var = left_var.to_bytes()?;
+		bytes_var.extend(right_var.to_bytes()?);
+		node_var = HashVar::evaluate(&param_var, &bytes_var)?;
+	}
 
 	// check membership
-	membership_var
-		.check_membership(&param_var, &root_var, &leaf_var)
-		.unwrap()
-		.enforce_equal(&Boolean::TRUE)
-		.unwrap();
+	node_var.enforce_equal(&root_var)
+}
+
+// =============================
+// deserialize a value of a witness; malformed bytes
+// leave the witness without an assignment
+// =============================
+fn deserialize_witness<T: CanonicalDeserialize>(bytes: &[u8]) -> Result<T, SynthesisError> {
+	T::deserialize(bytes).map_err(|_| SynthesisError::AssignmentMissing)
 }
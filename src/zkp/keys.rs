@@ -21,7 +21,7 @@ use ark_ed_on_bls12_381::Fq;
 use ark_groth16::generate_random_parameters;
 use ark_relations::r1cs::{ConstraintSynthesizer, ConstraintSystem};
 use ark_serialize::CanonicalSerialize;
-use ark_std::rand::{RngCore, SeedableRng};
+use ark_std::rand::{CryptoRng, RngCore, SeedableRng};
 use hkdf::Hkdf;
 use manta_asset::*;
 use manta_crypto::*;
@@ -39,12 +39,51 @@ pub const RECLAIM_PK: VerificationKey = VerificationKey {
 	data: &RECLAIM_VKBYTES,
 };
 
-/// Generate the ZKP keys with a default seed, and write to
-/// `transfer_pk.bin` and `reclaim_pk.bin`.
+/// Generate the ZKP keys with a default seed, and write the proving keys to
+/// `transfer_pk.bin` and `reclaim_pk.bin`, and their verification keys, i.e.,
+/// the bytes of `TRANSFER_PK` and `RECLAIM_PK`, to `transfer_vk.bin` and `reclaim_vk.bin`.
 #[cfg(feature = "std")]
 pub fn write_zkp_keys() {
-	let hash_param_seed = [1u8; 32];
-	let commit_param_seed = [2u8; 32];
+	let mut transfer_pk_bytes: Vec<u8> = Vec::new();
+	let pk = default_transfer_zkp_key();
+	let mut vk_buf: Vec<u8> = vec![];
+	pk.vk.serialize_uncompressed(&mut vk_buf).unwrap();
+	let mut file = File::create("transfer_vk.bin").unwrap();
+	file.write_all(vk_buf.as_mut()).unwrap();
+	pk.serialize_uncompressed(&mut transfer_pk_bytes).unwrap();
+	let mut file = File::create("transfer_pk.bin").unwrap();
+	file.write_all(transfer_pk_bytes.as_mut()).unwrap();
+	// println!("transfer circuit pk length: {}", transfer_pk_bytes.len());
+
+	let mut reclaim_pk_bytes: Vec<u8> = Vec::new();
+	let pk = default_reclaim_zkp_key();
+	let mut vk_buf: Vec<u8> = vec![];
+	pk.vk.serialize_uncompressed(&mut vk_buf).unwrap();
+	let mut file = File::create("reclaim_vk.bin").unwrap();
+	file.write_all(vk_buf.as_mut()).unwrap();
+	pk.serialize_uncompressed(&mut reclaim_pk_bytes).unwrap();
+	let mut file = File::create("reclaim_pk.bin").unwrap();
+	file.write_all(reclaim_pk_bytes.as_mut()).unwrap();
+	// println!("reclaim circuit pk length: {}", reclaim_pk_bytes.len());
+}
+
+/// Generate the proving key of the `TransferCircuit` with the default seed;
+/// its verification key is `TRANSFER_PK`.
+#[cfg(any(feature = "std", feature = "runtime-benchmarks"))]
+pub fn default_transfer_zkp_key() -> Groth16Pk {
+	manta_transfer_zkp_key_gen(&[1u8; 32], &[2u8; 32], &default_rng_seed())
+}
+
+/// Generate the proving key of the `ReclaimCircuit` with the default seed;
+/// its verification key is `RECLAIM_PK`.
+#[cfg(any(feature = "std", feature = "runtime-benchmarks"))]
+pub fn default_reclaim_zkp_key() -> Groth16Pk {
+	manta_reclaim_zkp_key_gen(&[1u8; 32], &[2u8; 32], &default_rng_seed())
+}
+
+// The seed of the rng that generates the default ZKP keys.
+#[cfg(any(feature = "std", feature = "runtime-benchmarks"))]
+fn default_rng_seed() -> [u8; 32] {
 	let seed = [3u8; 32];
 	let rng_salt: [u8; 32] = [
 		0x74, 0x68, 0x69, 0x73, 0x20, 0x69, 0x73, 0x20, 0x61, 0x20, 0x73, 0x65, 0x65, 0x64, 0x20,
@@ -54,27 +93,16 @@ pub fn write_zkp_keys() {
 	let mut rng_seed = [0u8; 32];
 	let digest = Hkdf::<Sha512Trunc256>::extract(Some(rng_salt.as_ref()), &seed);
 	rng_seed.copy_from_slice(&digest.0[0..32]);
-
-	let mut transfer_pk_bytes =
-		manta_transfer_zkp_key_gen(&hash_param_seed, &commit_param_seed, &rng_seed);
-	let mut file = File::create("transfer_pk.bin").unwrap();
-	file.write_all(transfer_pk_bytes.as_mut()).unwrap();
-	// println!("transfer circuit pk length: {}", transfer_pk_bytes.len());
-
-	let mut reclaim_pk_bytes =
-		manta_reclaim_zkp_key_gen(&hash_param_seed, &commit_param_seed, &rng_seed);
-	let mut file = File::create("reclaim_pk.bin").unwrap();
-	file.write_all(reclaim_pk_bytes.as_mut()).unwrap();
-	// println!("reclaim circuit pk length: {}", reclaim_pk_bytes.len());
+	rng_seed
 }
 
 // Generate ZKP keys for `private_transfer` circuit.
-#[cfg(feature = "std")]
+#[cfg(any(feature = "std", feature = "runtime-benchmarks"))]
 fn manta_transfer_zkp_key_gen(
 	hash_param_seed: &[u8; 32],
 	commit_param_seed: &[u8; 32],
 	rng_seed: &[u8; 32],
-) -> Vec<u8> {
+) -> Groth16Pk {
 	// rebuild the parameters from the inputs
 	let mut rng = ChaCha20Rng::from_seed(*commit_param_seed);
 	let commit_param = CommitmentScheme::setup(&mut rng).unwrap();
@@ -118,6 +146,11 @@ fn manta_transfer_zkp_key_gen(
 		commit_param,
 		hash_param,
 
+		// fee
+		asset_id: TEST_ASSET,
+		fee: 0,
+		binding: [0u8; 32],
+
 		// sender
 		sender_1,
 		sender_2,
@@ -134,29 +167,18 @@ fn manta_transfer_zkp_key_gen(
 		.unwrap();
 	assert!(sanity_cs.is_satisfied().unwrap());
 
-	// transfer pk_bytes
+	// transfer pk
 	let mut rng = ChaCha20Rng::from_seed(*rng_seed);
-	let pk = generate_random_parameters::<Bls12_381, _, _>(transfer_circuit, &mut rng).unwrap();
-	let mut transfer_pk_bytes: Vec<u8> = Vec::new();
-
-	let mut vk_buf: Vec<u8> = vec![];
-	let transfer_vk = &pk.vk;
-	transfer_vk.serialize_uncompressed(&mut vk_buf).unwrap();
-	#[cfg(features = "std")]
-	println!("pk_uncompressed len {}", transfer_pk_bytes.len());
-	println!("vk: {:?}", vk_buf);
-
-	pk.serialize_uncompressed(&mut transfer_pk_bytes).unwrap();
-	transfer_pk_bytes
+	generate_random_parameters::<Bls12_381, _, _>(transfer_circuit, &mut rng).unwrap()
 }
 
 // Generate ZKP keys for `reclaim` circuit.
-#[cfg(feature = "std")]
+#[cfg(any(feature = "std", feature = "runtime-benchmarks"))]
 fn manta_reclaim_zkp_key_gen(
 	hash_param_seed: &[u8; 32],
 	commit_param_seed: &[u8; 32],
 	rng_seed: &[u8; 32],
-) -> Vec<u8> {
+) -> Groth16Pk {
 	// rebuild the parameters from the inputs
 	let mut rng = ChaCha20Rng::from_seed(*commit_param_seed);
 	let commit_param = CommitmentScheme::setup(&mut rng).unwrap();
@@ -204,6 +226,8 @@ fn manta_reclaim_zkp_key_gen(
 		// reclaim value
 		asset_id: AssetId::default(),
 		reclaim_value: 130,
+		recipient: [0u8; 32],
+		binding: [0u8; 32],
 	};
 
 	let sanity_cs = ConstraintSystem::<Fq>::new_ref();
@@ -213,19 +237,75 @@ fn manta_reclaim_zkp_key_gen(
 		.unwrap();
 	assert!(sanity_cs.is_satisfied().unwrap());
 
-	// reclaim pk_bytes
+	// reclaim pk
 	let mut rng = ChaCha20Rng::from_seed(*rng_seed);
-	let pk = generate_random_parameters::<Bls12_381, _, _>(reclaim_circuit, &mut rng).unwrap();
-	let mut reclaim_pk_bytes: Vec<u8> = Vec::new();
+	generate_random_parameters::<Bls12_381, _, _>(reclaim_circuit, &mut rng).unwrap()
+}
 
-	let mut vk_buf: Vec<u8> = vec![];
-	let reclaim_vk = &pk.vk;
-	reclaim_vk.serialize_uncompressed(&mut vk_buf).unwrap();
-	println!("pk_uncompressed len {}", reclaim_pk_bytes.len());
-	println!("vk: {:?}", vk_buf);
+/// Generate the proving key of the `MultiTransferCircuit` with `senders` senders
+/// and `receivers` receivers. The verification key of the proving key is the
+/// one to register on chain for `CircuitKind::TransferN(senders, receivers)`.
+#[cfg(feature = "std")]
+pub fn generate_transfer_n_zkp_key<R: RngCore + CryptoRng>(
+	commit_param: &CommitmentParam,
+	hash_param: &HashParam,
+	senders: usize,
+	receivers: usize,
+	rng: &mut R,
+) -> Groth16Pk {
+	assert!(
+		senders > 0 && receivers > 0,
+		"a transfer needs senders and receivers"
+	);
 
-	pk.serialize_uncompressed(&mut reclaim_pk_bytes).unwrap();
-	reclaim_pk_bytes
+	let mut coins = Vec::new();
+	let mut ledger = Vec::new();
+	let mut sk = [0u8; 32];
+	for _ in 0..senders {
+		rng.fill_bytes(&mut sk);
+		let sender = MantaAsset::sample(commit_param, &sk, &TEST_ASSET, &100, rng);
+		ledger.push(sender.commitment);
+		coins.push(sender);
+	}
+	let senders_meta = coins
+		.into_iter()
+		.map(|coin| SenderMetaData::build(hash_param.clone(), coin, &ledger))
+		.collect();
+
+	// the receivers share the value of the senders,
+	// and the last receiver gets the remainder
+	let total = 100 * senders as u64;
+	let mut receivers_processed = Vec::new();
+	for i in 0..receivers {
+		let value = if i + 1 == receivers {
+			total - (total / receivers as u64) * i as u64
+		} else {
+			total / receivers as u64
+		};
+		rng.fill_bytes(&mut sk);
+		let receiver_full =
+			MantaAssetFullReceiver::sample(commit_param, &sk, &TEST_ASSET, &(), rng);
+		receivers_processed.push(receiver_full.prepared.process(&value, rng));
+	}
+
+	let circuit = MultiTransferCircuit {
+		commit_param: commit_param.clone(),
+		hash_param: hash_param.clone(),
+		asset_id: TEST_ASSET,
+		fee: 0,
+		binding: [0u8; 32],
+		senders: senders_meta,
+		receivers: receivers_processed,
+	};
+
+	let sanity_cs = ConstraintSystem::<Fq>::new_ref();
+	circuit
+		.clone()
+		.generate_constraints(sanity_cs.clone())
+		.unwrap();
+	assert!(sanity_cs.is_satisfied().unwrap());
+
+	generate_random_parameters::<Bls12_381, _, _>(circuit, rng).unwrap()
 }
 
 /// Pre-computed,
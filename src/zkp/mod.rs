@@ -21,20 +21,84 @@ mod gadget;
 mod keys;
 mod verifier;
 
-pub use circuit::{ReclaimCircuit, TransferCircuit};
+pub use circuit::{MultiTransferCircuit, ReclaimCircuit, TransferCircuit};
 pub(crate) use gadget::*;
+#[cfg(any(feature = "std", feature = "runtime-benchmarks"))]
+pub use keys::{default_reclaim_zkp_key, default_transfer_zkp_key};
 #[cfg(feature = "std")]
-pub use keys::write_zkp_keys;
+pub use keys::{generate_transfer_n_zkp_key, write_zkp_keys};
 pub use keys::{RECLAIM_PK, TRANSFER_PK};
+pub use verifier::{batch_verify, prepare_verification_key};
 
-use crate::payload::*;
-use ark_ff::ToConstraintField;
+use crate::{payload::*, MerkleWitness, WitnessError, WitnessTracker, MAX_TRANSFER_ARITY};
+use ark_ed_on_bls12_381::Fq;
+use ark_ff::{PrimeField, ToConstraintField};
 use ark_groth16::verify_proof;
 use ark_serialize::CanonicalDeserialize;
 use ark_std::vec::Vec;
+use frame_support::codec::{Decode, Encode};
 use manta_asset::*;
 use manta_crypto::*;
 
+/// The public input of a binding hash, see `binding_hash`.
+pub(crate) fn binding_to_field_element(binding: &[u8; 32]) -> Fq {
+	Fq::from_le_bytes_mod_order(binding)
+}
+
+/// The circuits whose verification keys are stored on chain.
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CircuitKind {
+	/// The circuit of `private_transfer`.
+	Transfer,
+	/// The circuit of `reclaim`.
+	Reclaim,
+	/// The circuit of `private_transfer_n`, i.e., a `MultiTransferCircuit`
+	/// with the given numbers of senders and receivers.
+	TransferN(u8, u8),
+}
+
+impl CircuitKind {
+	/// Check that the ledger can verify the proofs of this circuit.
+	pub fn is_supported(&self) -> bool {
+		match self {
+			CircuitKind::TransferN(senders, receivers) => {
+				(1..=MAX_TRANSFER_ARITY).contains(senders)
+					&& (1..=MAX_TRANSFER_ARITY).contains(receivers)
+			}
+			_ => true,
+		}
+	}
+}
+
+/// The reason a proof is rejected by `TryVerify::try_verify`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum VerifyError {
+	/// The verification key cannot be deserialized,
+	/// or it is not a key of the circuit.
+	BadVerificationKey,
+	/// The proof is not a valid encoding of a Groth16 proof.
+	BadProofEncoding,
+	/// A public input cannot be converted into field elements.
+	BadPublicInput,
+	/// The proof is well formed but the pairing check does not hold.
+	PairingCheckFailed,
+}
+
+/// Fallible verification of the proof in a payload.
+/// Unlike `MantaZKPVerifier::verify`, this does not panic on malformed data,
+/// and it tells why the proof is rejected.
+pub trait TryVerify {
+	/// Deserialize the proof, and the public inputs of the circuit.
+	fn proof_and_inputs(&self) -> Result<(Groth16Proof, Vec<Fq>), VerifyError>;
+
+	/// Verify the proof against the serialized prepared verification key.
+	fn try_verify(&self, key: &[u8]) -> Result<(), VerifyError> {
+		let pvk = verifier::deserialize_pvk(key)?;
+		let (proof, inputs) = self.proof_and_inputs()?;
+		verifier::check_proof(&pvk, &proof, &inputs)
+	}
+}
+
 /// A `SenderMetaData` is the data that a sender assembles from its `MantaAsset`
 /// and the current state of the ledger. This struct is an input to both
 /// `private transfer` and `reclaim` circuit.
@@ -42,23 +106,48 @@ use manta_crypto::*;
 pub struct SenderMetaData {
 	pub asset: MantaAsset,
 	pub root: LedgerMerkleTreeRoot,
-	pub membership: AccountMembership,
+	pub membership: MerkleWitness,
 }
 
 impl SenderMetaData {
 	/// Build the `SenderMetaData` from sender's `MantaAsset`
 	/// and the current state of the ledger.
+	/// Panics if the sender is not in the ledger, see `try_build`.
 	pub fn build(param: HashParam, sender: MantaAsset, leaves: &[[u8; 32]]) -> Self {
-		let tree = LedgerMerkleTree::new(param, &leaves).unwrap();
-		let root = tree.root();
+		Self::try_build(param, sender, leaves).unwrap()
+	}
 
-		let index = leaves.iter().position(|x| *x == sender.commitment).unwrap();
-		let membership = tree.generate_proof(index, &sender.commitment).unwrap();
+	/// Build the `SenderMetaData` from sender's `MantaAsset`
+	/// and the list of commitments of its shard.
+	/// This costs `O(n * depth)` hashes; a wallet that spends many coins
+	/// should track their witnesses with a `WitnessTracker` instead,
+	/// and use `from_witness`.
+	pub fn try_build(
+		param: HashParam,
+		sender: MantaAsset,
+		leaves: &[[u8; 32]],
+	) -> Result<Self, WitnessError> {
+		let tracker =
+			WitnessTracker::from_leaves(&param, leaves, |_, leaf| *leaf == sender.commitment);
+		let witness = tracker.witness(&sender.commitment)?.clone();
+		Self::from_witness(&param, sender, witness)
+	}
 
-		Self {
-			asset: sender,
-			root,
-			membership,
+	/// Build the `SenderMetaData` from sender's `MantaAsset`
+	/// and the witness of its commitment.
+	/// This costs `O(depth)` hashes.
+	pub fn from_witness(
+		param: &HashParam,
+		sender: MantaAsset,
+		witness: MerkleWitness,
+	) -> Result<Self, WitnessError> {
+		if witness.leaf != sender.commitment {
+			return Err(WitnessError::LeafMismatch);
 		}
+		Ok(Self {
+			root: witness.root(param),
+			asset: sender,
+			membership: witness,
+		})
 	}
 }
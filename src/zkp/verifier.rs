@@ -15,92 +15,248 @@
 // along with pallet-manta-pay.  If not, see <http://www.gnu.org/licenses/>.
 
 use super::*;
+use ark_bls12_381::Bls12_381;
+use ark_ec::{AffineCurve, PairingEngine, ProjectiveCurve};
 use ark_ed_on_bls12_381::Fq;
+use ark_ff::{Field, PrimeField, Zero};
+use ark_groth16::prepare_inputs;
+use ark_serialize::CanonicalSerialize;
 use manta_crypto::*;
+use sp_runtime::traits::{BlakeTwo256, Hash as HashT};
+
+/// Prepare the serialized verification key `vk_bytes` for the verifier,
+/// i.e., run the pairing precomputation once, and serialize the prepared key.
+/// Returns `None` if `vk_bytes` is not a valid verification key,
+/// including when its points are not in the prime order subgroups.
+pub fn prepare_verification_key(vk_bytes: &[u8]) -> Option<Vec<u8>> {
+	let vk = Groth16Vk::deserialize_uncompressed(vk_bytes).ok()?;
+	let pvk = Groth16Pvk::from(vk);
+	let mut pvk_bytes = Vec::new();
+	pvk.serialize_uncompressed(&mut pvk_bytes).ok()?;
+	Some(pvk_bytes)
+}
+
+/// Verify the proofs of `data` at once against the prepared verification key `key`.
+///
+/// The pairing equations of the proofs are combined with random coefficients
+/// into a single multi-pairing, which costs one final exponentiation instead of
+/// one per proof. The coefficients are derived from a hash of all the proofs and
+/// their public inputs, so that a submitter cannot pick them.
+/// If the combined check fails, the proofs are verified one by one, and the index
+/// of the first rejected proof is returned together with the reason.
+pub fn batch_verify<D: TryVerify>(key: &[u8], data: &[D]) -> Result<(), (usize, VerifyError)> {
+	let pvk = deserialize_pvk(key).map_err(|e| (0, e))?;
+	let mut proofs = Vec::with_capacity(data.len());
+	for (index, item) in data.iter().enumerate() {
+		proofs.push(item.proof_and_inputs().map_err(|e| (index, e))?);
+	}
+	if proofs.len() <= 1 {
+		return verify_each(&pvk, &proofs);
+	}
+
+	let mut pairs = Vec::with_capacity(proofs.len() + 2);
+	let mut acc_inputs = <Bls12_381 as PairingEngine>::G1Projective::zero();
+	let mut acc_c = <Bls12_381 as PairingEngine>::G1Projective::zero();
+	let mut acc_r = Fq::zero();
+	for (index, ((proof, inputs), r)) in proofs.iter().zip(batch_coefficients(&proofs)).enumerate()
+	{
+		let prepared_inputs =
+			prepare_inputs(&pvk, inputs).map_err(|_| (index, VerifyError::BadVerificationKey))?;
+		let r_repr = r.into_repr();
+		pairs.push((
+			AffineCurve::mul(&proof.a, r_repr).into_affine().into(),
+			proof.b.into(),
+		));
+		acc_inputs += &ProjectiveCurve::mul(prepared_inputs, r_repr);
+		acc_c += &AffineCurve::mul(&proof.c, r_repr);
+		acc_r += &r;
+	}
+	pairs.push((acc_inputs.into_affine().into(), pvk.gamma_g2_neg_pc.clone()));
+	pairs.push((acc_c.into_affine().into(), pvk.delta_g2_neg_pc.clone()));
+
+	// prod e(r_i * A_i, B_i) * e(sum r_i * IC_i, -gamma) * e(sum r_i * C_i, -delta)
+	// == e(alpha, beta)^(sum r_i)
+	if Bls12_381::product_of_pairings(pairs.iter()) == pvk.alpha_g1_beta_g2.pow(acc_r.into_repr()) {
+		return Ok(());
+	}
+
+	// find out which proof is invalid
+	verify_each(&pvk, &proofs)
+}
+
+fn verify_each(
+	pvk: &Groth16Pvk,
+	proofs: &[(Groth16Proof, Vec<Fq>)],
+) -> Result<(), (usize, VerifyError)> {
+	for (index, (proof, inputs)) in proofs.iter().enumerate() {
+		check_proof(pvk, proof, inputs).map_err(|e| (index, e))?;
+	}
+	Ok(())
+}
+
+// 128 bits coefficients, derived from a hash of the whole batch
+fn batch_coefficients(proofs: &[(Groth16Proof, Vec<Fq>)]) -> Vec<Fq> {
+	let mut transcript = Vec::new();
+	for (proof, inputs) in proofs.iter() {
+		// writing into a vector does not fail
+		let _ = proof.serialize(&mut transcript);
+		let _ = inputs.serialize(&mut transcript);
+	}
+	let seed = BlakeTwo256::hash(&transcript);
+
+	(0..proofs.len() as u32)
+		.map(|index| {
+			let digest = BlakeTwo256::hash(&[seed.as_ref(), &index.to_le_bytes()].concat());
+			Fq::from_le_bytes_mod_order(&digest.as_ref()[..16])
+		})
+		.collect()
+}
 
 impl MantaZKPVerifier for PrivateTransferData {
-	type VerificationKey = VerificationKey;
+	type VerificationKey = Vec<u8>;
 	/// This algorithm verifies the ZKP, given the verification key and the data.
-	fn verify(&self, transfer_key_bytes: &VerificationKey) -> bool {
-		let buf: &[u8] = transfer_key_bytes.data;
-		let vk = Groth16Vk::deserialize_unchecked(buf).unwrap();
-		let pvk = Groth16Pvk::from(vk);
-		let proof = Groth16Proof::deserialize(self.proof.as_ref()).unwrap();
-		let k_old_1 = CommitmentOutput::deserialize(self.sender_1.k.as_ref()).unwrap();
-		let k_old_2 = CommitmentOutput::deserialize(self.sender_2.k.as_ref()).unwrap();
-		let cm_new_1 = CommitmentOutput::deserialize(self.receiver_1.cm.as_ref()).unwrap();
-		let cm_new_2 = CommitmentOutput::deserialize(self.receiver_2.cm.as_ref()).unwrap();
-		let merkle_root_1 = HashOutput::deserialize(self.sender_1.root.as_ref()).unwrap();
-		let merkle_root_2 = HashOutput::deserialize(self.sender_2.root.as_ref()).unwrap();
-
-		let mut inputs = [
-			k_old_1.x, k_old_1.y, // sender coin 1
-			k_old_2.x, k_old_2.y, // sender coin 2
-			cm_new_1.x, cm_new_1.y, // receiver coin 1
-			cm_new_2.x, cm_new_2.y, // receiver coin 2
-		]
-		.to_vec();
-		let sn_1: Vec<Fq> =
-			ToConstraintField::<Fq>::to_field_elements(self.sender_1.void_number.as_ref()).unwrap();
-		let sn_2: Vec<Fq> =
-			ToConstraintField::<Fq>::to_field_elements(self.sender_2.void_number.as_ref()).unwrap();
-
-		let mr_1: Vec<Fq> = ToConstraintField::<Fq>::to_field_elements(&merkle_root_1).unwrap();
-		let mr_2: Vec<Fq> = ToConstraintField::<Fq>::to_field_elements(&merkle_root_2).unwrap();
-		inputs = [
-			inputs[..].as_ref(),
-			sn_1.as_ref(),
-			sn_2.as_ref(),
-			mr_1.as_ref(),
-			mr_2.as_ref(),
+	/// The key is a prepared verification key, see `prepare_verification_key`.
+	/// See `TryVerify::try_verify` for the reason of a failure.
+	fn verify(&self, transfer_key_bytes: &Self::VerificationKey) -> bool {
+		self.try_verify(transfer_key_bytes).is_ok()
+	}
+}
+
+impl TryVerify for PrivateTransferData {
+	fn proof_and_inputs(&self) -> Result<(Groth16Proof, Vec<Fq>), VerifyError> {
+		let proof = deserialize_proof(&self.proof)?;
+		let k_old_1 = deserialize_commitment(&self.sender_1.k)?;
+		let k_old_2 = deserialize_commitment(&self.sender_2.k)?;
+		let cm_new_1 = deserialize_commitment(&self.receiver_1.cm)?;
+		let cm_new_2 = deserialize_commitment(&self.receiver_2.cm)?;
+
+		let fee_fq = Fq::from(self.fee);
+		let asset_id_fq = Fq::from(self.asset_id as u64);
+		let inputs = [
+			[
+				k_old_1.x, k_old_1.y, // sender coin 1
+				k_old_2.x, k_old_2.y, // sender coin 2
+				cm_new_1.x, cm_new_1.y, // receiver coin 1
+				cm_new_2.x, cm_new_2.y, // receiver coin 2
+			]
+			.as_ref(),
+			bytes_to_field_elements(&self.sender_1.void_number)?.as_ref(),
+			bytes_to_field_elements(&self.sender_2.void_number)?.as_ref(),
+			root_to_field_elements(&self.sender_1.root)?.as_ref(),
+			root_to_field_elements(&self.sender_2.root)?.as_ref(),
+			[fee_fq].as_ref(),
+			[asset_id_fq].as_ref(),
+			[binding_to_field_element(&self.binding())].as_ref(),
 		]
 		.concat();
 
-		verify_proof(&pvk, &proof, &inputs[..]).unwrap()
+		Ok((proof, inputs))
+	}
+}
+
+impl TryVerify for PrivateTransferNData {
+	fn proof_and_inputs(&self) -> Result<(Groth16Proof, Vec<Fq>), VerifyError> {
+		let proof = deserialize_proof(&self.proof)?;
+
+		// the public inputs of `MultiTransferCircuit`
+		let mut inputs = Vec::new();
+		for sender in self.senders.iter() {
+			let k_old = deserialize_commitment(&sender.k)?;
+			inputs.extend_from_slice(&[k_old.x, k_old.y]);
+		}
+		for receiver in self.receivers.iter() {
+			let cm_new = deserialize_commitment(&receiver.cm)?;
+			inputs.extend_from_slice(&[cm_new.x, cm_new.y]);
+		}
+		for sender in self.senders.iter() {
+			inputs.extend(bytes_to_field_elements(&sender.void_number)?);
+		}
+		for sender in self.senders.iter() {
+			inputs.extend(root_to_field_elements(&sender.root)?);
+		}
+		inputs.push(Fq::from(self.fee));
+		inputs.push(Fq::from(self.asset_id as u64));
+		inputs.push(binding_to_field_element(&self.binding()));
+
+		Ok((proof, inputs))
 	}
 }
 
 impl MantaZKPVerifier for ReclaimData {
-	type VerificationKey = VerificationKey;
+	type VerificationKey = Vec<u8>;
 
 	/// This algorithm verifies the ZKP, given the verification key and the data.
-	fn verify(&self, reclaim_key_bytes: &VerificationKey) -> bool {
-		let buf: &[u8] = reclaim_key_bytes.data;
-		let vk = Groth16Vk::deserialize_unchecked(buf).unwrap();
-		let pvk = Groth16Pvk::from(vk);
-		let proof = Groth16Proof::deserialize(self.proof.as_ref()).unwrap();
-		let k_old_1 = CommitmentOutput::deserialize(self.sender_1.k.as_ref()).unwrap();
-		let k_old_2 = CommitmentOutput::deserialize(self.sender_2.k.as_ref()).unwrap();
-		let cm_new = CommitmentOutput::deserialize(self.receiver.cm.as_ref()).unwrap();
-		let merkle_root_1 = HashOutput::deserialize(self.sender_1.root.as_ref()).unwrap();
-		let merkle_root_2 = HashOutput::deserialize(self.sender_2.root.as_ref()).unwrap();
-
-		let mut inputs = [
-			k_old_1.x, k_old_1.y, // sender coin 1
-			k_old_2.x, k_old_2.y, // sender coin 2
-			cm_new.x, cm_new.y, // receiver coin
-		]
-		.to_vec();
-		let sn_1: Vec<Fq> =
-			ToConstraintField::<Fq>::to_field_elements(self.sender_1.void_number.as_ref()).unwrap();
-		let sn_2: Vec<Fq> =
-			ToConstraintField::<Fq>::to_field_elements(self.sender_2.void_number.as_ref()).unwrap();
-
-		let mr_1: Vec<Fq> = ToConstraintField::<Fq>::to_field_elements(&merkle_root_1).unwrap();
-		let mr_2: Vec<Fq> = ToConstraintField::<Fq>::to_field_elements(&merkle_root_2).unwrap();
+	/// The key is a prepared verification key, see `prepare_verification_key`.
+	/// See `TryVerify::try_verify` for the reason of a failure.
+	fn verify(&self, reclaim_key_bytes: &Self::VerificationKey) -> bool {
+		self.try_verify(reclaim_key_bytes).is_ok()
+	}
+}
+
+impl TryVerify for ReclaimData {
+	fn proof_and_inputs(&self) -> Result<(Groth16Proof, Vec<Fq>), VerifyError> {
+		let proof = deserialize_proof(&self.proof)?;
+		let k_old_1 = deserialize_commitment(&self.sender_1.k)?;
+		let k_old_2 = deserialize_commitment(&self.sender_2.k)?;
+		let cm_new = deserialize_commitment(&self.receiver.cm)?;
+
 		let value_fq = Fq::from(self.reclaim_amount);
 		let asset_id_fq = Fq::from(self.asset_id as u64);
-		inputs = [
-			inputs[..].as_ref(),
-			sn_1.as_ref(),
-			sn_2.as_ref(),
-			mr_1.as_ref(),
-			mr_2.as_ref(),
+		let inputs = [
+			[
+				k_old_1.x, k_old_1.y, // sender coin 1
+				k_old_2.x, k_old_2.y, // sender coin 2
+				cm_new.x, cm_new.y, // receiver coin
+			]
+			.as_ref(),
+			bytes_to_field_elements(&self.sender_1.void_number)?.as_ref(),
+			bytes_to_field_elements(&self.sender_2.void_number)?.as_ref(),
+			root_to_field_elements(&self.sender_1.root)?.as_ref(),
+			root_to_field_elements(&self.sender_2.root)?.as_ref(),
 			[value_fq].as_ref(),
 			[asset_id_fq].as_ref(),
+			bytes_to_field_elements(&self.recipient)?.as_ref(),
+			[binding_to_field_element(&self.binding())].as_ref(),
 		]
 		.concat();
 
-		verify_proof(&pvk, &proof, &inputs[..]).unwrap()
+		Ok((proof, inputs))
+	}
+}
+
+// the stored keys are checked when they are set,
+// so the prepared key is deserialized without the subgroup checks
+pub(super) fn deserialize_pvk(pvk_bytes: &[u8]) -> Result<Groth16Pvk, VerifyError> {
+	Groth16Pvk::deserialize_unchecked(pvk_bytes).map_err(|_| VerifyError::BadVerificationKey)
+}
+
+fn deserialize_proof(proof_bytes: &[u8]) -> Result<Groth16Proof, VerifyError> {
+	Groth16Proof::deserialize(proof_bytes).map_err(|_| VerifyError::BadProofEncoding)
+}
+
+fn deserialize_commitment(cm_bytes: &[u8]) -> Result<CommitmentOutput, VerifyError> {
+	CommitmentOutput::deserialize(cm_bytes).map_err(|_| VerifyError::BadPublicInput)
+}
+
+fn bytes_to_field_elements(bytes: &[u8]) -> Result<Vec<Fq>, VerifyError> {
+	ToConstraintField::<Fq>::to_field_elements(bytes).ok_or(VerifyError::BadPublicInput)
+}
+
+fn root_to_field_elements(root_bytes: &[u8]) -> Result<Vec<Fq>, VerifyError> {
+	let root = HashOutput::deserialize(root_bytes).map_err(|_| VerifyError::BadPublicInput)?;
+	ToConstraintField::<Fq>::to_field_elements(&root).ok_or(VerifyError::BadPublicInput)
+}
+
+// `verify_proof` only errors when the number of public inputs
+// does not match the key, i.e., the key is not a key of this circuit
+pub(super) fn check_proof(
+	pvk: &Groth16Pvk,
+	proof: &Groth16Proof,
+	inputs: &[Fq],
+) -> Result<(), VerifyError> {
+	match verify_proof(pvk, proof, inputs) {
+		Ok(true) => Ok(()),
+		Ok(false) => Err(VerifyError::PairingCheckFailed),
+		Err(_) => Err(VerifyError::BadVerificationKey),
 	}
 }